@@ -0,0 +1,323 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use ffmpeg_next as ffmpeg;
+use ffmpeg::format::{context::Input, sample, Sample};
+use ffmpeg::media::Type;
+use ffmpeg::software::resampling;
+use ffmpeg::util::frame::audio::Audio;
+use ffmpeg::{ChannelLayout, Packet};
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::source::SeekError;
+use rodio::Source;
+use crate::error::Error;
+
+const AV_TIME_BASE: f64 = 1_000_000.0;
+const AV_NOPTS_VALUE: i64 = i64::MIN;
+
+// Used when the output device can't be queried.
+const FALLBACK_SAMPLE_RATE: u32 = 44100;
+const FALLBACK_CHANNELS: u16 = 2;
+
+// The shared position is updated every this many samples.
+const POSITION_INTERVAL: usize = 512;
+
+/// Sample rate and channel count of the default output device, which is what
+/// rodio's mixer runs at. Decoding straight to this format means rodio never
+/// has to convert the audio itself.
+pub fn device_output_format() -> (u32, u16) {
+    rodio::cpal::default_host()
+        .default_output_device()
+        .and_then(|device| device.default_output_config().ok())
+        .map(|config| (config.sample_rate().0, config.channels()))
+        .unwrap_or((FALLBACK_SAMPLE_RATE, FALLBACK_CHANNELS))
+}
+
+/// Media time of the audio most recently handed to the sink, readable from
+/// the player thread to measure A/V drift. Output buffering in rodio and the
+/// device isn't included, so it runs slightly ahead of what is heard.
+#[derive(Clone, Debug, Default)]
+pub struct AudioPosition(Arc<AtomicU64>);
+
+impl AudioPosition {
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn set(&self, seconds: f64) {
+        self.0.store(seconds.to_bits(), Ordering::Relaxed);
+    }
+}
+
+fn audio_error(context: &str, e: ffmpeg::Error) -> Error {
+    Error::AudioUnavailable {
+        reason: context.to_string(),
+        source: Some(Box::new(e)),
+    }
+}
+
+/// A `rodio::Source` that decodes one audio stream of a video with
+/// ffmpeg-next as the sink asks for samples, resampling to the requested
+/// rate and channel count. Nothing is written to disk and playback starts as
+/// soon as the first packet is decoded.
+pub struct AudioStream {
+    input: Input,
+    decoder: ffmpeg::decoder::Audio,
+    // Built from the first decoded frame, and rebuilt if the stream's format
+    // changes midway.
+    resampler: Option<resampling::Context>,
+    stream_index: usize,
+    time_base: f64,
+    start_pts: i64,
+    duration: Option<Duration>,
+    sample_rate: u32,
+    channels: u16,
+    layout: ChannelLayout,
+    // Interleaved samples of the current frame, the read position in it and
+    // the media time of its first sample.
+    buffer: Vec<f32>,
+    position: usize,
+    buffer_time: f64,
+    shared_position: AudioPosition,
+    // After a seek, samples before this time are decoded and thrown away so
+    // playback resumes exactly at the requested position.
+    skip_until: Option<f64>,
+    eof_sent: bool,
+}
+
+impl AudioStream {
+    /// Opens an audio stream of `path`: the stream at container index
+    /// `stream_index`, or the best audio stream when `None`.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        stream_index: Option<usize>,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<Self, Error> {
+        ffmpeg::init().map_err(|e| audio_error("failed to initialize FFmpeg", e))?;
+
+        let input = ffmpeg::format::input(&path.as_ref())
+            .map_err(|e| audio_error("failed to open video", e))?;
+
+        let (stream_index, time_base, start_pts, parameters) = {
+            let stream = match stream_index {
+                Some(index) => input.stream(index)
+                    .filter(|stream| stream.parameters().medium() == Type::Audio),
+                None => input.streams().best(Type::Audio),
+            }
+            .ok_or_else(|| Error::AudioUnavailable { reason: String::from("no audio stream found"), source: None })?;
+
+            let start_pts = if stream.start_time() == AV_NOPTS_VALUE { 0 } else { stream.start_time() };
+
+            (stream.index(), f64::from(stream.time_base()), start_pts, stream.parameters())
+        };
+
+        let decoder = ffmpeg::codec::context::Context::from_parameters(parameters)
+            .and_then(|context| context.decoder().audio())
+            .map_err(|e| audio_error("failed to open audio decoder", e))?;
+
+        let duration = if input.duration() > 0 {
+            Some(Duration::from_secs_f64(input.duration() as f64 / AV_TIME_BASE))
+        } else {
+            None
+        };
+
+        Ok(AudioStream {
+            input,
+            decoder,
+            resampler: None,
+            stream_index,
+            time_base,
+            start_pts,
+            duration,
+            sample_rate,
+            channels,
+            layout: ChannelLayout::default(channels as i32),
+            buffer: Vec::new(),
+            position: 0,
+            buffer_time: 0.0,
+            shared_position: AudioPosition::default(),
+            skip_until: None,
+            eof_sent: false,
+        })
+    }
+
+    /// A handle to the playback position that stays valid while the stream
+    /// plays in the sink.
+    pub fn position(&self) -> AudioPosition {
+        self.shared_position.clone()
+    }
+
+    /// Decodes and resamples the next frame into `buffer`. Returns false at
+    /// the end of the stream.
+    fn refill(&mut self) -> Result<bool, Error> {
+        loop {
+            let frame = match self.decode_next()? {
+                Some(frame) => frame,
+                None => return Ok(false),
+            };
+
+            let frame_time = frame.timestamp()
+                .or_else(|| frame.pts())
+                .map(|pts| (pts - self.start_pts) as f64 * self.time_base);
+
+            // Frames without a timestamp follow on from the previous one.
+            let previous_end = self.buffer_time + self.buffer.len() as f64 / (self.sample_rate as f64 * self.channels as f64);
+            self.buffer = self.resample(&frame)?;
+            self.position = 0;
+            self.buffer_time = frame_time.unwrap_or(previous_end);
+
+            if let Some(target) = self.skip_until {
+                let frame_time = frame_time.unwrap_or(target);
+                self.buffer_time = frame_time;
+                let frame_end = frame_time + self.buffer.len() as f64 / (self.sample_rate as f64 * self.channels as f64);
+                if frame_end <= target {
+                    continue;
+                }
+                let skip = ((target - frame_time).max(0.0) * self.sample_rate as f64) as usize * self.channels as usize;
+                self.position = skip.min(self.buffer.len());
+                self.skip_until = None;
+            }
+
+            if self.position < self.buffer.len() {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn decode_next(&mut self) -> Result<Option<Audio>, Error> {
+        let mut frame = Audio::empty();
+
+        loop {
+            match self.decoder.receive_frame(&mut frame) {
+                Ok(()) => return Ok(Some(frame)),
+                Err(ffmpeg::Error::Eof) => return Ok(None),
+                Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => {
+                    if self.eof_sent {
+                        return Ok(None);
+                    }
+                },
+                Err(e) => return Err(audio_error("failed to decode audio", e)),
+            }
+
+            let mut packet = Packet::empty();
+            match packet.read(&mut self.input) {
+                Ok(()) => {
+                    if packet.stream() == self.stream_index {
+                        // A corrupt packet only costs a few milliseconds of
+                        // sound, so it is skipped rather than ending playback.
+                        let _ = self.decoder.send_packet(&packet);
+                    }
+                },
+                Err(ffmpeg::Error::Eof) => {
+                    self.decoder.send_eof()
+                        .map_err(|e| audio_error("failed to flush audio decoder", e))?;
+                    self.eof_sent = true;
+                },
+                Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => {},
+                Err(e) => return Err(audio_error("failed to read packet", e)),
+            }
+        }
+    }
+
+    fn resample(&mut self, frame: &Audio) -> Result<Vec<f32>, Error> {
+        // Some decoders leave the layout unset and only report a count.
+        let layout = if frame.channel_layout().is_empty() {
+            ChannelLayout::default(frame.channels() as i32)
+        } else {
+            frame.channel_layout()
+        };
+
+        let input = resampling::context::Definition {
+            format: frame.format(),
+            channel_layout: layout,
+            rate: frame.rate(),
+        };
+        let stale = self.resampler.as_ref().map(|resampler| *resampler.input() != input).unwrap_or(true);
+        if stale {
+            self.resampler = Some(resampling::Context::get(
+                input.format,
+                input.channel_layout,
+                input.rate,
+                Sample::F32(sample::Type::Packed),
+                self.layout,
+                self.sample_rate,
+            ).map_err(|e| audio_error("failed to create audio resampler", e))?);
+        }
+        let resampler = self.resampler.as_mut().expect("resampler was just created");
+
+        // Room for the converted frame plus whatever the resampler buffered
+        // from earlier frames.
+        let capacity = frame.samples() as u64 * self.sample_rate as u64 / input.rate.max(1) as u64 + 256;
+        let mut output = Audio::new(Sample::F32(sample::Type::Packed), capacity as usize, self.layout);
+        resampler.run(frame, &mut output)
+            .map_err(|e| audio_error("failed to resample audio", e))?;
+
+        let count = output.samples() * self.channels as usize;
+        Ok(output.data(0)[..count * 4]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect())
+    }
+}
+
+impl Iterator for AudioStream {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.buffer.len() {
+            // A decode error ends the audio; the video keeps playing.
+            if !self.refill().unwrap_or(false) {
+                return None;
+            }
+        }
+
+        if self.position.is_multiple_of(POSITION_INTERVAL) {
+            let frames = (self.position / self.channels as usize) as f64;
+            self.shared_position.set(self.buffer_time + frames / self.sample_rate as f64);
+        }
+
+        let sample = self.buffer[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl Source for AudioStream {
+    fn current_frame_len(&self) -> Option<usize> {
+        // Every frame is resampled to the same format, so the source never
+        // changes rate or channel count.
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let timestamp = pos.as_secs_f64();
+        let start_seconds = self.start_pts as f64 * self.time_base;
+        let target = ((start_seconds + timestamp) * AV_TIME_BASE) as i64;
+
+        self.input.seek(target, ..target)
+            .map_err(|e| SeekError::Other(Box::new(audio_error("failed to seek audio", e))))?;
+        self.decoder.flush();
+        // Samples buffered inside the resampler belong to the old position.
+        self.resampler = None;
+        self.buffer.clear();
+        self.position = 0;
+        self.buffer_time = timestamp;
+        self.skip_until = Some(timestamp);
+        self.eof_sent = false;
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, Luma, RgbImage};
+use crate::cells::{Cell, CellGrid, Rgb};
+
+// Squares of the checkerboard, in pixels of the decoded frame.
+const CHECKER_SIZE: u32 = 8;
+const CHECKER_LIGHT: Rgb = Rgb { r: 0x99, g: 0x99, b: 0x99 };
+const CHECKER_DARK: Rgb = Rgb { r: 0x66, g: 0x66, b: 0x66 };
+
+// Cells whose average opacity is at most this are treated as fully
+// transparent; edge pixels of cut-out images are rarely exactly 0.
+const MAX_TRANSPARENT_ALPHA: u8 = 8;
+
+/// What transparent parts of images and videos are drawn over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Background {
+    /// A solid color.
+    Color(Rgb),
+    /// Light and dark gray squares, the way image editors show transparency.
+    Checkerboard,
+    /// The terminal's own background. Cells the image leaves fully
+    /// transparent are cleared, so the image floats over the terminal's
+    /// theme; partly transparent pixels are blended onto black.
+    Terminal,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Color(Rgb::new(0, 0, 0))
+    }
+}
+
+impl Background {
+    /// `checkerboard`, `terminal` or a color `Rgb::parse` accepts.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "checkerboard" | "checker" => Some(Background::Checkerboard),
+            "terminal" | "none" => Some(Background::Terminal),
+            _ => Rgb::parse(value).map(Background::Color),
+        }
+    }
+
+    /// The name `parse` accepts for this background.
+    pub fn name(self) -> String {
+        match self {
+            Background::Color(color) => color.to_hex(),
+            Background::Checkerboard => String::from("checkerboard"),
+            Background::Terminal => String::from("terminal"),
+        }
+    }
+
+    /// The color showing through at pixel `(x, y)`.
+    fn color_at(self, x: u32, y: u32) -> Rgb {
+        match self {
+            Background::Color(color) => color,
+            Background::Checkerboard if (x / CHECKER_SIZE + y / CHECKER_SIZE).is_multiple_of(2) => CHECKER_LIGHT,
+            Background::Checkerboard => CHECKER_DARK,
+            Background::Terminal => Rgb::new(0, 0, 0),
+        }
+    }
+}
+
+/// Blends an image with transparency onto `background`. Images without an
+/// alpha channel are only converted to RGB.
+pub fn flatten(image: &DynamicImage, background: Background) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.to_rgb8();
+    }
+
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let under = background.color_at(x, y);
+        let blend = |c: u8, bg: u8| ((c as u32 * a as u32 + bg as u32 * (255 - a as u32) + 127) / 255) as u8;
+        image::Rgb([blend(r, under.r), blend(g, under.g), blend(b, under.b)])
+    })
+}
+
+/// The alpha channel of `image` when `background` needs it to clear cells
+/// later, i.e. the terminal's background and an image with transparency.
+pub fn alpha_for(image: &DynamicImage, background: Background) -> Option<GrayImage> {
+    if background != Background::Terminal || !image.color().has_alpha() {
+        return None;
+    }
+
+    let rgba = image.to_rgba8();
+    Some(GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| Luma([rgba.get_pixel(x, y)[3]])))
+}
+
+/// Clears the cells of `grid` that `alpha`, the opacity of the frame it was
+/// rendered from, leaves fully transparent: no glyph and the terminal's
+/// default background.
+pub fn clear_transparent(grid: &mut CellGrid, alpha: &GrayImage) {
+    let (width, height) = (grid.width(), grid.height());
+    let coverage = imageops::resize(alpha, width as u32, height as u32, FilterType::Triangle);
+
+    for y in 0..height {
+        for x in 0..width {
+            if coverage.get_pixel(x as u32, y as u32)[0] <= MAX_TRANSPARENT_ALPHA {
+                let cell = grid.row(y)[x];
+                grid.set(x, y, Cell { glyph: ' ', bg: None, ..cell });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use super::*;
+
+    /// A 16x16 image whose left half is opaque red and right half is
+    /// transparent.
+    fn half_transparent() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, _| {
+            if x < 8 { Rgba([255, 0, 0, 255]) } else { Rgba([255, 0, 0, 0]) }
+        }))
+    }
+
+    fn pixel(image: &RgbImage, x: u32, y: u32) -> Rgb {
+        let [r, g, b] = image.get_pixel(x, y).0;
+        Rgb::new(r, g, b)
+    }
+
+    #[test]
+    fn parses_and_names_backgrounds() {
+        assert_eq!(Background::parse("Checker"), Some(Background::Checkerboard));
+        assert_eq!(Background::parse("none"), Some(Background::Terminal));
+        assert_eq!(Background::parse("#202020"), Some(Background::Color(Rgb::new(0x20, 0x20, 0x20))));
+        assert_eq!(Background::parse("plaid"), None);
+
+        for background in [Background::default(), Background::Checkerboard, Background::Terminal] {
+            assert_eq!(Background::parse(&background.name()), Some(background));
+        }
+    }
+
+    #[test]
+    fn checkerboard_alternates_squares() {
+        let board = Background::Checkerboard;
+        assert_eq!(board.color_at(0, 0), CHECKER_LIGHT);
+        assert_eq!(board.color_at(CHECKER_SIZE - 1, 0), CHECKER_LIGHT);
+        assert_eq!(board.color_at(CHECKER_SIZE, 0), CHECKER_DARK);
+        assert_eq!(board.color_at(CHECKER_SIZE, CHECKER_SIZE), CHECKER_LIGHT);
+    }
+
+    #[test]
+    fn flattens_onto_the_background() {
+        let white = Background::Color(Rgb::new(255, 255, 255));
+        let flat = flatten(&half_transparent(), white);
+        assert_eq!(pixel(&flat, 0, 0), Rgb::new(255, 0, 0));
+        assert_eq!(pixel(&flat, 15, 0), Rgb::new(255, 255, 255));
+
+        let flat = flatten(&half_transparent(), Background::Checkerboard);
+        assert_eq!(pixel(&flat, 8, 0), CHECKER_DARK);
+        assert_eq!(pixel(&flat, 8, 8), CHECKER_LIGHT);
+    }
+
+    #[test]
+    fn blends_partial_alpha() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 128])));
+        let flat = flatten(&image, Background::default());
+        assert_eq!(pixel(&flat, 0, 0), Rgb::new(128, 128, 128));
+    }
+
+    #[test]
+    fn opaque_images_are_only_converted() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, image::Rgb([1, 2, 3])));
+        let flat = flatten(&image, Background::Checkerboard);
+        assert_eq!(pixel(&flat, 1, 1), Rgb::new(1, 2, 3));
+        assert!(alpha_for(&image, Background::Terminal).is_none());
+    }
+
+    #[test]
+    fn alpha_is_kept_only_for_the_terminal_background() {
+        assert!(alpha_for(&half_transparent(), Background::Checkerboard).is_none());
+        let alpha = alpha_for(&half_transparent(), Background::Terminal).unwrap();
+        assert_eq!(alpha.get_pixel(0, 0)[0], 255);
+        assert_eq!(alpha.get_pixel(15, 15)[0], 0);
+    }
+
+    #[test]
+    fn clears_transparent_cells() {
+        let alpha = alpha_for(&half_transparent(), Background::Terminal).unwrap();
+        let mut grid = CellGrid::new(4, 2);
+        let filled = Cell { glyph: '#', fg: Rgb::new(255, 0, 0), bg: Some(Rgb::new(0, 0, 0)) };
+        for y in 0..2 {
+            for x in 0..4 {
+                grid.set(x, y, filled);
+            }
+        }
+
+        clear_transparent(&mut grid, &alpha);
+        for y in 0..2 {
+            assert_eq!(grid.row(y)[0], filled);
+            assert_eq!(grid.row(y)[3].glyph, ' ');
+            assert_eq!(grid.row(y)[3].bg, None);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+
+    /// A `#rrggbb` hex color (the `#` is optional), or `black` or `white`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "black" => return Some(Rgb::new(0, 0, 0)),
+            "white" => return Some(Rgb::new(255, 255, 255)),
+            _ => {},
+        }
+
+        let hex = value.strip_prefix('#').unwrap_or(value);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Rgb::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    /// The color as `#rrggbb`, which `parse` accepts.
+    pub fn to_hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    /// Nearest entry in the 6x6x6 color cube of the xterm 256-color palette.
+    pub fn to_ansi256(self) -> u8 {
+        let level = |c: u8| ((c as u16 * 5 + 127) / 255) as u8;
+        16 + 36 * level(self.r) + 6 * level(self.g) + level(self.b)
+    }
+}
+
+/// How much color information is sent to the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    Mono,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "mono" | "none" => Some(ColorMode::Mono),
+            "256" | "ansi256" => Some(ColorMode::Ansi256),
+            "truecolor" | "24bit" => Some(ColorMode::TrueColor),
+            _ => None,
+        }
+    }
+
+    /// The name `parse` accepts for this mode.
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorMode::Mono => "mono",
+            ColorMode::Ansi256 => "256",
+            ColorMode::TrueColor => "truecolor",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub glyph: char,
+    pub fg: Rgb,
+    /// Background color, or `None` for the terminal's own.
+    pub bg: Option<Rgb>,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            glyph: ' ',
+            fg: Rgb::new(255, 255, 255),
+            bg: None,
+        }
+    }
+}
+
+/// A rendered frame: one glyph and its colors per character cell, stored
+/// row-major.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CellGrid {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl CellGrid {
+    pub fn new(width: usize, height: usize) -> Self {
+        CellGrid {
+            width,
+            height,
+            cells: vec![Cell::default(); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        self.cells[y * self.width + x] = cell;
+    }
+
+    pub fn row(&self, y: usize) -> &[Cell] {
+        &self.cells[y * self.width..(y + 1) * self.width]
+    }
+}
+
+/// A rendered frame together with its presentation time.
+#[derive(Clone, Debug)]
+pub struct AsciiFrame {
+    pub cells: CellGrid,
+    /// Seconds from the start of the video.
+    pub timestamp: f64,
+}
+
+impl fmt::Display for CellGrid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y in 0..self.height {
+            for cell in self.row(y) {
+                write!(f, "{}", cell.glyph)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use crate::background::Background;
+use crate::cells::ColorMode;
+use crate::error::Error;
+use crate::playlist::RepeatMode;
+use crate::renderer::RenderStyle;
+use crate::subtitles::SubtitlePosition;
+
+/// Where the config file lives: `$XDG_CONFIG_HOME/ascii-rendering/config.toml`,
+/// or `~/.config/ascii-rendering/config.toml` when that isn't set.
+pub fn config_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_dir.join("ascii-rendering").join("config.toml"))
+}
+
+/// Reads and parses the config file. A missing file reads as an empty
+/// config.
+pub fn load_config() -> Result<toml::Table, Error> {
+    let path = match config_path() {
+        Some(path) if path.exists() => path,
+        _ => return Ok(toml::Table::new()),
+    };
+
+    let content = fs::read_to_string(&path).map_err(|e| Error::file(&path, e))?;
+    content.parse::<toml::Table>()
+        .map_err(|e| invalid(&format!("Invalid config file {}: {}", path.display(), e)))
+}
+
+/// Playback settings that can come from the built-in defaults, the config
+/// file's `[defaults]` table, a `[preset.NAME]` table and the command line,
+/// each overriding the one before.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Grid size and brightness inversion; asked for on stdin when unset.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub invert: Option<bool>,
+    pub color: ColorMode,
+    pub style: RenderStyle,
+    /// What transparent parts of images and videos are drawn over.
+    pub background: Background,
+    pub max_fps: Option<f64>,
+    pub speed: f64,
+    pub pitch_correction: bool,
+    pub audio: bool,
+    pub subtitles: bool,
+    pub subtitle_position: SubtitlePosition,
+    pub repeat: RepeatMode,
+    pub shuffle: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            width: None,
+            height: None,
+            invert: None,
+            color: ColorMode::Mono,
+            style: RenderStyle::Ramp,
+            background: Background::default(),
+            max_fps: None,
+            speed: 1.0,
+            pitch_correction: true,
+            audio: false,
+            subtitles: true,
+            subtitle_position: SubtitlePosition::Below,
+            repeat: RepeatMode::All,
+            shuffle: false,
+        }
+    }
+}
+
+impl Settings {
+    /// The built-in defaults with the config's `[defaults]` table and then
+    /// `[preset.<preset>]` applied.
+    pub fn from_config(config: &toml::Table, preset: Option<&str>) -> Result<Self, Error> {
+        let mut settings = Settings::default();
+
+        if let Some(defaults) = config.get("defaults") {
+            let defaults = defaults.as_table().ok_or_else(|| invalid("[defaults] must be a table"))?;
+            settings.apply(defaults, "defaults")?;
+        }
+
+        if let Some(name) = preset {
+            let presets = config.get("preset").and_then(|presets| presets.as_table());
+            let table = presets
+                .and_then(|presets| presets.get(name))
+                .and_then(|preset| preset.as_table())
+                .ok_or_else(|| {
+                    let available: Vec<&str> = presets
+                        .map(|presets| presets.keys().map(String::as_str).collect())
+                        .unwrap_or_default();
+                    let available = if available.is_empty() { String::from("none") } else { available.join(", ") };
+                    Error::InvalidInput(format!("Unknown preset '{}' (available: {})", name, available))
+                })?;
+            settings.apply(table, &format!("preset.{}", name))?;
+        }
+
+        Ok(settings)
+    }
+
+    /// Overrides the settings given in `table`, named `section` in errors.
+    /// Unknown keys are reported and skipped, so a config written for a
+    /// newer version still loads.
+    fn apply(&mut self, table: &toml::Table, section: &str) -> Result<(), Error> {
+        for (key, value) in table {
+            let wrong_type = |expected: &str| invalid(&format!("[{}] {} must be {}", section, key, expected));
+
+            match key.as_str() {
+                "width" => self.width = Some(as_u32(value).ok_or_else(|| wrong_type("a positive integer"))?),
+                "height" => self.height = Some(as_u32(value).ok_or_else(|| wrong_type("a positive integer"))?),
+                "invert" => self.invert = Some(value.as_bool().ok_or_else(|| wrong_type("true or false"))?),
+                "color" => {
+                    self.color = value.as_str()
+                        .and_then(ColorMode::parse)
+                        .ok_or_else(|| wrong_type("\"mono\", \"256\" or \"truecolor\""))?;
+                },
+                "style" => {
+                    self.style = value.as_str()
+                        .and_then(RenderStyle::parse)
+                        .ok_or_else(|| wrong_type("\"ramp\", \"blocks\", \"braille\", \"edges\" or \"shapes\""))?;
+                },
+                "background" => {
+                    self.background = value.as_str()
+                        .and_then(Background::parse)
+                        .ok_or_else(|| wrong_type("a color such as \"#202020\", \"checkerboard\" or \"terminal\""))?;
+                },
+                "max_fps" => {
+                    let fps = as_f64(value).filter(|fps| *fps >= 0.0).ok_or_else(|| wrong_type("a number"))?;
+                    // 0 turns a cap from an earlier table back off.
+                    self.max_fps = Some(fps).filter(|fps| *fps > 0.0);
+                },
+                "speed" => self.speed = as_f64(value).filter(|rate| *rate > 0.0).ok_or_else(|| wrong_type("a positive number"))?,
+                "pitch_correction" => self.pitch_correction = value.as_bool().ok_or_else(|| wrong_type("true or false"))?,
+                "audio" => self.audio = value.as_bool().ok_or_else(|| wrong_type("true or false"))?,
+                "subtitles" => self.subtitles = value.as_bool().ok_or_else(|| wrong_type("true or false"))?,
+                "subtitle_position" => {
+                    self.subtitle_position = value.as_str()
+                        .and_then(SubtitlePosition::parse)
+                        .ok_or_else(|| wrong_type("\"below\" or \"overlay\""))?;
+                },
+                "repeat" => {
+                    self.repeat = value.as_str()
+                        .and_then(RepeatMode::parse)
+                        .ok_or_else(|| wrong_type("\"off\", \"all\" or \"one\""))?;
+                },
+                "shuffle" => self.shuffle = value.as_bool().ok_or_else(|| wrong_type("true or false"))?,
+                _ => eprintln!("Warning: Ignoring unknown setting '{}' in [{}]", key, section),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The settings as a `[defaults]` table, in the config file's format.
+    /// Settings that are asked for when unset are left out.
+    pub fn to_toml(&self) -> String {
+        let mut table = toml::Table::new();
+
+        if let Some(width) = self.width {
+            table.insert(String::from("width"), toml::Value::Integer(width as i64));
+        }
+        if let Some(height) = self.height {
+            table.insert(String::from("height"), toml::Value::Integer(height as i64));
+        }
+        if let Some(invert) = self.invert {
+            table.insert(String::from("invert"), toml::Value::Boolean(invert));
+        }
+        table.insert(String::from("color"), toml::Value::String(self.color.name().to_string()));
+        table.insert(String::from("style"), toml::Value::String(self.style.name().to_string()));
+        table.insert(String::from("background"), toml::Value::String(self.background.name()));
+        table.insert(String::from("max_fps"), toml::Value::Float(self.max_fps.unwrap_or(0.0)));
+        table.insert(String::from("speed"), toml::Value::Float(self.speed));
+        table.insert(String::from("pitch_correction"), toml::Value::Boolean(self.pitch_correction));
+        table.insert(String::from("audio"), toml::Value::Boolean(self.audio));
+        table.insert(String::from("subtitles"), toml::Value::Boolean(self.subtitles));
+        table.insert(String::from("subtitle_position"), toml::Value::String(self.subtitle_position.name().to_string()));
+        table.insert(String::from("repeat"), toml::Value::String(self.repeat.name().to_string()));
+        table.insert(String::from("shuffle"), toml::Value::Boolean(self.shuffle));
+
+        let mut document = toml::Table::new();
+        document.insert(String::from("defaults"), toml::Value::Table(table));
+        toml::to_string(&document).unwrap_or_default()
+    }
+}
+
+fn as_u32(value: &toml::Value) -> Option<u32> {
+    value.as_integer().filter(|n| *n > 0).and_then(|n| u32::try_from(n).ok())
+}
+
+/// Integers are accepted where a number is expected, so `max_fps = 12`
+/// works as well as `max_fps = 12.0`.
+fn as_f64(value: &toml::Value) -> Option<f64> {
+    value.as_float().or_else(|| value.as_integer().map(|n| n as f64))
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidConfig(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(config: &str) -> toml::Table {
+        config.parse().unwrap()
+    }
+
+    fn invalid_message(result: Result<Settings, Error>) -> String {
+        match result {
+            Err(Error::InvalidConfig(message)) | Err(Error::InvalidInput(message)) => message,
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    const CONFIG: &str = r#"
+        [defaults]
+        width = 120
+        color = "truecolor"
+        speed = 1.5
+        max_fps = 24
+
+        [preset.small]
+        width = 60
+        height = 20
+        style = "braille"
+        max_fps = 0
+
+        [preset.quiet]
+        audio = false
+    "#;
+
+    #[test]
+    fn empty_config_gives_defaults() {
+        let settings = Settings::from_config(&toml::Table::new(), None).unwrap();
+        assert_eq!(settings.width, None);
+        assert_eq!(settings.color, ColorMode::Mono);
+        assert_eq!(settings.speed, 1.0);
+        assert_eq!(settings.repeat, RepeatMode::All);
+    }
+
+    #[test]
+    fn defaults_table_overrides_built_ins() {
+        let settings = Settings::from_config(&table(CONFIG), None).unwrap();
+        assert_eq!(settings.width, Some(120));
+        assert_eq!(settings.height, None);
+        assert_eq!(settings.color, ColorMode::TrueColor);
+        assert_eq!(settings.speed, 1.5);
+        assert_eq!(settings.max_fps, Some(24.0));
+        assert_eq!(settings.style, RenderStyle::Ramp);
+    }
+
+    #[test]
+    fn preset_overrides_defaults_table() {
+        let settings = Settings::from_config(&table(CONFIG), Some("small")).unwrap();
+        assert_eq!(settings.width, Some(60));
+        assert_eq!(settings.height, Some(20));
+        assert_eq!(settings.style, RenderStyle::Braille);
+        assert_eq!(settings.color, ColorMode::TrueColor);
+        assert_eq!(settings.max_fps, None);
+    }
+
+    #[test]
+    fn unknown_preset_lists_available_ones() {
+        let message = invalid_message(Settings::from_config(&table(CONFIG), Some("huge")));
+        assert!(message.contains("'huge'"));
+        assert!(message.contains("small") && message.contains("quiet"));
+
+        let message = invalid_message(Settings::from_config(&toml::Table::new(), Some("huge")));
+        assert!(message.contains("available: none"));
+    }
+
+    #[test]
+    fn wrong_types_name_the_setting() {
+        for (config, setting) in [
+            ("[defaults]\nwidth = 0", "width"),
+            ("[defaults]\nwidth = \"wide\"", "width"),
+            ("[defaults]\ncolor = \"sepia\"", "color"),
+            ("[defaults]\nspeed = -1", "speed"),
+            ("[preset.bad]\nrepeat = \"twice\"", "preset.bad] repeat"),
+        ] {
+            let preset = config.contains("preset").then_some("bad");
+            let message = invalid_message(Settings::from_config(&table(config), preset));
+            assert!(message.contains(setting), "{:?} doesn't mention {}", message, setting);
+        }
+        assert!(invalid_message(Settings::from_config(&table("defaults = 3"), None)).contains("[defaults]"));
+    }
+
+    #[test]
+    fn unknown_settings_are_skipped() {
+        let settings = Settings::from_config(&table("[defaults]\nsparkle = true\nshuffle = true"), None).unwrap();
+        assert!(settings.shuffle);
+    }
+
+    #[test]
+    fn dumped_settings_load_back() {
+        let settings = Settings::from_config(&table(CONFIG), Some("small")).unwrap();
+        let loaded = Settings::from_config(&table(&settings.to_toml()), None).unwrap();
+
+        assert_eq!(loaded.width, settings.width);
+        assert_eq!(loaded.height, settings.height);
+        assert_eq!(loaded.invert, settings.invert);
+        assert_eq!(loaded.color, settings.color);
+        assert_eq!(loaded.style, settings.style);
+        assert_eq!(loaded.max_fps, settings.max_fps);
+        assert_eq!(loaded.speed, settings.speed);
+        assert_eq!(loaded.subtitle_position, settings.subtitle_position);
+        assert_eq!(loaded.repeat, settings.repeat);
+        assert_eq!(loaded.background, settings.background);
+    }
+}
@@ -0,0 +1,309 @@
+use std::fmt::Write;
+use crate::cells::{AsciiFrame, Cell};
+use crate::error::Error;
+use crate::frame_source::FrameSourceBuilder;
+use crate::probe::MediaInfo;
+use crate::scene_detection::{detect_scenes, SceneCut, DEFAULT_SCENE_THRESHOLD};
+use crate::timecode::format_timestamp;
+
+// Blank columns between neighbouring thumbnails.
+const GAP: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SheetFormat {
+    Text,
+    Ansi,
+    Html,
+}
+
+impl SheetFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "text" | "txt" => Some(SheetFormat::Text),
+            "ansi" => Some(SheetFormat::Ansi),
+            "html" => Some(SheetFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+/// Picks `count` timestamps of `source` to sample: evenly spaced over its
+/// duration, or the start plus the strongest scene cuts when
+/// `at_scene_changes` is set.
+pub fn sample_timestamps(
+    source: &FrameSourceBuilder,
+    info: &MediaInfo,
+    count: usize,
+    at_scene_changes: bool,
+) -> Result<Vec<f64>, Error> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+
+    if at_scene_changes {
+        let cuts = detect_scenes(source, DEFAULT_SCENE_THRESHOLD)?;
+        return Ok(scene_timestamps(cuts, count, info.duration));
+    }
+
+    let duration = info.duration.ok_or_else(|| {
+        Error::InvalidInput(format!("Duration of {} is unknown", info.path.display()))
+    })?;
+    Ok(even_timestamps(duration, count))
+}
+
+/// `count` times spread over `duration`, each in the middle of its share.
+fn even_timestamps(duration: f64, count: usize) -> Vec<f64> {
+    (0..count)
+        .map(|i| (i as f64 + 0.5) * duration / count as f64)
+        .collect()
+}
+
+/// The start plus the `count - 1` strongest `cuts`, in order. A video with
+/// fewer cuts is topped up with evenly spaced times, each as far from the
+/// ones already picked as possible; if its duration is unknown it gets
+/// fewer than `count`.
+fn scene_timestamps(mut cuts: Vec<SceneCut>, count: usize, duration: Option<f64>) -> Vec<f64> {
+    cuts.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    let mut timestamps = vec![0.0];
+    timestamps.extend(cuts.iter().take(count - 1).map(|cut| cut.timestamp));
+
+    if let Some(duration) = duration {
+        let mut candidates = even_timestamps(duration, count);
+        while timestamps.len() < count && !candidates.is_empty() {
+            let distance = |time: f64| {
+                timestamps.iter().map(|picked| (picked - time).abs()).fold(f64::INFINITY, f64::min)
+            };
+            let farthest = (0..candidates.len())
+                .max_by(|&a, &b| distance(candidates[a]).total_cmp(&distance(candidates[b])))
+                .unwrap();
+            timestamps.push(candidates.swap_remove(farthest));
+        }
+    }
+
+    timestamps.sort_by(f64::total_cmp);
+    timestamps
+}
+
+/// A grid of small ASCII thumbnails, each with its timestamp underneath.
+pub struct ContactSheet {
+    frames: Vec<AsciiFrame>,
+    columns: usize,
+}
+
+impl ContactSheet {
+    pub fn new(frames: Vec<AsciiFrame>, columns: usize) -> Self {
+        ContactSheet {
+            frames,
+            columns: columns.max(1),
+        }
+    }
+
+    /// Number of thumbnails of `thumb_width` that fit in `total_width`
+    /// columns, at least one.
+    pub fn columns_for_width(total_width: usize, thumb_width: usize) -> usize {
+        ((total_width + GAP) / (thumb_width + GAP)).max(1)
+    }
+
+    pub fn render(&self, format: SheetFormat) -> String {
+        let mut out = String::new();
+
+        if format == SheetFormat::Html {
+            out.push_str("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Contact sheet</title></head>\n");
+            out.push_str("<body style=\"background:#000;color:#ccc\">\n<pre style=\"font-family:monospace;line-height:1.0\">\n");
+        }
+
+        for (row_index, row) in self.frames.chunks(self.columns).enumerate() {
+            if row_index > 0 {
+                out.push('\n');
+            }
+
+            let height = row.iter().map(|frame| frame.cells.height()).max().unwrap_or(0);
+            for y in 0..height {
+                for (i, frame) in row.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(&" ".repeat(GAP));
+                    }
+                    if y < frame.cells.height() {
+                        push_cells(&mut out, frame.cells.row(y), format);
+                    } else {
+                        out.push_str(&" ".repeat(frame.cells.width()));
+                    }
+                }
+                out.push('\n');
+            }
+
+            for (i, frame) in row.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(&" ".repeat(GAP));
+                }
+                let label: String = format_timestamp(frame.timestamp)
+                    .chars()
+                    .take(frame.cells.width())
+                    .collect();
+                write!(out, "{:^width$}", label, width = frame.cells.width()).unwrap();
+            }
+            out.push('\n');
+        }
+
+        if format == SheetFormat::Html {
+            out.push_str("</pre>\n</body>\n</html>\n");
+        }
+
+        out
+    }
+}
+
+/// Appends one thumbnail row, grouping cells of the same colors so each color
+/// change is only emitted once.
+fn push_cells(out: &mut String, cells: &[Cell], format: SheetFormat) {
+    if format == SheetFormat::Text {
+        out.extend(cells.iter().map(|cell| cell.glyph));
+        return;
+    }
+
+    let mut start = 0;
+    while start < cells.len() {
+        let (fg, bg) = (cells[start].fg, cells[start].bg);
+        let end = cells[start..]
+            .iter()
+            .position(|cell| cell.fg != fg || cell.bg != bg)
+            .map_or(cells.len(), |offset| start + offset);
+
+        match format {
+            SheetFormat::Ansi => {
+                write!(out, "\x1b[38;2;{};{};{}m", fg.r, fg.g, fg.b).unwrap();
+                match bg {
+                    Some(bg) => write!(out, "\x1b[48;2;{};{};{}m", bg.r, bg.g, bg.b).unwrap(),
+                    None => out.push_str("\x1b[49m"),
+                }
+                out.extend(cells[start..end].iter().map(|cell| cell.glyph));
+            },
+            SheetFormat::Html => {
+                write!(out, "<span style=\"color:#{:02x}{:02x}{:02x}", fg.r, fg.g, fg.b).unwrap();
+                if let Some(bg) = bg {
+                    write!(out, ";background:#{:02x}{:02x}{:02x}", bg.r, bg.g, bg.b).unwrap();
+                }
+                out.push_str("\">");
+                for cell in &cells[start..end] {
+                    match cell.glyph {
+                        '<' => out.push_str("&lt;"),
+                        '>' => out.push_str("&gt;"),
+                        '&' => out.push_str("&amp;"),
+                        glyph => out.push(glyph),
+                    }
+                }
+                out.push_str("</span>");
+            },
+            SheetFormat::Text => {},
+        }
+
+        start = end;
+    }
+
+    if format == SheetFormat::Ansi {
+        out.push_str("\x1b[0m");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cells::{CellGrid, Rgb};
+    use super::*;
+
+    fn frame(glyph: char, width: usize, height: usize, timestamp: f64) -> AsciiFrame {
+        let mut cells = CellGrid::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                cells.set(x, y, Cell { glyph, ..Cell::default() });
+            }
+        }
+        AsciiFrame { cells, timestamp }
+    }
+
+    fn cut(timestamp: f64, score: f64) -> SceneCut {
+        SceneCut { timestamp, score }
+    }
+
+    #[test]
+    fn even_times_sit_in_the_middle_of_each_share() {
+        assert_eq!(even_timestamps(10.0, 4), [1.25, 3.75, 6.25, 8.75]);
+        assert_eq!(even_timestamps(10.0, 1), [5.0]);
+    }
+
+    #[test]
+    fn scene_times_are_the_start_and_strongest_cuts_in_order() {
+        let cuts = vec![cut(10.0, 0.9), cut(50.0, 0.3), cut(30.0, 0.6)];
+        assert_eq!(scene_timestamps(cuts, 3, Some(60.0)), [0.0, 10.0, 30.0]);
+    }
+
+    #[test]
+    fn too_few_cuts_are_topped_up_with_even_times() {
+        // Even times are 12.5, 37.5, 62.5 and 87.5; the two farthest from
+        // 0 and 20 fill in.
+        let cuts = vec![cut(20.0, 0.5)];
+        assert_eq!(scene_timestamps(cuts.clone(), 4, Some(100.0)), [0.0, 20.0, 62.5, 87.5]);
+        // Without a duration there is nothing to spread them over.
+        assert_eq!(scene_timestamps(cuts, 4, None), [0.0, 20.0]);
+        assert_eq!(scene_timestamps(Vec::new(), 2, None), [0.0]);
+    }
+
+    #[test]
+    fn columns_fit_the_width() {
+        assert_eq!(ContactSheet::columns_for_width(40, 10), 3);
+        assert_eq!(ContactSheet::columns_for_width(46, 10), 4);
+        assert_eq!(ContactSheet::columns_for_width(5, 10), 1);
+    }
+
+    #[test]
+    fn text_sheet_lays_out_rows_with_labels() {
+        let frames = vec![frame('a', 7, 2, 1.5), frame('b', 7, 2, 2.0), frame('c', 7, 2, 3.0)];
+        let sheet = ContactSheet::new(frames, 2).render(SheetFormat::Text);
+        assert_eq!(
+            sheet,
+            "aaaaaaa  bbbbbbb\naaaaaaa  bbbbbbb\n0:01.50  0:02.00\n\nccccccc\nccccccc\n0:03.00\n",
+        );
+    }
+
+    #[test]
+    fn short_and_narrow_thumbnails_are_padded_and_labels_cut() {
+        let frames = vec![frame('a', 3, 2, 0.0), frame('b', 9, 1, 0.0)];
+        let sheet = ContactSheet::new(frames, 2).render(SheetFormat::Text);
+        assert_eq!(sheet, "aaa  bbbbbbbbb\naaa           \n0:0   0:00.00 \n");
+    }
+
+    #[test]
+    fn ansi_sheet_sets_colors_once_per_run() {
+        let mut frame = frame('x', 3, 1, 0.0);
+        frame.cells.set(0, 0, Cell { glyph: 'x', fg: Rgb::new(255, 0, 0), bg: None });
+        frame.cells.set(1, 0, Cell { glyph: 'x', fg: Rgb::new(255, 0, 0), bg: None });
+        frame.cells.set(2, 0, Cell { glyph: '#', fg: Rgb::new(0, 255, 0), bg: Some(Rgb::new(0, 0, 255)) });
+
+        let sheet = ContactSheet::new(vec![frame], 1).render(SheetFormat::Ansi);
+        assert_eq!(
+            sheet,
+            "\x1b[38;2;255;0;0m\x1b[49mxx\x1b[38;2;0;255;0m\x1b[48;2;0;0;255m#\x1b[0m\n0:0\n",
+        );
+    }
+
+    #[test]
+    fn html_sheet_escapes_glyphs_inside_a_page() {
+        let mut frame = frame('x', 3, 1, 0.0);
+        for (x, glyph) in "<&>".chars().enumerate() {
+            frame.cells.set(x, 0, Cell { glyph, fg: Rgb::new(255, 0, 0), bg: Some(Rgb::new(0, 0, 255)) });
+        }
+
+        let sheet = ContactSheet::new(vec![frame], 1).render(SheetFormat::Html);
+        assert!(sheet.starts_with("<!DOCTYPE html>\n"));
+        assert!(sheet.ends_with("</pre>\n</body>\n</html>\n"));
+        assert!(sheet.contains("<span style=\"color:#ff0000;background:#0000ff\">&lt;&amp;&gt;</span>\n0:0\n"));
+    }
+
+    #[test]
+    fn parses_format_names() {
+        assert_eq!(SheetFormat::parse("TXT"), Some(SheetFormat::Text));
+        assert_eq!(SheetFormat::parse("ansi"), Some(SheetFormat::Ansi));
+        assert_eq!(SheetFormat::parse("Html"), Some(SheetFormat::Html));
+        assert_eq!(SheetFormat::parse("pdf"), None);
+    }
+}
@@ -0,0 +1,138 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Boxed underlying error, kept for `source()`.
+pub type BoxError = Box<dyn error::Error + Send + Sync>;
+
+/// Everything that can go wrong in this crate. The message says what failed,
+/// `source()` leads to the error from FFmpeg, the image decoder or the OS
+/// underneath, and `hint()` suggests what to do about it.
+#[derive(Debug)]
+pub enum Error {
+    /// A file to play doesn't exist.
+    NotFound(PathBuf),
+    /// `program` (ffprobe) couldn't be started, usually because FFmpeg isn't
+    /// installed.
+    DecoderMissing { program: &'static str, source: io::Error },
+    /// ffprobe ran but couldn't make sense of the file.
+    ProbeFailed { path: PathBuf, reason: String },
+    /// The file has no stream of the `kind` needed ("video", "audio").
+    MissingStream { path: PathBuf, kind: &'static str },
+    /// The FFmpeg build has no decoder for the stream's codec.
+    UnsupportedCodec { path: PathBuf, codec: String },
+    /// Opening, decoding or seeking a source failed.
+    Decode { context: String, source: BoxError },
+    /// A builder was used without a setting it needs; `method` sets it.
+    NotConfigured { setting: &'static str, method: &'static str },
+    /// An argument out of range, such as a track number that doesn't exist.
+    InvalidInput(String),
+    /// The config file can't be parsed or has a setting of the wrong type.
+    InvalidConfig(String),
+    /// Audio can't be played: no output device, or the track won't open.
+    AudioUnavailable { reason: String, source: Option<BoxError> },
+    /// The server couldn't listen on `address`, e.g. because the port is
+    /// taken.
+    Listen { address: String, source: io::Error },
+    /// The terminal couldn't be switched to the player UI, drawn to or
+    /// restored.
+    Terminal(io::Error),
+    /// Reading or writing files and other streams.
+    Io(io::Error),
+}
+
+impl Error {
+    /// A `Decode` error for `path`, e.g. "Failed to seek (clip.mp4)".
+    pub(crate) fn decode<E>(context: &str, path: &Path, source: E) -> Self
+    where
+        E: Into<BoxError>,
+    {
+        Error::Decode {
+            context: format!("{} ({})", context, path.display()),
+            source: source.into(),
+        }
+    }
+
+    /// An I/O error from opening or reading `path`, where a missing file
+    /// becomes `NotFound`.
+    pub(crate) fn file(path: &Path, e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => Error::NotFound(path.to_path_buf()),
+            _ => Error::Io(e),
+        }
+    }
+
+    /// What the user can do about the error, when there's something better
+    /// to suggest than reading the message.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Error::NotFound(_) => Some(
+                "Check the path. Directories and quoted glob patterns such as 'clips/*.mp4' work too."
+            ),
+            Error::DecoderMissing { .. } => Some(
+                "Install FFmpeg, which includes ffprobe, and make sure it is on your PATH (see Prerequisites in the README)."
+            ),
+            Error::ProbeFailed { .. } => Some(
+                "Make sure the file is a complete video that other players can open."
+            ),
+            Error::UnsupportedCodec { .. } => Some(
+                "Your FFmpeg build can't decode this codec. Install a full build, or convert the file, e.g. `ffmpeg -i input -c:v libx264 output.mp4`."
+            ),
+            Error::AudioUnavailable { .. } => Some(
+                "Play without --audio, or check that an audio output device is available (see Audio in WSL in the README)."
+            ),
+            Error::Listen { .. } => Some(
+                "Pick another port with --serve, or stop the program that is using this one."
+            ),
+            Error::Terminal(_) => Some(
+                "The player needs an interactive terminal. Use --raw to write frames to a pipe or a file instead."
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound(path) => write!(f, "{} not found", path.display()),
+            Error::DecoderMissing { program, .. } => write!(f, "Could not run {}", program),
+            Error::ProbeFailed { path, reason } => write!(f, "Could not read {}: {}", path.display(), reason),
+            Error::MissingStream { path, kind } => write!(f, "{} has no {} stream", path.display(), kind),
+            Error::UnsupportedCodec { path, codec } => {
+                write!(f, "No decoder for the {} codec of {}", codec, path.display())
+            },
+            Error::Decode { context, .. } => write!(f, "{}", context),
+            Error::NotConfigured { setting, method } => {
+                write!(f, "No {} configured. Call {}() before build().", setting, method)
+            },
+            Error::InvalidInput(message) | Error::InvalidConfig(message) => write!(f, "{}", message),
+            Error::AudioUnavailable { reason, .. } => write!(f, "Audio unavailable: {}", reason),
+            Error::Listen { address, .. } => write!(f, "Could not listen on {}", address),
+            Error::Terminal(_) => write!(f, "Terminal error"),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::DecoderMissing { source, .. } | Error::Listen { source, .. } | Error::Terminal(source) => {
+                Some(source)
+            },
+            Error::Decode { source, .. } => Some(source.as_ref()),
+            Error::AudioUnavailable { source: Some(source), .. } => Some(source.as_ref()),
+            // The I/O error is the message itself.
+            Error::Io(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
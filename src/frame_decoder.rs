@@ -0,0 +1,341 @@
+use std::path::{Path, PathBuf};
+use ffmpeg_next as ffmpeg;
+use ffmpeg::codec::Id;
+use ffmpeg::format::{context::Input, Pixel};
+use ffmpeg::media::Type;
+use ffmpeg::software::scaling::{self, Flags};
+use ffmpeg::util::frame::video::Video;
+use ffmpeg::Packet;
+use image::{DynamicImage, RgbImage, RgbaImage};
+use crate::background::Background;
+use crate::error::Error;
+use crate::frame_source::{scaled_size, DecodedFrame, FrameSource, SourceMetadata};
+use crate::image_source::to_frame;
+
+const AV_TIME_BASE: f64 = 1_000_000.0;
+const AV_NOPTS_VALUE: i64 = i64::MIN;
+
+// When the requested timestamp is less than this many seconds ahead of the
+// current decode position, decoding forward is cheaper than seeking back to
+// a keyframe.
+const FORWARD_DECODE_LIMIT: f64 = 2.0;
+
+/// In-process video decoder built on ffmpeg-next. Frames come out in
+/// presentation order, and `seek` lands on the exact frame that is on screen
+/// at a given time rather than on the nearest keyframe. Videos with an alpha
+/// channel, such as ProRes 4444 or WebM with transparency, are drawn over a
+/// background like images are.
+pub struct FrameDecoder {
+    path: PathBuf,
+    input: Input,
+    decoder: ffmpeg::decoder::Video,
+    scaler: scaling::Context,
+    stream_index: usize,
+    time_base: f64,
+    start_pts: i64,
+    frame_rate: f64,
+    metadata: SourceMetadata,
+    background: Background,
+    // The most recently returned frame and a frame that was decoded past a
+    // seek target; both are needed to resume decoding without seeking again.
+    last: Option<(Video, f64)>,
+    pending: Option<Video>,
+    eof_sent: bool,
+}
+
+impl FrameDecoder {
+    /// Opens the best video stream of `path`. Frames are scaled down to at
+    /// most `max_width` pixels wide, keeping the aspect ratio, and
+    /// transparent parts are drawn as `background` says.
+    pub fn open<P: AsRef<Path>>(path: P, max_width: u32, background: Background) -> Result<Self, Error> {
+        let path = path.as_ref();
+        ffmpeg::init().map_err(|e| Error::decode("Failed to initialize FFmpeg", path, e))?;
+
+        let input = ffmpeg::format::input(&path)
+            .map_err(|e| Error::decode("Failed to open video", path, e))?;
+
+        let (stream_index, time_base, start_pts, frame_rate, frame_count, has_alpha_track, parameters) = {
+            let stream = input.streams()
+                .best(Type::Video)
+                .ok_or_else(|| Error::MissingStream { path: path.to_path_buf(), kind: "video" })?;
+
+            let rate = stream.avg_frame_rate();
+            let rate = if rate.denominator() == 0 || rate.numerator() == 0 {
+                stream.rate()
+            } else {
+                rate
+            };
+            let frame_rate = if rate.denominator() == 0 { 0.0 } else { f64::from(rate) };
+
+            let start_pts = if stream.start_time() == AV_NOPTS_VALUE { 0 } else { stream.start_time() };
+
+            let frame_count = u64::try_from(stream.frames()).ok().filter(|frames| *frames > 0);
+
+            // WebM keeps the alpha of VP8 and VP9 video in a separate track
+            // the stream's metadata announces.
+            let has_alpha_track = stream.metadata().get("alpha_mode") == Some("1");
+
+            (
+                stream.index(),
+                f64::from(stream.time_base()),
+                start_pts,
+                frame_rate,
+                frame_count,
+                has_alpha_track,
+                stream.parameters(),
+            )
+        };
+
+        let duration = if input.duration() > 0 {
+            Some(input.duration() as f64 / AV_TIME_BASE)
+        } else {
+            None
+        };
+
+        let codec = parameters.id();
+        // FFmpeg's own VP8 and VP9 decoders ignore that track; the libvpx
+        // ones read it, when FFmpeg was built with them.
+        let alpha_decoder = match codec {
+            Id::VP8 if has_alpha_track => ffmpeg::decoder::find_by_name("libvpx"),
+            Id::VP9 if has_alpha_track => ffmpeg::decoder::find_by_name("libvpx-vp9"),
+            _ => None,
+        };
+        let decoder = ffmpeg::codec::context::Context::from_parameters(parameters)
+            .and_then(|context| match alpha_decoder {
+                Some(alpha_decoder) => context.decoder().open_as(alpha_decoder).and_then(|opened| opened.video()),
+                None => context.decoder().video(),
+            })
+            .map_err(|e| match e {
+                ffmpeg::Error::DecoderNotFound => Error::UnsupportedCodec {
+                    path: path.to_path_buf(),
+                    codec: codec.name().to_string(),
+                },
+                e => Error::decode("Failed to open video decoder", path, e),
+            })?;
+
+        let (width, height) = scaled_size(decoder.width(), decoder.height(), max_width);
+
+        let scaler = scaler_for(decoder.format(), decoder.width(), decoder.height(), width, height)
+            .map_err(|e| Error::decode("Failed to create frame scaler", path, e))?;
+
+        Ok(FrameDecoder {
+            path: path.to_path_buf(),
+            input,
+            decoder,
+            scaler,
+            stream_index,
+            time_base,
+            start_pts,
+            frame_rate,
+            metadata: SourceMetadata {
+                width,
+                height,
+                frame_rate,
+                frame_count,
+                duration,
+            },
+            background,
+            last: None,
+            pending: None,
+            eof_sent: false,
+        })
+    }
+}
+
+impl FrameSource for FrameDecoder {
+    fn metadata(&self) -> SourceMetadata {
+        self.metadata
+    }
+
+    /// Decodes the next frame in presentation order. Returns `None` at the
+    /// end of the stream.
+    fn next_frame(&mut self) -> Result<Option<DecodedFrame>, Error> {
+        let frame = match self.pending.take() {
+            Some(frame) => frame,
+            None => match self.decode_next()? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            },
+        };
+
+        let timestamp = self.timestamp_of(&frame);
+        let decoded = self.convert(&frame, timestamp)?;
+        self.last = Some((frame, timestamp));
+        Ok(Some(decoded))
+    }
+
+    /// Returns the frame that is on screen at `timestamp`, i.e. the last frame
+    /// whose presentation time is not after it. Seeks to the preceding
+    /// keyframe and decodes forward, unless the target is just ahead of the
+    /// current position, in which case it decodes forward directly.
+    fn seek(&mut self, timestamp: f64) -> Result<Option<DecodedFrame>, Error> {
+        let timestamp = timestamp.max(0.0);
+
+        let mut candidate = match self.last.take() {
+            Some((frame, position)) if decodes_forward(position, timestamp) => Some((frame, position)),
+            _ => {
+                let start_seconds = self.start_pts as f64 * self.time_base;
+                let target = ((start_seconds + timestamp) * AV_TIME_BASE) as i64;
+                self.input.seek(target, ..target)
+                    .map_err(|e| Error::decode("Failed to seek", &self.path, e))?;
+                self.decoder.flush();
+                self.pending = None;
+                self.eof_sent = false;
+                None
+            },
+        };
+
+        // Half a frame of tolerance absorbs rounding in the requested time.
+        let tolerance = if self.frame_rate > 0.0 { 0.5 / self.frame_rate } else { 0.0 };
+
+        loop {
+            let frame = match self.pending.take() {
+                Some(frame) => frame,
+                None => match self.decode_next()? {
+                    Some(frame) => frame,
+                    None => break,
+                },
+            };
+
+            let frame_time = self.timestamp_of(&frame);
+            if frame_time <= timestamp + tolerance || candidate.is_none() {
+                let past_target = frame_time > timestamp + tolerance;
+                candidate = Some((frame, frame_time));
+                if past_target {
+                    break;
+                }
+            } else {
+                self.pending = Some(frame);
+                break;
+            }
+        }
+
+        match candidate {
+            Some((frame, frame_time)) => {
+                let decoded = self.convert(&frame, frame_time)?;
+                self.last = Some((frame, frame_time));
+                Ok(Some(decoded))
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+impl FrameDecoder {
+    fn decode_next(&mut self) -> Result<Option<Video>, Error> {
+        let mut frame = Video::empty();
+
+        loop {
+            match self.decoder.receive_frame(&mut frame) {
+                Ok(()) => return Ok(Some(frame)),
+                Err(ffmpeg::Error::Eof) => return Ok(None),
+                Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => {
+                    if self.eof_sent {
+                        return Ok(None);
+                    }
+                },
+                Err(e) => return Err(Error::decode("Failed to decode frame", &self.path, e)),
+            }
+
+            let mut packet = Packet::empty();
+            match packet.read(&mut self.input) {
+                Ok(()) => {
+                    if packet.stream() == self.stream_index {
+                        self.decoder.send_packet(&packet)
+                            .map_err(|e| Error::decode("Failed to decode frame", &self.path, e))?;
+                    }
+                },
+                Err(ffmpeg::Error::Eof) => {
+                    self.decoder.send_eof()
+                        .map_err(|e| Error::decode("Failed to flush decoder", &self.path, e))?;
+                    self.eof_sent = true;
+                },
+                Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => {},
+                Err(e) => return Err(Error::decode("Failed to read packet", &self.path, e)),
+            }
+        }
+    }
+
+    fn timestamp_of(&self, frame: &Video) -> f64 {
+        match frame.timestamp().or_else(|| frame.pts()) {
+            Some(pts) => (pts - self.start_pts) as f64 * self.time_base,
+            // Frames without timestamps follow the previous one at the
+            // nominal frame rate.
+            None => match (&self.last, self.frame_rate > 0.0) {
+                (Some((_, position)), true) => position + 1.0 / self.frame_rate,
+                (Some((_, position)), false) => *position,
+                (None, _) => 0.0,
+            },
+        }
+    }
+
+    fn convert(&mut self, frame: &Video, timestamp: f64) -> Result<DecodedFrame, Error> {
+        // Decoders may only settle on a pixel format, e.g. one with alpha,
+        // once they have decoded something.
+        if frame.format() != self.scaler.input().format {
+            self.scaler = scaler_for(frame.format(), frame.width(), frame.height(), self.metadata.width, self.metadata.height)
+                .map_err(|e| Error::decode("Failed to create frame scaler", &self.path, e))?;
+        }
+
+        let mut rgb = Video::empty();
+        self.scaler.run(frame, &mut rgb)
+            .map_err(|e| Error::decode("Failed to convert frame to RGB", &self.path, e))?;
+
+        let width = rgb.width() as usize;
+        let height = rgb.height() as usize;
+        let stride = rgb.stride(0);
+        let data = rgb.data(0);
+        let channels = if rgb.format() == Pixel::RGBA { 4 } else { 3 };
+
+        let mut pixels = Vec::with_capacity(width * height * channels);
+        for y in 0..height {
+            pixels.extend_from_slice(&data[y * stride..y * stride + width * channels]);
+        }
+
+        let size_error = || Error::decode("Failed to convert frame to RGB", &self.path, "unexpected frame size");
+        if channels == 4 {
+            let image = RgbaImage::from_raw(width as u32, height as u32, pixels).ok_or_else(size_error)?;
+            Ok(to_frame(DynamicImage::ImageRgba8(image), width as u32, self.background, timestamp))
+        } else {
+            let image = RgbImage::from_raw(width as u32, height as u32, pixels).ok_or_else(size_error)?;
+            Ok(DecodedFrame { image, alpha: None, timestamp })
+        }
+    }
+}
+
+/// Whether `seek` reaches `timestamp` from the frame last returned, shown
+/// at `position`, by decoding forward rather than seeking to a keyframe:
+/// only for targets a short way ahead.
+fn decodes_forward(position: f64, timestamp: f64) -> bool {
+    position <= timestamp && timestamp - position < FORWARD_DECODE_LIMIT
+}
+
+/// A scaler from `format` to RGB, or to RGBA when `format` has an alpha
+/// channel.
+fn scaler_for(format: Pixel, width: u32, height: u32, scaled_width: u32, scaled_height: u32) -> Result<scaling::Context, ffmpeg::Error> {
+    // Gray with alpha has two components, and YUV or RGB with alpha four;
+    // formats with padding such as RGB0 count three.
+    let has_alpha = matches!(format.descriptor().map(|descriptor| descriptor.nb_components()), Some(2) | Some(4));
+    let output = if has_alpha { Pixel::RGBA } else { Pixel::RGB24 };
+    scaling::Context::get(format, width, height, output, scaled_width, scaled_height, Flags::BILINEAR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_forward_to_nearby_targets() {
+        assert!(decodes_forward(10.0, 10.0));
+        assert!(decodes_forward(10.0, 10.04));
+        assert!(decodes_forward(10.0, 10.0 + FORWARD_DECODE_LIMIT - 0.01));
+    }
+
+    #[test]
+    fn seeks_to_a_keyframe_for_far_or_earlier_targets() {
+        assert!(!decodes_forward(10.0, 10.0 + FORWARD_DECODE_LIMIT));
+        assert!(!decodes_forward(10.0, 60.0));
+        assert!(!decodes_forward(10.0, 9.96));
+        assert!(!decodes_forward(10.0, 0.0));
+    }
+}
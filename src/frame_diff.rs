@@ -0,0 +1,376 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use crate::cells::{Cell, CellGrid, ColorMode, Rgb};
+
+// Unchanged cells between two changed runs are rewritten rather than skipped
+// when the gap is shorter than this; a cursor move costs at least 6 bytes.
+const MAX_MERGE_GAP: usize = 6;
+
+/// Turns a sequence of cell grids into a minimal stream of terminal escape
+/// sequences. Only cells that differ from the previously emitted frame are
+/// written, cursor moves are skipped when the cursor is already in place and
+/// SGR color changes are only emitted for the colors that actually change.
+pub struct FrameDiffer {
+    color_mode: ColorMode,
+    previous: Option<CellGrid>,
+    bytes_written: u64,
+}
+
+impl FrameDiffer {
+    pub fn new(color_mode: ColorMode) -> Self {
+        FrameDiffer {
+            color_mode,
+            previous: None,
+            bytes_written: 0,
+        }
+    }
+
+    /// Total number of bytes produced by `diff` and `finish` so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Returns the bytes needed to turn the previously emitted frame into
+    /// `next`. The first frame, and any frame with different dimensions,
+    /// is drawn in full after clearing the screen.
+    pub fn diff(&mut self, next: &CellGrid) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        let previous = match self.previous.take() {
+            Some(prev) if prev.width() == next.width() && prev.height() == next.height() => Some(prev),
+            _ => None,
+        };
+
+        if previous.is_none() {
+            out.extend_from_slice(b"\x1b[0m\x1b[2J");
+        }
+
+        // Neither position nor color is known at the start of a diff, since
+        // anything may have been written to the terminal in between.
+        let mut cursor: Option<(usize, usize)> = None;
+        let mut fg: Option<Rgb> = None;
+        let mut bg: Option<Option<Rgb>> = None;
+
+        for y in 0..next.height() {
+            let row = next.row(y);
+            let spans = match &previous {
+                Some(prev) => self.changed_spans(prev.row(y), row),
+                None => vec![(0, row.len())],
+            };
+
+            for (start, end) in spans {
+                if cursor != Some((start, y)) {
+                    write!(out, "\x1b[{};{}H", y + 1, start + 1).unwrap();
+                }
+
+                for cell in &row[start..end] {
+                    if self.color_mode != ColorMode::Mono {
+                        let new_fg = Some(cell.fg).filter(|_| !fg.is_some_and(|fg| self.same_color(fg, cell.fg)));
+                        let new_bg = Some(cell.bg).filter(|_| !bg.is_some_and(|bg| self.same_bg(bg, cell.bg)));
+                        write_sgr(&mut out, self.color_mode, new_fg, new_bg);
+                        fg = Some(cell.fg);
+                        bg = Some(cell.bg);
+                    }
+                    let mut buf = [0u8; 4];
+                    out.extend_from_slice(cell.glyph.encode_utf8(&mut buf).as_bytes());
+                }
+
+                cursor = Some((end, y));
+            }
+        }
+
+        self.previous = Some(next.clone());
+        self.bytes_written += out.len() as u64;
+        out
+    }
+
+    /// Bytes that restore default attributes and park the cursor below the
+    /// last drawn frame, so the shell prompt doesn't land inside it.
+    pub fn finish(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if self.color_mode != ColorMode::Mono {
+            out.extend_from_slice(b"\x1b[0m");
+        }
+        if let Some(prev) = &self.previous {
+            write!(out, "\x1b[{};1H", prev.height() + 1).unwrap();
+        }
+        self.bytes_written += out.len() as u64;
+        out
+    }
+
+    fn same(&self, a: &Cell, b: &Cell) -> bool {
+        a.glyph == b.glyph
+            && (self.color_mode == ColorMode::Mono || (self.same_color(a.fg, b.fg) && self.same_bg(a.bg, b.bg)))
+    }
+
+    /// Whether two colors look the same on the terminal: in 256-color mode,
+    /// whether they map to the same palette entry.
+    fn same_color(&self, a: Rgb, b: Rgb) -> bool {
+        match self.color_mode {
+            ColorMode::Ansi256 => a.to_ansi256() == b.to_ansi256(),
+            _ => a == b,
+        }
+    }
+
+    fn same_bg(&self, a: Option<Rgb>, b: Option<Rgb>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => self.same_color(a, b),
+            (a, b) => a == b,
+        }
+    }
+
+    /// Half-open `(start, end)` column ranges that need rewriting, with short
+    /// unchanged gaps folded into the surrounding runs.
+    fn changed_spans(&self, prev: &[Cell], next: &[Cell]) -> Vec<(usize, usize)> {
+        let mut spans: Vec<(usize, usize)> = Vec::new();
+
+        for x in 0..next.len() {
+            if self.same(&prev[x], &next[x]) {
+                continue;
+            }
+            match spans.last_mut() {
+                Some((_, end)) if x - *end <= MAX_MERGE_GAP => *end = x + 1,
+                _ => spans.push((x, x + 1)),
+            }
+        }
+
+        spans
+    }
+}
+
+/// Writes one SGR sequence setting whichever of the foreground and
+/// background is given. A background of `None` is the terminal default.
+pub(crate) fn write_sgr(out: &mut Vec<u8>, color_mode: ColorMode, fg: Option<Rgb>, bg: Option<Option<Rgb>>) {
+    if color_mode == ColorMode::Mono || (fg.is_none() && bg.is_none()) {
+        return;
+    }
+
+    let mut params = Vec::new();
+    if let Some(fg) = fg {
+        params.push(match color_mode {
+            ColorMode::Ansi256 => format!("38;5;{}", fg.to_ansi256()),
+            _ => format!("38;2;{};{};{}", fg.r, fg.g, fg.b),
+        });
+    }
+    match bg {
+        Some(Some(bg)) => params.push(match color_mode {
+            ColorMode::Ansi256 => format!("48;5;{}", bg.to_ansi256()),
+            _ => format!("48;2;{};{};{}", bg.r, bg.g, bg.b),
+        }),
+        Some(None) => params.push(String::from("49")),
+        None => {},
+    }
+    write!(out, "\x1b[{}m", params.join(";")).unwrap();
+}
+
+/// Wraps a writer and counts every byte that passes through it. The counter
+/// is shared so it can be read while the writer is owned by something else,
+/// such as a ratatui backend.
+pub struct CountingWriter<W: Write> {
+    inner: W,
+    count: Arc<AtomicU64>,
+}
+
+impl<W: Write> CountingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        CountingWriter {
+            inner,
+            count: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn counter(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.count)
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count.fetch_add(written as u64, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Converts a growing byte total into a rate, re-sampled about once a second.
+pub struct BandwidthMeter {
+    last_total: u64,
+    last_sample: Instant,
+    bytes_per_sec: f64,
+}
+
+impl BandwidthMeter {
+    pub fn new() -> Self {
+        BandwidthMeter {
+            last_total: 0,
+            last_sample: Instant::now(),
+            bytes_per_sec: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, total: u64) -> f64 {
+        let elapsed = self.last_sample.elapsed().as_secs_f64();
+        if elapsed >= 1.0 {
+            self.bytes_per_sec = total.saturating_sub(self.last_total) as f64 / elapsed;
+            self.last_total = total;
+            self.last_sample = Instant::now();
+        }
+        self.bytes_per_sec
+    }
+}
+
+impl Default for BandwidthMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn format_bandwidth(bytes_per_sec: f64) -> String {
+    if bytes_per_sec >= 1024.0 * 1024.0 {
+        format!("{:.1} MB/s", bytes_per_sec / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1} KB/s", bytes_per_sec / 1024.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(glyph: char, fg: Rgb, bg: Option<Rgb>) -> Cell {
+        Cell { glyph, fg, bg }
+    }
+
+    /// A `width` by `height` grid of dots in the default colors.
+    fn grid(width: usize, height: usize) -> CellGrid {
+        let mut grid = CellGrid::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                grid.set(x, y, Cell { glyph: '.', ..Cell::default() });
+            }
+        }
+        grid
+    }
+
+    fn text(bytes: Vec<u8>) -> String {
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn first_frame_is_drawn_in_full() {
+        let mut differ = FrameDiffer::new(ColorMode::Mono);
+        assert_eq!(text(differ.diff(&grid(3, 2))), "\x1b[0m\x1b[2J\x1b[1;1H...\x1b[2;1H...");
+    }
+
+    #[test]
+    fn unchanged_frame_writes_nothing() {
+        let mut differ = FrameDiffer::new(ColorMode::TrueColor);
+        differ.diff(&grid(4, 3));
+        assert!(differ.diff(&grid(4, 3)).is_empty());
+    }
+
+    #[test]
+    fn only_changed_span_is_written() {
+        let mut differ = FrameDiffer::new(ColorMode::Mono);
+        differ.diff(&grid(20, 3));
+
+        let mut next = grid(20, 3);
+        next.set(5, 1, Cell { glyph: 'X', ..Cell::default() });
+        next.set(6, 1, Cell { glyph: 'Y', ..Cell::default() });
+        assert_eq!(text(differ.diff(&next)), "\x1b[2;6HXY");
+    }
+
+    #[test]
+    fn small_gaps_are_merged() {
+        let mut differ = FrameDiffer::new(ColorMode::Mono);
+        differ.diff(&grid(30, 1));
+
+        let mut next = grid(30, 1);
+        next.set(2, 0, Cell { glyph: 'A', ..Cell::default() });
+        next.set(6, 0, Cell { glyph: 'B', ..Cell::default() });
+        // The three unchanged cells in between are cheaper to rewrite.
+        assert_eq!(text(differ.diff(&next)), "\x1b[1;3HA...B");
+    }
+
+    #[test]
+    fn large_gaps_move_the_cursor() {
+        let mut differ = FrameDiffer::new(ColorMode::Mono);
+        differ.diff(&grid(30, 1));
+
+        let mut next = grid(30, 1);
+        next.set(2, 0, Cell { glyph: 'A', ..Cell::default() });
+        next.set(20, 0, Cell { glyph: 'B', ..Cell::default() });
+        assert_eq!(text(differ.diff(&next)), "\x1b[1;3HA\x1b[1;21HB");
+    }
+
+    #[test]
+    fn resize_redraws_in_full() {
+        let mut differ = FrameDiffer::new(ColorMode::Mono);
+        differ.diff(&grid(3, 2));
+        assert!(text(differ.diff(&grid(2, 2))).starts_with("\x1b[0m\x1b[2J"));
+    }
+
+    #[test]
+    fn colors_are_set_only_when_they_change() {
+        let red = Rgb::new(255, 0, 0);
+        let blue = Rgb::new(0, 0, 255);
+        let mut frame = CellGrid::new(3, 1);
+        frame.set(0, 0, cell('a', red, None));
+        frame.set(1, 0, cell('b', red, None));
+        frame.set(2, 0, cell('c', blue, None));
+
+        let mut differ = FrameDiffer::new(ColorMode::TrueColor);
+        assert_eq!(
+            text(differ.diff(&frame)),
+            "\x1b[0m\x1b[2J\x1b[1;1H\x1b[38;2;255;0;0;49mab\x1b[38;2;0;0;255mc"
+        );
+    }
+
+    #[test]
+    fn ansi256_compares_palette_entries() {
+        let red = Rgb::new(255, 0, 0);
+        let near_red = Rgb::new(250, 10, 0);
+        assert_eq!(red.to_ansi256(), near_red.to_ansi256());
+        assert_ne!(red, near_red);
+
+        let mut frame = CellGrid::new(2, 1);
+        frame.set(0, 0, cell('a', red, Some(near_red)));
+        frame.set(1, 0, cell('b', near_red, Some(red)));
+
+        // One color sequence for both cells.
+        let mut differ = FrameDiffer::new(ColorMode::Ansi256);
+        assert_eq!(text(differ.diff(&frame)), "\x1b[0m\x1b[2J\x1b[1;1H\x1b[38;5;196;48;5;196mab");
+
+        // Swapping colors within a palette entry changes nothing on screen.
+        let mut next = CellGrid::new(2, 1);
+        next.set(0, 0, cell('a', near_red, Some(red)));
+        next.set(1, 0, cell('b', red, Some(near_red)));
+        assert!(differ.diff(&next).is_empty());
+    }
+
+    #[test]
+    fn terminal_background_is_restored() {
+        let white = Rgb::new(255, 255, 255);
+        let mut frame = CellGrid::new(2, 1);
+        frame.set(0, 0, cell('a', white, Some(Rgb::new(0, 0, 0))));
+        frame.set(1, 0, cell('b', white, None));
+
+        let mut differ = FrameDiffer::new(ColorMode::TrueColor);
+        assert!(text(differ.diff(&frame)).ends_with("a\x1b[49mb"));
+    }
+
+    #[test]
+    fn finish_parks_the_cursor_below_the_frame() {
+        let mut differ = FrameDiffer::new(ColorMode::TrueColor);
+        let drawn = differ.diff(&grid(4, 3)).len() as u64;
+        let finish = differ.finish();
+        assert_eq!(text(finish.clone()), "\x1b[0m\x1b[4;1H");
+        assert_eq!(differ.bytes_written(), drawn + finish.len() as u64);
+    }
+}
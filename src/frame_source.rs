@@ -0,0 +1,340 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use image::{GrayImage, RgbImage};
+use crate::background::Background;
+use crate::cells::AsciiFrame;
+use crate::error::Error;
+use crate::frame_decoder::FrameDecoder;
+use crate::image_source::{self, ImageSequence};
+use crate::probe::{MediaInfo, Probe};
+use crate::raw_input::RawRgbSource;
+use crate::renderer::Renderer;
+
+/// Width frames are decoded at before being resized to the grid, unless the
+/// builder says otherwise. Grids are rarely wider than this, and decoding
+/// small keeps up with high frame rates.
+pub const DEFAULT_DECODE_WIDTH: u32 = 320;
+
+/// Frame rate of image sequences and stdin input unless one is given.
+pub const DEFAULT_FRAME_RATE: f64 = 25.0;
+
+/// A decoded frame, scaled and converted to RGB.
+#[derive(Clone)]
+pub struct DecodedFrame {
+    pub image: RgbImage,
+    /// Opacity of each pixel of `image`, kept only when transparent parts
+    /// are left to the terminal's background.
+    pub alpha: Option<GrayImage>,
+    /// Presentation time in seconds, relative to the start of the source.
+    pub timestamp: f64,
+}
+
+/// What a source knows about its frames up front.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SourceMetadata {
+    /// Size of the frames the source returns, after any scaling.
+    pub width: u32,
+    pub height: u32,
+    /// Average frame rate, or 0 if unknown.
+    pub frame_rate: f64,
+    pub frame_count: Option<u64>,
+    pub duration: Option<f64>,
+}
+
+/// Anything that produces RGB frames in presentation order: video files,
+/// images, image sequences, stdin. Sources hold decoder state that usually
+/// can't move between threads, so they are opened through a
+/// `FrameSourceBuilder` on the thread that uses them.
+pub trait FrameSource {
+    fn metadata(&self) -> SourceMetadata;
+
+    /// The next frame, or `None` at the end of the source.
+    fn next_frame(&mut self) -> Result<Option<DecodedFrame>, Error>;
+
+    /// Jumps to the frame on screen at `timestamp` seconds and returns it.
+    /// Following `next_frame` calls continue from there.
+    fn seek(&mut self, timestamp: f64) -> Result<Option<DecodedFrame>, Error>;
+
+    /// Decodes the frame on screen at `timestamp` and renders it into a grid
+    /// of `width` by `height` cells. Calls with increasing timestamps are
+    /// cheapest, since most sources reach nearby targets by decoding forward.
+    fn render_at(&mut self, timestamp: f64, renderer: &dyn Renderer, width: u32, height: u32) -> Result<AsciiFrame, Error> {
+        match self.seek(timestamp)? {
+            Some(frame) => Ok(AsciiFrame {
+                cells: renderer.render_frame(&frame, width, height),
+                timestamp: frame.timestamp,
+            }),
+            None => Err(Error::InvalidInput(format!("No frame found at {:.3}s", timestamp))),
+        }
+    }
+
+    /// Renders the frames at each of `timestamps` in order, reusing this
+    /// open source for all of them. Sorted timestamps are cheapest.
+    fn render_frames(&mut self, timestamps: &[f64], renderer: &dyn Renderer, width: u32, height: u32) -> Result<Vec<AsciiFrame>, Error> {
+        timestamps
+            .iter()
+            .map(|&timestamp| self.render_at(timestamp, renderer, width, height))
+            .collect()
+    }
+
+    /// Like `render_at`, addressing the frame by its index at the source's
+    /// average frame rate.
+    fn render_at_index(&mut self, index: u64, renderer: &dyn Renderer, width: u32, height: u32) -> Result<AsciiFrame, Error> {
+        let frame_rate = self.metadata().frame_rate;
+        if frame_rate <= 0.0 {
+            return Err(Error::InvalidInput(String::from(
+                "Frame rate is unknown, cannot address frames by index"
+            )));
+        }
+
+        self.render_at(index as f64 / frame_rate, renderer, width, height)
+    }
+}
+
+type OpenFn = dyn Fn(u32) -> Result<Box<dyn FrameSource>, Error> + Send + Sync;
+
+#[derive(Clone)]
+enum SourceKind {
+    Video(PathBuf),
+    Image(PathBuf),
+    Sequence(String),
+    Stdin { width: u32, height: u32 },
+    Custom { name: String, open: Arc<OpenFn> },
+}
+
+/// Describes a frame source and how to open it. Holds no decoder state, so
+/// it can be sent to the thread that will do the decoding, and opened more
+/// than once for sources that allow it.
+#[derive(Clone)]
+pub struct FrameSourceBuilder {
+    kind: SourceKind,
+    decode_width: u32,
+    frame_rate: f64,
+    background: Background,
+}
+
+impl fmt::Debug for FrameSourceBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FrameSourceBuilder({})", self.name())
+    }
+}
+
+impl FrameSourceBuilder {
+    fn new(kind: SourceKind) -> Self {
+        FrameSourceBuilder {
+            kind,
+            decode_width: DEFAULT_DECODE_WIDTH,
+            frame_rate: DEFAULT_FRAME_RATE,
+            background: Background::default(),
+        }
+    }
+
+    /// A video file, decoded with FFmpeg.
+    pub fn video<P: AsRef<Path>>(path: P) -> Self {
+        Self::new(SourceKind::Video(path.as_ref().to_path_buf()))
+    }
+
+    /// A still or animated image (GIF, APNG, WebP), decoded with the `image`
+    /// crate.
+    pub fn image<P: AsRef<Path>>(path: P) -> Self {
+        Self::new(SourceKind::Image(path.as_ref().to_path_buf()))
+    }
+
+    /// The images matching a glob pattern such as `frames/*.png`, one per
+    /// frame in numeric order.
+    pub fn sequence(pattern: &str) -> Self {
+        Self::new(SourceKind::Sequence(pattern.to_string()))
+    }
+
+    /// Raw RGB24 frames of `width` by `height` pixels read from stdin, e.g.
+    /// piped from `ffmpeg -f rawvideo -pix_fmt rgb24 -`. Stdin can only be
+    /// read through once.
+    pub fn stdin_rgb(width: u32, height: u32) -> Self {
+        Self::new(SourceKind::Stdin { width: width.max(1), height: height.max(1) })
+    }
+
+    /// A source opened by `open`, which gets the decode width. For backends
+    /// this crate doesn't provide.
+    pub fn custom<F>(name: &str, open: F) -> Self
+    where
+        F: Fn(u32) -> Result<Box<dyn FrameSource>, Error> + Send + Sync + 'static,
+    {
+        Self::new(SourceKind::Custom { name: name.to_string(), open: Arc::new(open) })
+    }
+
+    /// An image or a video, going by the file extension.
+    pub fn detect<P: AsRef<Path>>(path: P) -> Self {
+        if image_source::is_image_file(path.as_ref()) {
+            Self::image(path)
+        } else {
+            Self::video(path)
+        }
+    }
+
+    /// Frames are scaled down to at most `width` pixels wide, keeping the
+    /// aspect ratio.
+    pub fn decode_width(mut self, width: u32) -> Self {
+        self.decode_width = width.max(1);
+        self
+    }
+
+    /// Frame rate of image sequences and stdin input, which carry no timing
+    /// of their own.
+    pub fn frame_rate(mut self, fps: f64) -> Self {
+        if fps > 0.0 {
+            self.frame_rate = fps;
+        }
+        self
+    }
+
+    /// What transparent parts of images and videos are drawn over.
+    pub fn background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// Name for messages and the status bar: the file name, pattern or
+    /// "stdin".
+    pub fn name(&self) -> String {
+        match &self.kind {
+            SourceKind::Video(path) | SourceKind::Image(path) => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string()),
+            SourceKind::Sequence(pattern) => pattern.clone(),
+            SourceKind::Stdin { .. } => String::from("stdin"),
+            SourceKind::Custom { name, .. } => name.clone(),
+        }
+    }
+
+    /// Whether the source is a video file, the only kind with audio and
+    /// subtitle tracks.
+    pub fn is_video(&self) -> bool {
+        matches!(self.kind, SourceKind::Video(_))
+    }
+
+    /// Whether the source is a single image file, still or animated.
+    pub fn is_image(&self) -> bool {
+        matches!(self.kind, SourceKind::Image(_))
+    }
+
+    /// Whether the source can be opened again, e.g. for a scene detection
+    /// pass next to playback. Stdin can't.
+    pub fn is_reopenable(&self) -> bool {
+        !matches!(self.kind, SourceKind::Stdin { .. })
+    }
+
+    pub fn open(&self) -> Result<Box<dyn FrameSource>, Error> {
+        Ok(match &self.kind {
+            SourceKind::Video(path) => Box::new(FrameDecoder::open(path, self.decode_width, self.background)?),
+            SourceKind::Image(path) => image_source::open(path, self.decode_width, self.background)?,
+            SourceKind::Sequence(pattern) => {
+                Box::new(ImageSequence::open(pattern, self.frame_rate, self.decode_width, self.background)?)
+            },
+            SourceKind::Stdin { width, height } => {
+                Box::new(RawRgbSource::new(io::stdin(), *width, *height, self.frame_rate, self.decode_width))
+            },
+            SourceKind::Custom { open, .. } => open(self.decode_width)?,
+        })
+    }
+
+    /// Metadata for the player and the exporters. Videos are probed with
+    /// ffprobe, which also finds chapters and audio tracks; everything else
+    /// is described by its own metadata.
+    pub fn probe(&self) -> Result<MediaInfo, Error> {
+        let path = match &self.kind {
+            SourceKind::Video(path) => return Probe::new(path).run(),
+            SourceKind::Image(path) => path.clone(),
+            SourceKind::Sequence(pattern) => PathBuf::from(pattern),
+            SourceKind::Stdin { .. } => PathBuf::from("-"),
+            SourceKind::Custom { name, .. } => PathBuf::from(name),
+        };
+
+        let metadata = self.open()?.metadata();
+        Ok(MediaInfo {
+            path,
+            width: Some(metadata.width),
+            height: Some(metadata.height),
+            frame_count: metadata.frame_count,
+            duration: metadata.duration,
+            chapters: Vec::new(),
+            audio_tracks: Vec::new(),
+        })
+    }
+}
+
+/// `(width, height)` scaled down to at most `max_width` wide, keeping the
+/// aspect ratio.
+pub(crate) fn scaled_size(width: u32, height: u32, max_width: u32) -> (u32, u32) {
+    let scaled_width = width.min(max_width).max(1);
+    let scaled_height = ((height as u64 * scaled_width as u64) / width.max(1) as u64).max(1) as u32;
+    (scaled_width, scaled_height)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::RampRenderer;
+    use super::*;
+
+    /// Ten frames at 10 fps, each a solid gray one step brighter than the
+    /// last. Counts seeks to show a batch reuses the one source.
+    struct Steps {
+        position: usize,
+        seeks: usize,
+    }
+
+    impl Steps {
+        fn frame(index: usize) -> DecodedFrame {
+            DecodedFrame {
+                image: RgbImage::from_pixel(4, 4, image::Rgb([index as u8 * 25; 3])),
+                alpha: None,
+                timestamp: index as f64 / 10.0,
+            }
+        }
+    }
+
+    impl FrameSource for Steps {
+        fn metadata(&self) -> SourceMetadata {
+            SourceMetadata { width: 4, height: 4, frame_rate: 10.0, frame_count: Some(10), duration: Some(1.0) }
+        }
+
+        fn next_frame(&mut self) -> Result<Option<DecodedFrame>, Error> {
+            let frame = (self.position < 10).then(|| Steps::frame(self.position));
+            self.position += 1;
+            Ok(frame)
+        }
+
+        fn seek(&mut self, timestamp: f64) -> Result<Option<DecodedFrame>, Error> {
+            self.seeks += 1;
+            self.position = (timestamp * 10.0 + 1e-9) as usize;
+            self.next_frame()
+        }
+    }
+
+    #[test]
+    fn renders_batches_in_order_from_one_source() {
+        let mut source = Steps { position: 0, seeks: 0 };
+        let renderer = RampRenderer::new();
+
+        let frames = source.render_frames(&[0.5, 0.1, 0.95], &renderer, 3, 2).unwrap();
+        let timestamps: Vec<f64> = frames.iter().map(|frame| frame.timestamp).collect();
+        assert_eq!(timestamps, [0.5, 0.1, 0.9]);
+        assert!(frames.iter().all(|frame| (frame.cells.width(), frame.cells.height()) == (3, 2)));
+        assert_eq!(source.seeks, 3);
+    }
+
+    #[test]
+    fn batch_fails_on_a_missing_frame() {
+        let mut source = Steps { position: 0, seeks: 0 };
+        assert!(source.render_frames(&[0.2, 5.0], &RampRenderer::new(), 3, 2).is_err());
+    }
+
+    #[test]
+    fn addresses_frames_by_index() {
+        let mut source = Steps { position: 0, seeks: 0 };
+        let frame = source.render_at_index(7, &RampRenderer::new(), 3, 2).unwrap();
+        assert_eq!(frame.timestamp, 0.7);
+    }
+}
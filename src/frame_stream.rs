@@ -0,0 +1,327 @@
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::cells::CellGrid;
+use crate::error::Error;
+use crate::frame_source::{DecodedFrame, FrameSource, FrameSourceBuilder, SourceMetadata};
+use crate::scene_detection::{SceneCut, SceneDetector};
+
+// How many converted frames the decode thread may run ahead of the player.
+const FRAME_BUFFER: usize = 8;
+
+/// A converted frame and its presentation time.
+pub struct StreamFrame {
+    pub cells: CellGrid,
+    /// Seconds from the start of the video.
+    pub timestamp: f64,
+    /// Time spent decoding and scaling the frame, and converting it to cells.
+    pub decode_time: Duration,
+    pub convert_time: Duration,
+}
+
+pub enum StreamEvent {
+    Frame(StreamFrame),
+    /// The decoder reached the end of the video.
+    End,
+    /// Decoding failed; nothing more comes until the player seeks.
+    Failed(Error),
+}
+
+enum Command {
+    Seek { timestamp: f64, generation: u64 },
+}
+
+/// Decodes and converts frames on a background thread, in presentation
+/// order and at the video's own frame rate, keeping a few frames buffered
+/// ahead of playback.
+///
+/// Every seek starts a new generation; frames still in flight from before
+/// the seek are discarded on the receiving side, so the player never shows a
+/// stale frame after jumping.
+pub struct FrameStream {
+    commands: Sender<Command>,
+    events: Receiver<(u64, StreamEvent)>,
+    // Events sent but not yet received, for reporting how full the buffer is.
+    queued: Arc<AtomicUsize>,
+    // Scene cuts found in decoded frames, regardless of generation.
+    scene_cuts: Receiver<SceneCut>,
+    generation: u64,
+    metadata: SourceMetadata,
+}
+
+impl FrameStream {
+    /// Starts decoding the source `source` opens and converting each frame
+    /// with `convert`. With `max_fps`, frames that follow the previous one
+    /// more closely than `1 / max_fps` are skipped before conversion. With
+    /// `scene_threshold`, every decoded frame also goes through a
+    /// `SceneDetector` and the cuts it finds are available from
+    /// `scene_cuts` as decoding reaches them.
+    pub fn spawn<F>(
+        source: FrameSourceBuilder,
+        max_fps: Option<f64>,
+        scene_threshold: Option<f64>,
+        convert: F,
+    ) -> Result<Self, Error>
+    where
+        F: Fn(&DecodedFrame) -> CellGrid + Send + 'static,
+    {
+        let (command_tx, command_rx) = mpsc::channel();
+        let (event_tx, event_rx) = mpsc::sync_channel(FRAME_BUFFER);
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let (cut_tx, cut_rx) = mpsc::channel();
+        let queued = Arc::new(AtomicUsize::new(0));
+        let sender = EventSender { events: event_tx, queued: Arc::clone(&queued) };
+        let scenes = scene_threshold.map(|threshold| SceneSender {
+            detector: SceneDetector::new(threshold),
+            cuts: cut_tx,
+        });
+
+        // The source holds FFmpeg state that can't move between threads, so
+        // it is opened on the thread that uses it.
+        thread::spawn(move || {
+            let source = match source.open() {
+                Ok(source) => {
+                    let _ = ready_tx.send(Ok(source.metadata()));
+                    source
+                },
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                },
+            };
+            decode_loop(source, max_fps, convert, command_rx, sender, scenes);
+        });
+
+        let metadata = ready_rx.recv()
+            .map_err(|_| Error::Io(io::Error::other("Decoder thread exited unexpectedly")))??;
+
+        Ok(FrameStream {
+            commands: command_tx,
+            events: event_rx,
+            queued,
+            scene_cuts: cut_rx,
+            generation: 0,
+            metadata,
+        })
+    }
+
+    /// What the source reported about itself when it was opened.
+    pub fn metadata(&self) -> SourceMetadata {
+        self.metadata
+    }
+
+    /// Frames decoded ahead of playback, and how many fit in the buffer.
+    pub fn buffered(&self) -> (usize, usize) {
+        (self.queued.load(Ordering::Relaxed).min(FRAME_BUFFER), FRAME_BUFFER)
+    }
+
+    /// Scene cuts found since the last call, in decoding order. A cut can
+    /// come up again when the same part of the video is decoded twice.
+    pub fn scene_cuts(&self) -> Vec<SceneCut> {
+        self.scene_cuts.try_iter().collect()
+    }
+
+    /// Restarts decoding at the frame on screen at `timestamp`.
+    pub fn seek(&mut self, timestamp: f64) {
+        self.generation += 1;
+        let _ = self.commands.send(Command::Seek { timestamp, generation: self.generation });
+    }
+
+    /// The next event if one is ready, without waiting.
+    pub fn try_next(&mut self) -> Option<StreamEvent> {
+        for (generation, event) in self.events.try_iter() {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+            if generation == self.generation {
+                return Some(event);
+            }
+        }
+        None
+    }
+
+    /// Waits for the next event. Returns `None` if the decode thread is gone.
+    pub fn next(&mut self) -> Option<StreamEvent> {
+        loop {
+            let (generation, event) = self.events.recv().ok()?;
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+            if generation == self.generation {
+                return Some(event);
+            }
+        }
+    }
+}
+
+struct EventSender {
+    events: SyncSender<(u64, StreamEvent)>,
+    queued: Arc<AtomicUsize>,
+}
+
+impl EventSender {
+    /// Blocks while the buffer is full. Returns false once the player is gone.
+    fn send(&self, generation: u64, event: StreamEvent) -> bool {
+        // Counted before sending so the receiver can never see it go negative.
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        self.events.send((generation, event)).is_ok()
+    }
+}
+
+struct SceneSender {
+    detector: SceneDetector,
+    cuts: Sender<SceneCut>,
+}
+
+impl SceneSender {
+    fn push(&mut self, frame: &DecodedFrame) {
+        let found = self.detector.cuts().len();
+        self.detector.push(frame.timestamp, &frame.image);
+        for cut in &self.detector.cuts()[found..] {
+            let _ = self.cuts.send(*cut);
+        }
+    }
+}
+
+fn decode_loop<F>(
+    mut source: Box<dyn FrameSource>,
+    max_fps: Option<f64>,
+    convert: F,
+    commands: Receiver<Command>,
+    events: EventSender,
+    mut scenes: Option<SceneSender>,
+) where
+    F: Fn(&DecodedFrame) -> CellGrid,
+{
+    let min_interval = max_fps.filter(|fps| *fps > 0.0).map(|fps| 1.0 / fps);
+    let mut generation = 0;
+    let mut last_sent: Option<f64> = None;
+    let mut seek_to: Option<f64> = None;
+
+    loop {
+        // Only the latest of several queued seeks matters.
+        for Command::Seek { timestamp, generation: seek_generation } in commands.try_iter() {
+            seek_to = Some(timestamp);
+            generation = seek_generation;
+        }
+
+        let decode_started = Instant::now();
+        let frame = match seek_to.take() {
+            Some(timestamp) => {
+                last_sent = None;
+                // The jump itself isn't a cut.
+                if let Some(scenes) = &mut scenes {
+                    scenes.detector.restart();
+                }
+                source.seek(timestamp)
+            },
+            None => source.next_frame(),
+        };
+
+        let frame = match frame {
+            Ok(Some(frame)) => frame,
+            Ok(None) | Err(_) => {
+                let event = match frame {
+                    Err(e) => StreamEvent::Failed(e),
+                    _ => StreamEvent::End,
+                };
+                if !events.send(generation, event) {
+                    return;
+                }
+                // Nothing more to decode until the player seeks.
+                match commands.recv() {
+                    Ok(Command::Seek { timestamp, generation: seek_generation }) => {
+                        seek_to = Some(timestamp);
+                        generation = seek_generation;
+                        continue;
+                    },
+                    Err(_) => return,
+                }
+            },
+        };
+
+        // Every decoded frame is compared, including ones skipped below, so
+        // cuts land on the right frame.
+        if let Some(scenes) = &mut scenes {
+            scenes.push(&frame);
+        }
+
+        if let (Some(interval), Some(last)) = (min_interval, last_sent) {
+            // A little slack keeps e.g. 30fps content at 30fps with --max-fps 30.
+            if frame.timestamp - last < interval * 0.99 {
+                continue;
+            }
+        }
+        last_sent = Some(frame.timestamp);
+
+        let decode_time = decode_started.elapsed();
+        let convert_started = Instant::now();
+        let cells = convert(&frame);
+
+        let event = StreamEvent::Frame(StreamFrame {
+            cells,
+            timestamp: frame.timestamp,
+            decode_time,
+            convert_time: convert_started.elapsed(),
+        });
+        if !events.send(generation, event) {
+            return;
+        }
+    }
+}
+
+/// Media clock for playback: the position advances with wall time scaled by
+/// the playback rate, and stands still while paused.
+pub struct PlaybackClock {
+    base: f64,
+    started: Option<Instant>,
+}
+
+impl PlaybackClock {
+    /// A running clock at position 0.
+    pub fn new() -> Self {
+        PlaybackClock {
+            base: 0.0,
+            started: Some(Instant::now()),
+        }
+    }
+
+    pub fn position(&self, rate: f64) -> f64 {
+        match self.started {
+            Some(started) => self.base + started.elapsed().as_secs_f64() * rate,
+            None => self.base,
+        }
+    }
+
+    pub fn seek(&mut self, position: f64) {
+        self.base = position.max(0.0);
+        if self.started.is_some() {
+            self.started = Some(Instant::now());
+        }
+    }
+
+    pub fn pause(&mut self, rate: f64) {
+        self.base = self.position(rate);
+        self.started = None;
+    }
+
+    pub fn resume(&mut self) {
+        if self.started.is_none() {
+            self.started = Some(Instant::now());
+        }
+    }
+
+    /// Must be called with the old rate right before the rate changes, so
+    /// time already played isn't rescaled.
+    pub fn rebase(&mut self, rate: f64) {
+        let position = self.position(rate);
+        self.seek(position);
+    }
+
+    /// Wall time until the clock reaches `timestamp`; zero if it already
+    /// has, `None` while paused.
+    pub fn until(&self, timestamp: f64, rate: f64) -> Option<Duration> {
+        self.started?;
+        let remaining = (timestamp - self.position(rate)) / rate;
+        Some(Duration::from_secs_f64(remaining.max(0.0)))
+    }
+}
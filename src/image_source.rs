@@ -0,0 +1,510 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::imageops::FilterType;
+use image::{AnimationDecoder, DynamicImage, Frames, ImageError, RgbImage};
+use crate::background::{alpha_for, flatten, Background};
+use crate::error::Error;
+use crate::frame_source::{scaled_size, DecodedFrame, FrameSource, SourceMetadata};
+
+/// File extensions decoded with the `image` crate instead of FFmpeg.
+pub const IMAGE_EXTENSIONS: [&str; 9] = ["png", "apng", "jpg", "jpeg", "gif", "webp", "bmp", "tif", "tiff"];
+
+// Browsers show animation frames without a delay for 100 ms; so do we.
+const DEFAULT_FRAME_DELAY: f64 = 0.1;
+// GIF delays below this are treated as missing, as browsers do, since many
+// GIFs ask for 0 or 10 ms and rely on being slowed down.
+const MIN_GIF_FRAME_DELAY: f64 = 0.02;
+
+pub fn is_image_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| IMAGE_EXTENSIONS.iter().any(|image| ext.eq_ignore_ascii_case(image)))
+        .unwrap_or(false)
+}
+
+pub(crate) fn image_error(path: &Path, e: ImageError) -> Error {
+    match e {
+        ImageError::IoError(e) => Error::file(path, e),
+        e => Error::decode("Failed to decode image", path, e),
+    }
+}
+
+/// Converts a decoded image to a frame at most `max_width` pixels wide, with
+/// transparent parts drawn as `background` says.
+pub(crate) fn to_frame(image: DynamicImage, max_width: u32, background: Background, timestamp: f64) -> DecodedFrame {
+    let (width, height) = scaled_size(image.width(), image.height(), max_width);
+    // Scaled before flattening, so checkerboard squares keep their size.
+    let image = if (width, height) == (image.width(), image.height()) {
+        image
+    } else {
+        image.resize_exact(width, height, FilterType::Triangle)
+    };
+
+    DecodedFrame {
+        image: flatten(&image, background),
+        alpha: alpha_for(&image, background),
+        timestamp,
+    }
+}
+
+/// Opens an image file: animated GIF, APNG and WebP files as animations,
+/// anything else as a single still frame. Transparent parts are drawn as
+/// `background` says.
+pub fn open(path: &Path, max_width: u32, background: Background) -> Result<Box<dyn FrameSource>, Error> {
+    let reader = || File::open(path).map(BufReader::new).map_err(|e| Error::file(path, e));
+    let extension = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let frames = match extension.as_str() {
+        "gif" => Some(GifDecoder::new(reader()?).map_err(|e| image_error(path, e))?.into_frames()),
+        "png" | "apng" => {
+            let decoder = PngDecoder::new(reader()?).map_err(|e| image_error(path, e))?;
+            if decoder.is_apng().map_err(|e| image_error(path, e))? {
+                Some(decoder.apng().map_err(|e| image_error(path, e))?.into_frames())
+            } else {
+                None
+            }
+        },
+        "webp" => {
+            let decoder = WebPDecoder::new(reader()?).map_err(|e| image_error(path, e))?;
+            if decoder.has_animation() { Some(decoder.into_frames()) } else { None }
+        },
+        _ => None,
+    };
+
+    Ok(match frames {
+        Some(frames) => {
+            let plays = play_count(path, &extension)?;
+            let min_delay = if extension == "gif" { MIN_GIF_FRAME_DELAY } else { 0.0 };
+            Box::new(AnimatedImage::decode(path, frames, plays, min_delay, max_width, background)?)
+        },
+        None => {
+            let image = image::open(path).map_err(|e| image_error(path, e))?;
+            Box::new(StillImage::from_frame(to_frame(image, max_width, background, 0.0)))
+        },
+    })
+}
+
+/// How many times an animation asks to be played, or `None` for forever.
+/// Only GIF and APNG files say; anything else plays once.
+fn play_count(path: &Path, extension: &str) -> Result<Option<u32>, Error> {
+    let file = File::open(path).map(BufReader::new).map_err(|e| Error::file(path, e))?;
+
+    match extension {
+        "gif" => {
+            let mut decoder = gif::Decoder::new(file)
+                .map_err(|e| Error::decode("Failed to decode image", path, e))?;
+            // The loop count is in an extension block before the first
+            // frame, so that frame's header has to be read first.
+            decoder.next_frame_info()
+                .map_err(|e| Error::decode("Failed to decode image", path, e))?;
+            Ok(match decoder.repeat() {
+                gif::Repeat::Infinite => None,
+                // The count is of repetitions after the first play.
+                gif::Repeat::Finite(loops) => Some(loops as u32 + 1),
+            })
+        },
+        "png" | "apng" => {
+            let reader = png::Decoder::new(file)
+                .read_info()
+                .map_err(|e| Error::decode("Failed to decode image", path, e))?;
+            Ok(match reader.info().animation_control {
+                Some(control) if control.num_plays == 0 => None,
+                Some(control) => Some(control.num_plays),
+                None => Some(1),
+            })
+        },
+        _ => Ok(Some(1)),
+    }
+}
+
+/// A single image shown as a one-frame clip.
+pub struct StillImage {
+    frame: DecodedFrame,
+    shown: bool,
+}
+
+impl StillImage {
+    pub fn new(image: RgbImage) -> Self {
+        StillImage::from_frame(DecodedFrame { image, alpha: None, timestamp: 0.0 })
+    }
+
+    fn from_frame(frame: DecodedFrame) -> Self {
+        StillImage { frame, shown: false }
+    }
+}
+
+impl FrameSource for StillImage {
+    fn metadata(&self) -> SourceMetadata {
+        SourceMetadata {
+            width: self.frame.image.width(),
+            height: self.frame.image.height(),
+            frame_rate: 0.0,
+            frame_count: Some(1),
+            duration: None,
+        }
+    }
+
+    fn next_frame(&mut self) -> Result<Option<DecodedFrame>, Error> {
+        if self.shown {
+            return Ok(None);
+        }
+        self.shown = true;
+        Ok(Some(self.frame.clone()))
+    }
+
+    fn seek(&mut self, _timestamp: f64) -> Result<Option<DecodedFrame>, Error> {
+        self.shown = false;
+        self.next_frame()
+    }
+}
+
+/// An animated image, decoded up front. Animations are short and small
+/// once scaled to the decode width, and having every frame at hand makes
+/// seeking free.
+///
+/// The decoder hands over every frame already composited with the ones
+/// before it, following each frame's disposal method; what is left
+/// transparent is drawn over the background here. An animation with
+/// a loop count plays that many times as one clip. One that loops forever
+/// plays once, and repeats as the playlist's repeat mode says, so it can't
+/// hold up the rest of a playlist.
+pub struct AnimatedImage {
+    // Each frame, timestamped within one play.
+    frames: Vec<DecodedFrame>,
+    // Length of one play.
+    duration: f64,
+    plays: u32,
+    // Frames handed out so far, counting every play.
+    position: usize,
+}
+
+impl AnimatedImage {
+    fn decode(
+        path: &Path,
+        frames: Frames<'_>,
+        plays: Option<u32>,
+        min_delay: f64,
+        max_width: u32,
+        background: Background,
+    ) -> Result<Self, Error> {
+        let mut decoded = Vec::new();
+        let mut time = 0.0;
+
+        for frame in frames {
+            let frame = frame.map_err(|e| image_error(path, e))?;
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            let delay = numerator as f64 / denominator.max(1) as f64 / 1000.0;
+            let delay = if delay > 0.0 && delay >= min_delay { delay } else { DEFAULT_FRAME_DELAY };
+
+            let image = DynamicImage::ImageRgba8(frame.into_buffer());
+            decoded.push(to_frame(image, max_width, background, time));
+            time += delay;
+        }
+
+        if decoded.is_empty() {
+            return Err(Error::decode("Failed to decode image", path, "the animation has no frames"));
+        }
+
+        Ok(AnimatedImage {
+            frames: decoded,
+            duration: time,
+            plays: plays.unwrap_or(1).max(1),
+            position: 0,
+        })
+    }
+}
+
+impl FrameSource for AnimatedImage {
+    fn metadata(&self) -> SourceMetadata {
+        let (width, height) = self.frames[0].image.dimensions();
+        SourceMetadata {
+            width,
+            height,
+            frame_rate: self.frames.len() as f64 / self.duration,
+            frame_count: Some(self.frames.len() as u64 * self.plays as u64),
+            duration: Some(self.duration * self.plays as f64),
+        }
+    }
+
+    fn next_frame(&mut self) -> Result<Option<DecodedFrame>, Error> {
+        if self.position >= self.frames.len() * self.plays as usize {
+            return Ok(None);
+        }
+
+        let play = self.position / self.frames.len();
+        let mut frame = self.frames[self.position % self.frames.len()].clone();
+        frame.timestamp += play as f64 * self.duration;
+        self.position += 1;
+        Ok(Some(frame))
+    }
+
+    fn seek(&mut self, timestamp: f64) -> Result<Option<DecodedFrame>, Error> {
+        let timestamp = timestamp.max(0.0);
+        let play = ((timestamp / self.duration) as usize).min(self.plays as usize - 1);
+        let offset = timestamp - play as f64 * self.duration;
+
+        // The last frame that started at or before the target.
+        let index = self.frames
+            .partition_point(|frame| frame.timestamp <= offset)
+            .saturating_sub(1);
+        self.position = play * self.frames.len() + index;
+        self.next_frame()
+    }
+}
+
+/// Numbered image files played as frames at a fixed rate. Each image is
+/// loaded when its frame comes up.
+pub struct ImageSequence {
+    paths: Vec<PathBuf>,
+    frame_rate: f64,
+    max_width: u32,
+    background: Background,
+    size: (u32, u32),
+    position: usize,
+}
+
+impl ImageSequence {
+    /// The files matching the glob `pattern`, ordered by the number in their
+    /// names so `frame_10.png` comes after `frame_9.png`.
+    pub fn open(pattern: &str, frame_rate: f64, max_width: u32, background: Background) -> Result<Self, Error> {
+        let mut paths: Vec<PathBuf> = glob::glob(pattern)
+            .map_err(|e| Error::InvalidInput(format!("Invalid pattern '{}': {}", pattern, e)))?
+            .filter_map(Result::ok)
+            .filter(|path| path.is_file())
+            .collect();
+        paths.sort_by_cached_key(|path| (frame_number(path), path.clone()));
+
+        let first = paths.first()
+            .ok_or_else(|| Error::NotFound(PathBuf::from(pattern)))?;
+        let (width, height) = image::image_dimensions(first).map_err(|e| image_error(first, e))?;
+
+        Ok(ImageSequence {
+            size: scaled_size(width, height, max_width),
+            paths,
+            frame_rate,
+            max_width,
+            background,
+            position: 0,
+        })
+    }
+
+    fn load(&self, index: usize) -> Result<DecodedFrame, Error> {
+        let path = &self.paths[index];
+        let image = image::open(path).map_err(|e| image_error(path, e))?;
+        Ok(to_frame(image, self.max_width, self.background, index as f64 / self.frame_rate))
+    }
+}
+
+impl FrameSource for ImageSequence {
+    fn metadata(&self) -> SourceMetadata {
+        SourceMetadata {
+            width: self.size.0,
+            height: self.size.1,
+            frame_rate: self.frame_rate,
+            frame_count: Some(self.paths.len() as u64),
+            duration: Some(self.paths.len() as f64 / self.frame_rate),
+        }
+    }
+
+    fn next_frame(&mut self) -> Result<Option<DecodedFrame>, Error> {
+        if self.position >= self.paths.len() {
+            return Ok(None);
+        }
+        let frame = self.load(self.position)?;
+        self.position += 1;
+        Ok(Some(frame))
+    }
+
+    fn seek(&mut self, timestamp: f64) -> Result<Option<DecodedFrame>, Error> {
+        // A little slack so rounding doesn't land on the frame before.
+        let index = (timestamp.max(0.0) * self.frame_rate + 1e-6) as usize;
+        self.position = index.min(self.paths.len() - 1);
+        self.next_frame()
+    }
+}
+
+/// The last run of digits in the file name, e.g. 42 for `shot_0042.png`.
+fn frame_number(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_string_lossy();
+    let end = stem.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = stem[..end].trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    stem[start..end].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::{fs, process};
+    use super::*;
+
+    /// An image written to the system temp directory, removed when the test
+    /// is done with it.
+    struct TempImage(PathBuf);
+
+    impl TempImage {
+        fn new(name: &str, bytes: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!("ascii-rendering-{}-{}", process::id(), name));
+            fs::write(&path, bytes).unwrap();
+            TempImage(path)
+        }
+
+        fn open(&self) -> Box<dyn FrameSource> {
+            open(&self.0, 16, Background::default()).unwrap()
+        }
+    }
+
+    impl Drop for TempImage {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// A 2x2 GIF with one frame per delay, given in hundredths of a second.
+    fn gif(delays: &[u16], repeat: gif::Repeat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut bytes, 2, 2, &[0, 0, 0, 255, 255, 255]).unwrap();
+            encoder.set_repeat(repeat).unwrap();
+            for (i, &delay) in delays.iter().enumerate() {
+                let frame = gif::Frame {
+                    width: 2,
+                    height: 2,
+                    delay,
+                    buffer: Cow::Owned(vec![(i % 2) as u8; 4]),
+                    ..gif::Frame::default()
+                };
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+        bytes
+    }
+
+    /// A 2x2 APNG with one frame per delay, given as a fraction of a second.
+    fn apng(delays: &[(u16, u16)], num_plays: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 2, 2);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(delays.len() as u32, num_plays).unwrap();
+            let mut writer = encoder.write_header().unwrap();
+            for (i, &(numerator, denominator)) in delays.iter().enumerate() {
+                writer.set_frame_delay(numerator, denominator).unwrap();
+                writer.write_image_data(&[(i * 100) as u8, 0, 0, 255].repeat(4)).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        bytes
+    }
+
+    fn timestamps(source: &mut dyn FrameSource) -> Vec<f64> {
+        std::iter::from_fn(|| source.next_frame().unwrap())
+            .map(|frame| frame.timestamp)
+            .collect()
+    }
+
+    fn assert_times(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len(), "{:?} vs {:?}", actual, expected);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-9, "{:?} vs {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn gif_delays_below_the_minimum_fall_back_to_the_default() {
+        // 50 ms is kept; 10 ms and a missing delay both become 100 ms.
+        let image = TempImage::new("delays.gif", &gif(&[5, 1, 0], gif::Repeat::Finite(0)));
+        let mut source = image.open();
+
+        let metadata = source.metadata();
+        assert_eq!(metadata.frame_count, Some(3));
+        assert!((metadata.duration.unwrap() - 0.25).abs() < 1e-9);
+        assert_times(&timestamps(source.as_mut()), &[0.0, 0.05, 0.15]);
+    }
+
+    #[test]
+    fn apng_delays_are_kept_however_short() {
+        let image = TempImage::new("delays.png", &apng(&[(1, 100), (0, 100), (1, 4)], 1));
+        let mut source = image.open();
+
+        assert!((source.metadata().duration.unwrap() - 0.36).abs() < 1e-9);
+        assert_times(&timestamps(source.as_mut()), &[0.0, 0.01, 0.11]);
+    }
+
+    #[test]
+    fn gif_loop_count_adds_to_the_first_play() {
+        let image = TempImage::new("loops.gif", &gif(&[10, 20], gif::Repeat::Finite(2)));
+        let mut source = image.open();
+
+        let metadata = source.metadata();
+        assert_eq!(metadata.frame_count, Some(6));
+        assert!((metadata.duration.unwrap() - 0.9).abs() < 1e-9);
+        assert_times(&timestamps(source.as_mut()), &[0.0, 0.1, 0.3, 0.4, 0.6, 0.7]);
+    }
+
+    #[test]
+    fn endless_animations_play_once() {
+        let image = TempImage::new("forever.gif", &gif(&[10, 10], gif::Repeat::Infinite));
+        assert_eq!(image.open().metadata().frame_count, Some(2));
+
+        let image = TempImage::new("forever.png", &apng(&[(1, 10), (1, 10)], 0));
+        assert_eq!(image.open().metadata().frame_count, Some(2));
+    }
+
+    #[test]
+    fn apng_plays_as_often_as_it_asks() {
+        let image = TempImage::new("twice.png", &apng(&[(1, 10), (1, 10)], 2));
+        let mut source = image.open();
+
+        assert_eq!(source.metadata().frame_count, Some(4));
+        assert_times(&timestamps(source.as_mut()), &[0.0, 0.1, 0.2, 0.3]);
+    }
+
+    #[test]
+    fn seeks_into_later_plays() {
+        // Two plays of 0.0, 0.1 and 0.3, each 0.4 s long.
+        let image = TempImage::new("seek.gif", &gif(&[10, 20, 10], gif::Repeat::Finite(1)));
+        let mut source = image.open();
+
+        let seek = |source: &mut Box<dyn FrameSource>, to: f64| source.seek(to).unwrap().unwrap().timestamp;
+        assert_times(&[seek(&mut source, 0.55)], &[0.5]);
+        assert_times(&timestamps(source.as_mut()), &[0.7]);
+        assert_times(&[seek(&mut source, 0.2)], &[0.1]);
+        // Past the end lands on the last frame of the last play.
+        assert_times(&[seek(&mut source, 10.0)], &[0.7]);
+        assert!(source.next_frame().unwrap().is_none());
+        assert_times(&[seek(&mut source, -1.0)], &[0.0]);
+    }
+
+    #[test]
+    fn still_png_is_one_frame() {
+        let mut bytes = Vec::new();
+        image::DynamicImage::ImageRgb8(RgbImage::new(4, 2))
+            .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        let image = TempImage::new("still.png", &bytes);
+        let mut source = image.open();
+
+        assert_eq!(source.metadata().frame_count, Some(1));
+        assert_times(&timestamps(source.as_mut()), &[0.0]);
+    }
+
+    #[test]
+    fn frame_number_is_the_last_run_of_digits() {
+        assert_eq!(frame_number(Path::new("shot_0042.png")), Some(42));
+        assert_eq!(frame_number(Path::new("take2_frame10.png")), Some(10));
+        assert_eq!(frame_number(Path::new("dir9/cover.png")), None);
+
+        let mut paths: Vec<PathBuf> = ["f10.png", "f9.png", "f100.png", "cover.png"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        paths.sort_by_cached_key(|path| (frame_number(path), path.clone()));
+        assert_eq!(paths, ["cover.png", "f9.png", "f10.png", "f100.png"].map(PathBuf::from));
+    }
+}
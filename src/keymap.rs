@@ -0,0 +1,619 @@
+use std::time::{Duration, Instant};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crate::error::Error;
+
+// A multi-key binding has to be finished within this long of its previous
+// key, like Vim's `timeoutlen`.
+const SEQUENCE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Everything the player and the image viewer can be asked to do from the
+/// keyboard or mouse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    TogglePause,
+    ToggleMute,
+    VolumeUp,
+    VolumeDown,
+    SpeedDown,
+    SpeedUp,
+    BackFrames,
+    ForwardFrames,
+    SeekBack,
+    SeekForward,
+    StepBack,
+    StepForward,
+    JumpStart,
+    JumpEnd,
+    GoTo,
+    PrevScene,
+    NextScene,
+    NextAudioTrack,
+    ToggleSubtitles,
+    NextSubtitleTrack,
+    PrevItem,
+    NextItem,
+    CycleRepeat,
+    ToggleShuffle,
+    AbRepeat,
+    ToggleStats,
+    ZoomIn,
+    ZoomOut,
+    ResetZoom,
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+}
+
+impl Action {
+    /// Every action, in the order the controls footer lists them.
+    pub const ALL: [Action; 34] = [
+        Action::Quit,
+        Action::TogglePause,
+        Action::ToggleMute,
+        Action::VolumeUp,
+        Action::VolumeDown,
+        Action::SpeedDown,
+        Action::SpeedUp,
+        Action::BackFrames,
+        Action::ForwardFrames,
+        Action::SeekBack,
+        Action::SeekForward,
+        Action::StepBack,
+        Action::StepForward,
+        Action::JumpStart,
+        Action::JumpEnd,
+        Action::GoTo,
+        Action::PrevScene,
+        Action::NextScene,
+        Action::NextAudioTrack,
+        Action::ToggleSubtitles,
+        Action::NextSubtitleTrack,
+        Action::PrevItem,
+        Action::NextItem,
+        Action::CycleRepeat,
+        Action::ToggleShuffle,
+        Action::AbRepeat,
+        Action::ToggleStats,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::ResetZoom,
+        Action::PanLeft,
+        Action::PanRight,
+        Action::PanUp,
+        Action::PanDown,
+    ];
+
+    /// Name used for the action in the `[keys]` table of the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::TogglePause => "toggle_pause",
+            Action::ToggleMute => "toggle_mute",
+            Action::VolumeUp => "volume_up",
+            Action::VolumeDown => "volume_down",
+            Action::SpeedDown => "speed_down",
+            Action::SpeedUp => "speed_up",
+            Action::BackFrames => "back_frames",
+            Action::ForwardFrames => "forward_frames",
+            Action::SeekBack => "seek_back",
+            Action::SeekForward => "seek_forward",
+            Action::StepBack => "step_back",
+            Action::StepForward => "step_forward",
+            Action::JumpStart => "jump_start",
+            Action::JumpEnd => "jump_end",
+            Action::GoTo => "go_to",
+            Action::PrevScene => "prev_scene",
+            Action::NextScene => "next_scene",
+            Action::NextAudioTrack => "next_audio_track",
+            Action::ToggleSubtitles => "toggle_subtitles",
+            Action::NextSubtitleTrack => "next_subtitle_track",
+            Action::PrevItem => "prev_item",
+            Action::NextItem => "next_item",
+            Action::CycleRepeat => "cycle_repeat",
+            Action::ToggleShuffle => "toggle_shuffle",
+            Action::AbRepeat => "ab_repeat",
+            Action::ToggleStats => "toggle_stats",
+            Action::ZoomIn => "zoom_in",
+            Action::ZoomOut => "zoom_out",
+            Action::ResetZoom => "reset_zoom",
+            Action::PanLeft => "pan_left",
+            Action::PanRight => "pan_right",
+            Action::PanUp => "pan_up",
+            Action::PanDown => "pan_down",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Action::ALL.iter().copied().find(|action| action.name() == name)
+    }
+
+    /// Short description for the controls footer.
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::TogglePause => "Pause/Play",
+            Action::ToggleMute => "Mute/Unmute",
+            Action::VolumeUp => "Volume Up",
+            Action::VolumeDown => "Volume Down",
+            Action::SpeedDown => "Slower",
+            Action::SpeedUp => "Faster",
+            Action::BackFrames => "Back 10 Frames",
+            Action::ForwardFrames => "Forward 10 Frames",
+            Action::SeekBack => "Back 10s",
+            Action::SeekForward => "Forward 10s",
+            Action::StepBack => "Step Back",
+            Action::StepForward => "Step Forward",
+            Action::JumpStart => "Start",
+            Action::JumpEnd => "End",
+            Action::GoTo => "Go To",
+            Action::PrevScene => "Prev Scene",
+            Action::NextScene => "Next Scene",
+            Action::NextAudioTrack => "Audio Track",
+            Action::ToggleSubtitles => "Subtitles",
+            Action::NextSubtitleTrack => "Subtitle Track",
+            Action::PrevItem => "Prev Item",
+            Action::NextItem => "Next Item",
+            Action::CycleRepeat => "Repeat",
+            Action::ToggleShuffle => "Shuffle",
+            Action::AbRepeat => "A-B Repeat",
+            Action::ToggleStats => "Stats",
+            Action::ZoomIn => "Zoom In",
+            Action::ZoomOut => "Zoom Out",
+            Action::ResetZoom => "Fit",
+            Action::PanLeft => "Pan Left",
+            Action::PanRight => "Pan Right",
+            Action::PanUp => "Pan Up",
+            Action::PanDown => "Pan Down",
+        }
+    }
+
+    /// Whether the video player responds to the action.
+    pub fn in_player(self) -> bool {
+        !matches!(
+            self,
+            Action::ZoomIn | Action::ZoomOut | Action::ResetZoom
+                | Action::PanLeft | Action::PanRight | Action::PanUp | Action::PanDown
+        )
+    }
+
+    /// Whether the image viewer responds to the action.
+    pub fn in_viewer(self) -> bool {
+        matches!(
+            self,
+            Action::Quit | Action::PrevItem | Action::NextItem
+                | Action::ZoomIn | Action::ZoomOut | Action::ResetZoom
+                | Action::PanLeft | Action::PanRight | Action::PanUp | Action::PanDown
+        )
+    }
+
+    /// Built-in bindings, in the same notation as the config file. Vim's
+    /// motion keys work alongside the arrow and paging keys. The player and
+    /// the viewer have their own bindings, so the same keys can mean
+    /// different things in each.
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Action::Quit => &["q", "ctrl+c", "esc"],
+            Action::TogglePause => &["p", "space"],
+            Action::ToggleMute => &["m"],
+            Action::VolumeUp => &["+", "="],
+            Action::VolumeDown => &["-"],
+            Action::SpeedDown => &["left"],
+            Action::SpeedUp => &["right"],
+            Action::BackFrames => &["up", "k"],
+            Action::ForwardFrames => &["down", "j"],
+            Action::SeekBack => &["pageup", "h"],
+            Action::SeekForward => &["pagedown", "l"],
+            Action::StepBack => &[","],
+            Action::StepForward => &["."],
+            Action::JumpStart => &["home", "g g"],
+            Action::JumpEnd => &["end", "G"],
+            Action::GoTo => &[":"],
+            Action::PrevScene => &["["],
+            Action::NextScene => &["]"],
+            Action::NextAudioTrack => &["a"],
+            Action::ToggleSubtitles => &["s"],
+            Action::NextSubtitleTrack => &["c"],
+            Action::PrevItem => &["<"],
+            Action::NextItem => &[">"],
+            Action::CycleRepeat => &["r"],
+            Action::ToggleShuffle => &["z"],
+            Action::AbRepeat => &["L"],
+            Action::ToggleStats => &["i"],
+            Action::ZoomIn => &["+", "="],
+            Action::ZoomOut => &["-"],
+            Action::ResetZoom => &["0"],
+            Action::PanLeft => &["left", "h"],
+            Action::PanRight => &["right", "l"],
+            Action::PanUp => &["up", "k"],
+            Action::PanDown => &["down", "j"],
+        }
+    }
+}
+
+/// A key together with the modifiers held down, e.g. Ctrl+C.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyPress {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyPress {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        // Shift is already in the character itself ('G' rather than 'g').
+        if let KeyCode::Char(_) = code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        KeyPress { code, modifiers }
+    }
+
+    pub fn from_event(event: &KeyEvent) -> Self {
+        KeyPress::new(event.code, event.modifiers)
+    }
+
+    /// Parses a key such as `q`, `G`, `space`, `pageup`, `ctrl+c` or
+    /// `shift+left`.
+    pub fn parse(spec: &str) -> Option<Self> {
+        // `+` is both the separator and a key of its own.
+        let parts: Vec<&str> = spec.split('+').collect();
+        let (modifier_names, key) = if spec.ends_with('+') {
+            (&parts[..parts.len().saturating_sub(2)], "+")
+        } else {
+            (&parts[..parts.len() - 1], parts[parts.len() - 1])
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for name in modifier_names {
+            modifiers |= match name.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return None,
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::Char(c.to_ascii_uppercase()),
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" | "ins" => KeyCode::Insert,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" | "pgup" => KeyCode::PageUp,
+                "pagedown" | "pgdn" => KeyCode::PageDown,
+                name => KeyCode::F(name.strip_prefix('f')?.parse().ok().filter(|n| (1..=12).contains(n))?),
+            },
+        };
+
+        Some(KeyPress::new(code, modifiers))
+    }
+
+    /// How the key is shown in the controls footer.
+    fn label(&self) -> String {
+        let key = match self.code {
+            KeyCode::Char(' ') => String::from("Space"),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::Left => String::from("←"),
+            KeyCode::Right => String::from("→"),
+            KeyCode::Up => String::from("↑"),
+            KeyCode::Down => String::from("↓"),
+            KeyCode::PageUp => String::from("PgUp"),
+            KeyCode::PageDown => String::from("PgDn"),
+            KeyCode::F(n) => format!("F{}", n),
+            code => format!("{:?}", code),
+        };
+
+        let mut label = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            label.push_str("Ctrl+");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            label.push_str("Alt+");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            label.push_str("Shift+");
+        }
+        label + &key
+    }
+}
+
+/// Parses a binding: one key, or several separated by spaces for a
+/// sequence such as `g g`.
+fn parse_binding(spec: &str) -> Option<Vec<KeyPress>> {
+    let keys: Option<Vec<KeyPress>> = spec.split_whitespace().map(KeyPress::parse).collect();
+    keys.filter(|keys| !keys.is_empty())
+}
+
+/// Which key sequences trigger which actions.
+#[derive(Clone)]
+pub struct Keymap {
+    bindings: Vec<(Vec<KeyPress>, Action)>,
+}
+
+impl Keymap {
+    pub fn defaults() -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .flat_map(|&action| {
+                action.default_keys().iter().map(move |spec| {
+                    (parse_binding(spec).expect("default bindings are valid"), action)
+                })
+            })
+            .collect();
+
+        Keymap { bindings }
+    }
+
+    /// The default bindings with the config file's `[keys]` table applied.
+    /// Each entry replaces all default keys of one action and takes a key
+    /// or a list of keys, e.g. `toggle_pause = ["p", "space"]`; an empty
+    /// list unbinds the action.
+    pub fn from_config(config: &toml::Table) -> Result<Self, Error> {
+        let mut keymap = Keymap::defaults();
+        let keys = match config.get("keys") {
+            Some(keys) => keys.as_table().ok_or_else(|| invalid("[keys] must be a table"))?,
+            None => return Ok(keymap),
+        };
+
+        for (name, value) in keys {
+            let action = Action::parse(name)
+                .ok_or_else(|| invalid(&format!("Unknown action '{}' in [keys]", name)))?;
+
+            let specs: Vec<&str> = match value {
+                toml::Value::String(spec) => vec![spec.as_str()],
+                toml::Value::Array(specs) => specs
+                    .iter()
+                    .map(|spec| spec.as_str().ok_or_else(|| invalid(&format!("Keys for '{}' must be strings", name))))
+                    .collect::<Result<_, _>>()?,
+                _ => return Err(invalid(&format!("Keys for '{}' must be a string or a list", name))),
+            };
+
+            keymap.bindings.retain(|(_, bound)| *bound != action);
+            for spec in specs {
+                let keys = parse_binding(spec)
+                    .ok_or_else(|| invalid(&format!("Invalid key '{}' for '{}'", spec, name)))?;
+                keymap.bindings.push((keys, action));
+            }
+        }
+
+        Ok(keymap)
+    }
+
+    /// The bindings of the actions the video player responds to.
+    pub fn player(&self) -> Keymap {
+        self.only(Action::in_player)
+    }
+
+    /// The bindings of the actions the image viewer responds to.
+    pub fn viewer(&self) -> Keymap {
+        self.only(Action::in_viewer)
+    }
+
+    fn only(&self, keep: fn(Action) -> bool) -> Keymap {
+        Keymap {
+            bindings: self.bindings.iter().filter(|(_, action)| keep(*action)).cloned().collect(),
+        }
+    }
+
+    /// The controls line shown under the player or viewer, listing every
+    /// bound action with its keys.
+    pub fn footer(&self) -> String {
+        let entries: Vec<String> = Action::ALL
+            .iter()
+            .filter_map(|&action| {
+                let keys: Vec<String> = self.bindings
+                    .iter()
+                    .filter(|(_, bound)| *bound == action)
+                    .map(|(keys, _)| keys.iter().map(KeyPress::label).collect::<Vec<_>>().join(" "))
+                    .collect();
+                if keys.is_empty() {
+                    None
+                } else {
+                    Some(format!("{} - {}", keys.join("/"), action.label()))
+                }
+            })
+            .collect();
+
+        format!("Controls: {}", entries.join(" | "))
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidConfig(message.to_string())
+}
+
+/// Collects key presses into the bound sequences they form.
+#[derive(Default)]
+pub struct KeySequence {
+    pending: Vec<KeyPress>,
+    last_key: Option<Instant>,
+}
+
+impl KeySequence {
+    pub fn new() -> Self {
+        KeySequence::default()
+    }
+
+    /// Adds a key press and returns the action it completes, if any. While
+    /// the keys so far are the start of a longer binding, nothing happens
+    /// yet.
+    pub fn feed(&mut self, keymap: &Keymap, key: KeyPress) -> Option<Action> {
+        if self.last_key.map(|time| time.elapsed() > SEQUENCE_TIMEOUT).unwrap_or(false) {
+            self.pending.clear();
+        }
+        self.last_key = Some(Instant::now());
+        self.pending.push(key);
+
+        loop {
+            let is_prefix = keymap.bindings
+                .iter()
+                .any(|(keys, _)| keys.len() > self.pending.len() && keys.starts_with(&self.pending));
+            if is_prefix {
+                return None;
+            }
+
+            let action = keymap.bindings
+                .iter()
+                .find(|(keys, _)| *keys == self.pending)
+                .map(|(_, action)| *action);
+            if action.is_some() || self.pending.len() == 1 {
+                self.pending.clear();
+                return action;
+            }
+
+            // The sequence went nowhere; the last key may still start one.
+            self.pending = vec![key];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(spec: &str) -> KeyPress {
+        KeyPress::parse(spec).unwrap()
+    }
+
+    fn keymap(config: &str) -> Result<Keymap, Error> {
+        Keymap::from_config(&config.parse::<toml::Table>().unwrap())
+    }
+
+    #[test]
+    fn parses_plain_and_named_keys() {
+        assert_eq!(key("q"), KeyPress::new(KeyCode::Char('q'), KeyModifiers::NONE));
+        assert_eq!(key("space"), KeyPress::new(KeyCode::Char(' '), KeyModifiers::NONE));
+        assert_eq!(key("PageUp"), KeyPress::new(KeyCode::PageUp, KeyModifiers::NONE));
+        assert_eq!(key("f12"), KeyPress::new(KeyCode::F(12), KeyModifiers::NONE));
+        assert_eq!(key("+"), KeyPress::new(KeyCode::Char('+'), KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn parses_modifiers() {
+        assert_eq!(key("ctrl+c"), KeyPress::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert_eq!(key("Alt+Shift+left"), KeyPress::new(KeyCode::Left, KeyModifiers::ALT | KeyModifiers::SHIFT));
+        assert_eq!(key("ctrl++"), KeyPress::new(KeyCode::Char('+'), KeyModifiers::CONTROL));
+    }
+
+    #[test]
+    fn shift_is_part_of_the_character() {
+        assert_eq!(key("shift+g"), key("G"));
+        let event = KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT);
+        assert_eq!(KeyPress::from_event(&event), key("G"));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert_eq!(KeyPress::parse("hyper+x"), None);
+        assert_eq!(KeyPress::parse("f13"), None);
+        assert_eq!(KeyPress::parse("nope"), None);
+        assert_eq!(KeyPress::parse(""), None);
+        assert_eq!(parse_binding("  "), None);
+    }
+
+    #[test]
+    fn labels_keys_for_the_footer() {
+        assert_eq!(key("ctrl+c").label(), "Ctrl+c");
+        assert_eq!(key("space").label(), "Space");
+        assert_eq!(key("shift+left").label(), "Shift+←");
+        assert_eq!(key("pagedown").label(), "PgDn");
+    }
+
+    #[test]
+    fn action_names_round_trip() {
+        for action in Action::ALL {
+            assert_eq!(Action::parse(action.name()), Some(action));
+        }
+        assert_eq!(Action::parse("dance"), None);
+    }
+
+    #[test]
+    fn default_bindings_resolve() {
+        let keymap = Keymap::defaults().player();
+        let mut sequence = KeySequence::new();
+        assert_eq!(sequence.feed(&keymap, key("space")), Some(Action::TogglePause));
+        assert_eq!(sequence.feed(&keymap, key("ctrl+c")), Some(Action::Quit));
+        assert_eq!(sequence.feed(&keymap, key("x")), None);
+    }
+
+    #[test]
+    fn sequences_wait_for_their_last_key() {
+        let keymap = Keymap::defaults().player();
+        let mut sequence = KeySequence::new();
+        assert_eq!(sequence.feed(&keymap, key("g")), None);
+        assert_eq!(sequence.feed(&keymap, key("g")), Some(Action::JumpStart));
+    }
+
+    #[test]
+    fn broken_sequence_retries_last_key() {
+        let keymap = Keymap::defaults().player();
+        let mut sequence = KeySequence::new();
+        assert_eq!(sequence.feed(&keymap, key("g")), None);
+        assert_eq!(sequence.feed(&keymap, key("p")), Some(Action::TogglePause));
+        assert_eq!(sequence.feed(&keymap, key("g")), None);
+        assert_eq!(sequence.feed(&keymap, key("x")), None);
+        assert_eq!(sequence.feed(&keymap, key("g")), None);
+        assert_eq!(sequence.feed(&keymap, key("g")), Some(Action::JumpStart));
+    }
+
+    #[test]
+    fn config_replaces_and_unbinds() {
+        let keymap = keymap("[keys]\ntoggle_pause = \"x\"\nquit = [\"ctrl+q\", \"z z\"]\ntoggle_stats = []\n").unwrap().player();
+        let mut sequence = KeySequence::new();
+
+        assert_eq!(sequence.feed(&keymap, key("x")), Some(Action::TogglePause));
+        assert_eq!(sequence.feed(&keymap, key("space")), None);
+        assert_eq!(sequence.feed(&keymap, key("ctrl+q")), Some(Action::Quit));
+        assert_eq!(sequence.feed(&keymap, key("q")), None);
+        assert_eq!(sequence.feed(&keymap, key("z")), None);
+        assert_eq!(sequence.feed(&keymap, key("z")), Some(Action::Quit));
+        assert_eq!(sequence.feed(&keymap, key("i")), None);
+        assert!(!keymap.footer().contains("Stats"));
+    }
+
+    #[test]
+    fn config_errors_name_the_problem() {
+        let message = |config: &str| match keymap(config) {
+            Err(Error::InvalidConfig(message)) => message,
+            _ => panic!("expected an invalid config error for {:?}", config),
+        };
+
+        assert!(message("keys = 1").contains("[keys]"));
+        assert!(message("[keys]\ndance = \"d\"").contains("dance"));
+        assert!(message("[keys]\nquit = 1").contains("quit"));
+        assert!(message("[keys]\nquit = [1]").contains("quit"));
+        assert!(message("[keys]\nquit = \"hyper+q\"").contains("hyper+q"));
+    }
+
+    #[test]
+    fn footer_lists_keys_in_action_order() {
+        let footer = keymap("[keys]\nquit = \"q\"\ntoggle_pause = [\"p\", \"space\"]\n").unwrap().player().footer();
+        assert!(footer.starts_with("Controls: q - Quit | p/Space - Pause/Play | m - Mute/Unmute"));
+    }
+
+    #[test]
+    fn player_and_viewer_share_keys_for_different_actions() {
+        let keymap = Keymap::defaults();
+        let (player, viewer) = (keymap.player(), keymap.viewer());
+
+        assert_eq!(KeySequence::new().feed(&player, key("h")), Some(Action::SeekBack));
+        assert_eq!(KeySequence::new().feed(&viewer, key("h")), Some(Action::PanLeft));
+        assert_eq!(KeySequence::new().feed(&viewer, key("esc")), Some(Action::Quit));
+        assert_eq!(KeySequence::new().feed(&viewer, key("p")), None);
+        assert!(viewer.footer().contains("0 - Fit"));
+        assert!(!player.footer().contains("Pan Left"));
+    }
+}
@@ -0,0 +1,48 @@
+//! Video to ASCII art rendering and terminal playback.
+//!
+//! The pipeline is split into four parts that can be used on their own:
+//! [`Probe`] reads a video's metadata, a [`FrameSource`] decodes frames from
+//! a video, image, image sequence or stdin, a [`Renderer`] turns frames into
+//! cell grids in one of several styles and a [`Player`] plays them in the terminal with audio and
+//! subtitles. Still images can skip the player and go to an [`ImageViewer`],
+//! and a [`Server`] streams to telnet clients instead of the terminal.
+
+mod audio_stream;
+pub mod background;
+pub mod cells;
+pub mod config;
+pub mod contact_sheet;
+pub mod error;
+mod frame_decoder;
+pub mod frame_diff;
+pub mod frame_source;
+mod frame_stream;
+pub mod image_source;
+pub mod keymap;
+mod playback_stats;
+pub mod player;
+pub mod playlist;
+pub mod probe;
+pub mod raw_input;
+pub mod raw_output;
+pub mod renderer;
+pub mod scene_detection;
+pub mod server;
+pub mod subtitles;
+mod time_stretch;
+pub mod timecode;
+pub mod viewer;
+
+pub use background::Background;
+pub use cells::{AsciiFrame, Cell, CellGrid, ColorMode, Rgb};
+pub use error::Error;
+pub use frame_diff::FrameDiffer;
+pub use frame_source::{DecodedFrame, FrameSource, FrameSourceBuilder, SourceMetadata};
+pub use player::{PlaybackEnd, PlaybackSummary, Player, PlayerBuilder};
+pub use probe::{MediaInfo, Probe};
+pub use raw_output::RawFrameWriter;
+pub use renderer::{
+    BrailleRenderer, EdgeRenderer, HalfBlockRenderer, RampRenderer, RenderStyle, Renderer, ShapeRenderer,
+};
+pub use server::Server;
+pub use viewer::{ImageViewer, Viewport};
@@ -1,62 +1,601 @@
-mod video_extraction;
-
-use std::io::{self, Write, BufRead};
+use std::io::{self, Write, BufRead, IsTerminal};
 use std::env;
-use video_extraction::VideoExtractor;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use ascii_rendering::config::{self, Settings};
+use ascii_rendering::contact_sheet::{sample_timestamps, ContactSheet, SheetFormat};
+use ascii_rendering::keymap::Keymap;
+use ascii_rendering::playlist::{expand_inputs, Playlist, RepeatMode};
+use ascii_rendering::scene_detection::{detect_scenes, write_scenes_csv, write_scenes_json, DEFAULT_SCENE_THRESHOLD};
+use ascii_rendering::subtitles::{self, SubtitlePosition, SubtitleTrack};
+use ascii_rendering::{
+    Background, ColorMode, Error, FrameSourceBuilder, ImageViewer, MediaInfo, PlaybackEnd, Player, RenderStyle, Server,
+};
+
+fn parse_flag_value<T: std::str::FromStr>(args: &[String], i: usize, flag: &str) -> Option<T> {
+    match args.get(i + 1).map(|value| value.parse::<T>()) {
+        Some(Ok(value)) => Some(value),
+        _ => {
+            eprintln!("Ignoring invalid or missing value for {}", flag);
+            None
+        }
+    }
+}
+
+/// Parses a `WIDTHxHEIGHT` size such as `640x360`.
+fn parse_size(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.split_once(['x', 'X'])?;
+    Some((width.parse().ok()?, height.parse().ok()?)).filter(|(width, height)| *width > 0 && *height > 0)
+}
+
+/// The address `--serve` listens on: a bare port is only reachable from
+/// this machine, `0.0.0.0:PORT` from anywhere.
+fn listen_address(value: &str) -> String {
+    match value.parse::<u16>() {
+        Ok(port) => format!("127.0.0.1:{}", port),
+        Err(_) => value.to_string(),
+    }
+}
+
+/// How playlist items are turned into frame sources: `--sequence` and
+/// `--stdin-rgb` items are recognised by their pattern and `-`, everything
+/// else is an image or a video file.
+struct SourceOptions {
+    sequence: Option<String>,
+    stdin_size: Option<(u32, u32)>,
+    frame_rate: Option<f64>,
+    background: Background,
+}
+
+impl SourceOptions {
+    fn source_for(&self, path: &Path) -> FrameSourceBuilder {
+        let source = match (&self.sequence, self.stdin_size) {
+            (_, Some((width, height))) if path == Path::new("-") => FrameSourceBuilder::stdin_rgb(width, height),
+            (Some(pattern), _) if path == Path::new(pattern) => FrameSourceBuilder::sequence(pattern),
+            _ => FrameSourceBuilder::detect(path),
+        };
+        let source = source.background(self.background);
+
+        match self.frame_rate {
+            Some(fps) => source.frame_rate(fps),
+            None => source,
+        }
+    }
+
+    /// Opens the playlist item at `path` and reads its metadata.
+    fn open(&self, path: &Path) -> Result<(FrameSourceBuilder, MediaInfo), Error> {
+        let source = self.source_for(path);
+        let info = source.probe()?;
+        Ok((source, info))
+    }
+}
+
+/// Whether the item is a single still image, which is shown in the image
+/// viewer instead of the player.
+fn is_still_image(source: &FrameSourceBuilder, info: &MediaInfo) -> bool {
+    source.is_image() && info.frame_count == Some(1)
+}
 
-fn main() -> Result<(), std::io::Error> {
-    let args: Vec<String> = env::args().collect();
-    let mut video_path = "input_video.mp4";
-    let mut audio_enabled = false;
+/// The audio track to play, by number or else by language. Falls back to
+/// the default track with a warning when the requested one doesn't exist.
+fn audio_track_index(info: &MediaInfo, number: Option<usize>, language: Option<&str>) -> usize {
+    // An explicit track number wins over a language.
+    let selection = match (number, language) {
+        (Some(number), _) => info.audio_track_number(number),
+        (None, Some(language)) => info.audio_track_language(language),
+        (None, None) => return 0,
+    };
+
+    selection.unwrap_or_else(|e| {
+        eprintln!("Warning: {}, using the default audio track", e);
+        0
+    })
+}
+
+/// Like `audio_track_index`, for the subtitle track shown first.
+fn subtitle_track_index(tracks: &[SubtitleTrack], number: Option<usize>, language: Option<&str>) -> usize {
+    let selection = match (number, language) {
+        (Some(number), _) if number == 0 || number > tracks.len() => {
+            Err(format!("Subtitle track {} does not exist ({} available)", number, tracks.len()))
+        },
+        (Some(number), _) => Ok(number - 1),
+        (None, Some(language)) => subtitles::find_language(tracks, language)
+            .ok_or_else(|| format!("No subtitle track with language '{}'", language)),
+        (None, None) => return 0,
+    };
+
+    selection.unwrap_or_else(|e| {
+        eprintln!("Warning: {}, using the first subtitle track", e);
+        0
+    })
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            report(&e);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+/// Prints `e` with the chain of errors that caused it and, when there is
+/// one, a hint on what to do about it.
+fn report(e: &Error) {
+    eprintln!("Error: {}", e);
+    let mut source = std::error::Error::source(e);
+    while let Some(cause) = source {
+        eprintln!("  Caused by: {}", cause);
+        source = cause.source();
+    }
+    if let Some(hint) = e.hint() {
+        eprintln!("Hint: {}", hint);
+    }
+}
+
+fn run() -> Result<(), Error> {
+    let mut args: Vec<String> = env::args().collect();
+
+    // `config dump` prints the settings the other arguments would result in.
+    let dump_config = args.get(1).map(String::as_str) == Some("config") && args.get(2).map(String::as_str) == Some("dump");
+    if dump_config {
+        args.drain(1..3);
+    }
+
+    // A broken config file shouldn't stop playback; the built-in defaults
+    // still work.
+    let config = config::load_config().unwrap_or_else(|e| {
+        eprintln!("Warning: {}, using the built-in defaults", e);
+        toml::Table::new()
+    });
+    // The preset has to be known before the other flags are applied on top.
+    let preset = args.iter().position(|arg| arg == "--preset").and_then(|i| args.get(i + 1)).cloned();
+    let mut settings = Settings::from_config(&config, preset.as_deref())?;
+
+    let mut inputs: Vec<String> = Vec::new();
+    let mut raw_mode = false;
+    let mut print_mode = false;
+    let mut width_flag: Option<u32> = None;
+    let mut height_flag: Option<u32> = None;
+    let mut sheet_count: Option<usize> = None;
+    let mut sheet_scenes = false;
+    let mut sheet_format = SheetFormat::Text;
+    let mut sheet_columns: Option<usize> = None;
+    let mut output_path: Option<String> = None;
+    let mut scenes_path: Option<String> = None;
+    let mut subtitle_file: Option<String> = None;
+    let mut audio_track: Option<usize> = None;
+    let mut audio_lang: Option<String> = None;
+    let mut subtitle_track: Option<usize> = None;
+    let mut subtitle_lang: Option<String> = None;
+    let mut loop_count: Option<u32> = None;
+    let mut sequence: Option<String> = None;
+    let mut stdin_size: Option<(u32, u32)> = None;
+    let mut frame_rate: Option<f64> = None;
+    let mut serve_address: Option<String> = None;
+    let mut max_clients: Option<usize> = None;
 
     let mut i = 1;
     while i < args.len() {
         match args[i].as_str() {
             "--input" => {
                 if i + 1 < args.len() {
-                    video_path = &args[i + 1];
+                    inputs.push(args[i + 1].clone());
                     i += 1;
                 }
             },
             "--audio" => {
-                audio_enabled = true;
+                settings.audio = true;
+            },
+            "--raw" => {
+                raw_mode = true;
+            },
+            "--print" => {
+                print_mode = true;
+            },
+            "--preset" => {
+                // Already applied.
+                i += 1;
+            },
+            "--width" => {
+                width_flag = parse_flag_value(&args, i, "--width");
+                i += 1;
+            },
+            "--height" => {
+                height_flag = parse_flag_value(&args, i, "--height");
+                i += 1;
+            },
+            "--max-fps" => {
+                if let Some(fps) = parse_flag_value::<f64>(&args, i, "--max-fps") {
+                    settings.max_fps = Some(fps).filter(|fps| *fps > 0.0);
+                }
+                i += 1;
+            },
+            // Older versions took a frame delay; it now caps the frame rate.
+            "--delay" => {
+                if let Some(delay) = parse_flag_value::<u64>(&args, i, "--delay").filter(|delay| *delay > 0) {
+                    settings.max_fps = Some(1000.0 / delay as f64);
+                }
+                i += 1;
+            },
+            "--invert" => {
+                settings.invert = Some(true);
+            },
+            "--color" => {
+                match args.get(i + 1).and_then(|name| ColorMode::parse(name)) {
+                    Some(mode) => settings.color = mode,
+                    None => eprintln!("Unknown color mode, expected mono, 256 or truecolor"),
+                }
+                i += 1;
+            },
+            "--style" => {
+                match args.get(i + 1).and_then(|name| RenderStyle::parse(name)) {
+                    Some(style) => settings.style = style,
+                    None => eprintln!("Unknown style, expected ramp, blocks, braille, edges or shapes"),
+                }
+                i += 1;
+            },
+            "--background" => {
+                match args.get(i + 1).and_then(|value| Background::parse(value)) {
+                    Some(background) => settings.background = background,
+                    None => eprintln!("Invalid background, expected e.g. #202020, black, white, checkerboard or terminal"),
+                }
+                i += 1;
+            },
+            "--contact-sheet" => {
+                sheet_count = parse_flag_value(&args, i, "--contact-sheet");
+                i += 1;
+            },
+            "--scenes" => {
+                sheet_scenes = true;
+            },
+            "--sheet-format" => {
+                match args.get(i + 1).and_then(|name| SheetFormat::parse(name)) {
+                    Some(format) => sheet_format = format,
+                    None => eprintln!("Unknown sheet format, expected text, ansi or html"),
+                }
+                i += 1;
+            },
+            "--columns" => {
+                sheet_columns = parse_flag_value(&args, i, "--columns");
+                i += 1;
+            },
+            "--subtitles" => {
+                subtitle_file = args.get(i + 1).cloned();
+                i += 1;
+            },
+            "--no-subtitles" => {
+                settings.subtitles = false;
+            },
+            "--subtitle-position" => {
+                match args.get(i + 1).and_then(|name| SubtitlePosition::parse(name)) {
+                    Some(position) => settings.subtitle_position = position,
+                    None => eprintln!("Unknown subtitle position, expected below or overlay"),
+                }
+                i += 1;
+            },
+            "--audio-track" => {
+                audio_track = parse_flag_value(&args, i, "--audio-track");
+                i += 1;
+            },
+            "--audio-lang" => {
+                audio_lang = args.get(i + 1).cloned();
+                i += 1;
+            },
+            "--subtitle-track" => {
+                subtitle_track = parse_flag_value(&args, i, "--subtitle-track");
+                i += 1;
+            },
+            "--subtitle-lang" => {
+                subtitle_lang = args.get(i + 1).cloned();
+                i += 1;
+            },
+            "--speed" => {
+                match parse_flag_value::<f64>(&args, i, "--speed") {
+                    Some(rate) if rate > 0.0 => settings.speed = rate,
+                    Some(_) => eprintln!("Ignoring non-positive --speed"),
+                    None => {},
+                }
+                i += 1;
+            },
+            "--no-pitch-correction" => {
+                settings.pitch_correction = false;
+            },
+            "--shuffle" => {
+                settings.shuffle = true;
+            },
+            "--repeat" => {
+                match args.get(i + 1).and_then(|name| RepeatMode::parse(name)) {
+                    Some(mode) => settings.repeat = mode,
+                    None => eprintln!("Unknown repeat mode, expected off, all or one"),
+                }
+                i += 1;
+            },
+            "--once" => {
+                settings.repeat = RepeatMode::Off;
+            },
+            "--loop" => {
+                loop_count = parse_flag_value::<u32>(&args, i, "--loop").filter(|count| *count > 0);
+                i += 1;
+            },
+            "--sequence" => {
+                sequence = args.get(i + 1).cloned();
+                i += 1;
+            },
+            "--stdin-rgb" => {
+                stdin_size = args.get(i + 1).and_then(|value| parse_size(value));
+                // Anything that isn't a size is left for the next round,
+                // so a missing size doesn't swallow an input or a flag.
+                match stdin_size {
+                    Some(_) => i += 1,
+                    None => eprintln!("Ignoring invalid or missing size for --stdin-rgb, expected WIDTHxHEIGHT"),
+                }
+            },
+            "--fps" => {
+                frame_rate = parse_flag_value::<f64>(&args, i, "--fps").filter(|fps| *fps > 0.0);
+                i += 1;
+            },
+            "--export-scenes" => {
+                scenes_path = args.get(i + 1).cloned();
+                i += 1;
+            },
+            "--serve" => {
+                serve_address = args.get(i + 1).map(|value| listen_address(value));
+                if serve_address.is_none() {
+                    eprintln!("Ignoring --serve without a port or address");
+                }
+                i += 1;
+            },
+            "--max-clients" => {
+                max_clients = parse_flag_value::<usize>(&args, i, "--max-clients").filter(|max| *max > 0);
+                i += 1;
+            },
+            "--output" => {
+                output_path = args.get(i + 1).cloned();
+                i += 1;
             },
             _ => {
                 if !args[i].starts_with("--") {
-                    video_path = &args[i];
+                    inputs.push(args[i].clone());
                 }
             }
         }
         i += 1;
     }
 
-    let mut extractor = VideoExtractor::new(video_path, audio_enabled)?;
+    // Contact sheets size their thumbnails from the flags alone; the
+    // configured size is meant for playback.
+    let (thumb_width, thumb_height) = (width_flag.unwrap_or(32), height_flag.unwrap_or(12));
+    settings.width = width_flag.or(settings.width);
+    settings.height = height_flag.or(settings.height);
 
-    match extractor.load_metadata() {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error loading video metadata: {}", e);
-            eprintln!("Make sure FFmpeg is installed and the video file exists.");
-            return Err(e);
+    if dump_config {
+        if let Some(name) = &preset {
+            println!("# Effective settings with preset '{}'", name);
+        }
+        print!("{}", settings.to_toml());
+        return Ok(());
+    }
+
+    if inputs.is_empty() && sequence.is_none() {
+        inputs.push(String::from(if stdin_size.is_some() { "-" } else { "input_video.mp4" }));
+    }
+    let mut items = expand_inputs(&inputs)?;
+    // The pattern is one item; expanding it would make every image its own.
+    if let Some(pattern) = &sequence {
+        items.push(PathBuf::from(pattern));
+    }
+    if items.is_empty() {
+        eprintln!("No video files found in {}", inputs.join(", "));
+        return Ok(());
+    }
+    let mut playlist = Playlist::new(items, settings.repeat);
+    if settings.shuffle {
+        playlist.set_shuffle(true);
+    }
+    if let Some(count) = loop_count {
+        playlist.set_loop_count(count);
+    }
+    // Stdin can only be read through once.
+    let reads_stdin = stdin_size.is_some() && inputs.iter().any(|input| input == "-");
+    if reads_stdin {
+        playlist.set_repeat(RepeatMode::Off);
+    }
+
+    let sources = SourceOptions { sequence, stdin_size, frame_rate, background: settings.background };
+    // Scene export and contact sheets work on a single item, the first one.
+    let (mut source, mut info) = sources.open(playlist.current())?;
+
+    if let Some(path) = &scenes_path {
+        eprintln!("Detecting scene changes...");
+        let cuts = detect_scenes(&source, DEFAULT_SCENE_THRESHOLD)?;
+
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        if path.to_lowercase().ends_with(".csv") {
+            write_scenes_csv(&mut file, &cuts)?;
+        } else {
+            write_scenes_json(&mut file, &cuts)?;
+        }
+        file.flush()?;
+
+        eprintln!("Wrote {} scene changes to {}", cuts.len(), path);
+        return Ok(());
+    }
+
+    if let Some(count) = sheet_count {
+        let renderer = settings.style.renderer(settings.invert.unwrap_or(false));
+
+        let columns = sheet_columns.unwrap_or_else(|| {
+            let total_width = if io::stdout().is_terminal() {
+                crossterm::terminal::size().map(|(w, _)| w as usize).unwrap_or(80)
+            } else {
+                80
+            };
+            ContactSheet::columns_for_width(total_width, thumb_width as usize)
+        });
+
+        eprintln!("Sampling {} frames...", count);
+        let timestamps = sample_timestamps(&source, &info, count, sheet_scenes)?;
+        let frames = source.open()?.render_frames(&timestamps, renderer.as_ref(), thumb_width, thumb_height)?;
+        let sheet = ContactSheet::new(frames, columns).render(sheet_format);
+
+        match &output_path {
+            Some(path) => fs::write(path, sheet)?,
+            None => io::stdout().write_all(sheet.as_bytes())?,
+        }
+        return Ok(());
+    }
+
+    if let Some(address) = &serve_address {
+        if playlist.len() > 1 {
+            eprintln!("Warning: --serve streams only {}", source.name());
+        }
+        if settings.audio {
+            eprintln!("Warning: audio is not sent to --serve clients");
+        }
+
+        let renderer = settings.style.renderer(settings.invert.unwrap_or(false));
+        let mut server = Server::new(source, renderer)
+            .color_mode(settings.color)
+            .speed(settings.speed)
+            .max_fps(settings.max_fps);
+        if let Some(max_clients) = max_clients {
+            server = server.max_clients(max_clients);
+        }
+        return server.run(address);
+    }
+
+    // Printing to a file or a pipe is the only thing a still image can do
+    // without a terminal.
+    if !raw_mode && !io::stdout().is_terminal() && is_still_image(&source, &info) {
+        print_mode = true;
+    }
+
+    if print_mode {
+        // Each item is fitted into the terminal, leaving a line for the
+        // prompt, or made 80 columns wide when the output isn't a terminal.
+        let (max_width, max_height) = if io::stdout().is_terminal() {
+            crossterm::terminal::size()
+                .map(|(w, h)| (w as u32, h.saturating_sub(1) as u32))
+                .unwrap_or((80, 30))
+        } else {
+            (80, u32::MAX)
+        };
+        let (max_width, max_height) = (settings.width.unwrap_or(max_width), settings.height.unwrap_or(max_height));
+
+        playlist.set_repeat(RepeatMode::Off);
+        let renderer = settings.style.renderer(settings.invert.unwrap_or(false));
+        let mut stdout = io::stdout().lock();
+        loop {
+            let viewer = if source.is_image() {
+                ImageViewer::open(&info.path, Arc::clone(&renderer), settings.color)?.background(settings.background)
+            } else {
+                // Anything else prints its first frame.
+                let frame = source.open()?.next_frame()?
+                    .ok_or_else(|| Error::InvalidInput(format!("{} has no frames", source.name())))?;
+                ImageViewer::new(frame.image, &source.name(), Arc::clone(&renderer), settings.color)
+            };
+            let (width, height) = viewer.fit(max_width, max_height);
+            viewer.print(&mut stdout, width, height)?;
+
+            if !playlist.advance() {
+                return Ok(());
+            }
+            (source, info) = sources.open(playlist.current())?;
         }
     }
 
-    if let Some((width, height)) = extractor.dimensions() {
-        println!("Video dimensions: {}x{}", width, height);
-    } else {
-        println!("Video dimensions: Unknown");
+    if settings.style.needs_color() && settings.color == ColorMode::Mono {
+        eprintln!("Warning: the {} style draws with colors only, try --color 256 or truecolor", settings.style.name());
     }
 
-    if let Some(frame_count) = extractor.frame_count() {
-        println!("Estimated frame count: {}", frame_count);
-    } else {
-        println!("Estimated frame count: Unknown");
+    if raw_mode {
+        if info.duration.is_none() && source.is_video() {
+            eprintln!("Cannot play as ASCII: Video duration is unknown.");
+            return Ok(());
+        }
+
+        // Without a terminal on stdout there is nothing to measure, so the
+        // grid size comes from the flags or the defaults.
+        let (term_width, term_height) = if io::stdout().is_terminal() {
+            crossterm::terminal::size()
+                .map(|(w, h)| (w as u32, h.saturating_sub(1) as u32))
+                .unwrap_or((80, 30))
+        } else {
+            (80, 30)
+        };
+
+        if settings.audio {
+            eprintln!("Warning: audio playback is not supported in --raw mode");
+        }
+
+        // Raw output always ends: the playlist plays through once, or as
+        // many times as --loop asks.
+        if loop_count.is_none() {
+            playlist.set_repeat(RepeatMode::Off);
+        }
+        let renderer = settings.style.renderer(settings.invert.unwrap_or(false));
+        loop {
+            let player = Player::builder(info)
+                .source(source)
+                .renderer(Arc::clone(&renderer))
+                .size(settings.width.unwrap_or(term_width), settings.height.unwrap_or(term_height))
+                .color_mode(settings.color)
+                .speed(settings.speed)
+                .max_fps(settings.max_fps)
+                .build()?;
+            player.play_raw()?;
+            let summary = player.summary();
+            eprintln!(
+                "Wrote {} bytes for {} frames ({} dropped)",
+                summary.bytes_written, summary.frames_shown, summary.dropped_frames
+            );
+
+            if !playlist.advance() {
+                return Ok(());
+            }
+            (source, info) = sources.open(playlist.current())?;
+        }
     }
 
-    if let Some(duration) = extractor.duration() {
-        println!("Video duration: {:.2} seconds", duration);
-    } else {
-        println!("Video duration: Unknown");
+    // Still images are fitted to the terminal, so there is nothing to ask
+    // or report before the viewer opens.
+    let still_image = is_still_image(&source, &info);
+    if !still_image {
+        if let Some((width, height)) = info.dimensions() {
+            println!("Video dimensions: {}x{}", width, height);
+        } else {
+            println!("Video dimensions: Unknown");
+        }
+
+        if let Some(frame_count) = info.frame_count {
+            println!("Estimated frame count: {}", frame_count);
+        } else {
+            println!("Estimated frame count: Unknown");
+        }
+
+        if let Some(duration) = info.duration {
+            println!("Video duration: {:.2} seconds", duration);
+        } else {
+            println!("Video duration: Unknown");
+        }
+
+        if !info.chapters.is_empty() {
+            println!("Chapters: {}", info.chapters.len());
+        }
+
+        if info.audio_tracks.len() > 1 {
+            println!("Audio tracks:");
+            for (number, track) in info.audio_tracks.iter().enumerate() {
+                let channels = track.channels
+                    .map(|channels| format!(", {} channels", channels))
+                    .unwrap_or_default();
+                println!("  {}: {} ({}{})", number + 1, track.label(), track.codec, channels);
+            }
+        }
     }
 
     io::stdout().flush()?;
@@ -64,57 +603,153 @@ fn main() -> Result<(), std::io::Error> {
     let stdin = io::stdin();
     let mut handle = stdin.lock();
 
-    if extractor.duration().is_none() {
+    if info.duration.is_none() && source.is_video() {
         println!("Cannot play as ASCII: Video duration is unknown.");
         return Ok(());
     }
 
-    print!("Enter ASCII width (characters): ");
-    io::stdout().flush()?;
-    let mut width_str = String::new();
-    handle.read_line(&mut width_str)?;
-    let width: u32 = match width_str.trim().parse() {
-        Ok(val) => val,
-        Err(_) => {
-            println!("Invalid width, using default of 80 characters");
-            80
+    // With frames coming in on stdin there is nobody to answer, and a still
+    // image needs no size, so the defaults are used.
+    let width: u32 = match settings.width {
+        Some(val) => val,
+        None if reads_stdin || still_image => 80,
+        None => {
+            print!("Enter ASCII width (characters): ");
+            io::stdout().flush()?;
+            let mut width_str = String::new();
+            handle.read_line(&mut width_str)?;
+            match width_str.trim().parse() {
+                Ok(val) => val,
+                Err(_) => {
+                    println!("Invalid width, using default of 80 characters");
+                    80
+                }
+            }
         }
     };
 
-    print!("Enter ASCII height (characters): ");
-    io::stdout().flush()?;
-    let mut height_str = String::new();
-    handle.read_line(&mut height_str)?;
-    let height: u32 = match height_str.trim().parse() {
-        Ok(val) => val,
-        Err(_) => {
-            println!("Invalid height, using default of 30 characters");
-            30
+    let height: u32 = match settings.height {
+        Some(val) => val,
+        None if reads_stdin || still_image => 30,
+        None => {
+            print!("Enter ASCII height (characters): ");
+            io::stdout().flush()?;
+            let mut height_str = String::new();
+            handle.read_line(&mut height_str)?;
+            match height_str.trim().parse() {
+                Ok(val) => val,
+                Err(_) => {
+                    println!("Invalid height, using default of 30 characters");
+                    30
+                }
+            }
         }
     };
 
-    print!("Enter frame delay in milliseconds (e.g., 100): ");
-    io::stdout().flush()?;
-    let mut delay_str = String::new();
-    handle.read_line(&mut delay_str)?;
-    let delay: u64 = match delay_str.trim().parse() {
-        Ok(val) => val,
-        Err(_) => {
-            println!("Invalid delay, using default of 100ms");
-            100
+    let invert = match settings.invert {
+        Some(invert) => invert,
+        None if reads_stdin || still_image => false,
+        None => {
+            print!("Invert brightness? (y/n): ");
+            io::stdout().flush()?;
+            let mut invert_str = String::new();
+            handle.read_line(&mut invert_str)?;
+            invert_str.trim().to_lowercase() == "y"
         }
     };
 
-    print!("Invert brightness? (y/n): ");
-    io::stdout().flush()?;
-    let mut invert_str = String::new();
-    handle.read_line(&mut invert_str)?;
-    let invert = invert_str.trim().to_lowercase() == "y";
-
-    extractor.configure_ascii(width, height, invert);
+    let keymap = match Keymap::from_config(&config) {
+        Ok(keymap) => keymap,
+        Err(e) => {
+            eprintln!("Warning: {}, using the default key bindings", e);
+            Keymap::defaults()
+        }
+    };
 
     println!("Playing video as ASCII art (press Ctrl+C to stop)...");
-    extractor.play_as_ascii(delay)?;
+    // Items that fail to open are skipped; this many in a row means none of
+    // the remaining ones can be played.
+    let mut failures = 0;
+    let renderer = settings.style.renderer(invert);
+    let mut next_item = Some((source, info));
+
+    loop {
+        let (source, info) = match next_item.take() {
+            Some(item) => item,
+            None => match sources.open(playlist.current()) {
+                Ok(item) => item,
+                Err(e) if failures + 1 < playlist.len() => {
+                    eprintln!("Warning: Skipping {}: {}", playlist.current().display(), e);
+                    failures += 1;
+                    if !playlist.skip_forward() {
+                        return Ok(());
+                    }
+                    continue;
+                },
+                Err(e) => return Err(e),
+            },
+        };
+        failures = 0;
+
+        let (end, summary) = if is_still_image(&source, &info) {
+            let end = ImageViewer::open(&info.path, Arc::clone(&renderer), settings.color)?
+                .background(settings.background)
+                .view(&playlist, &keymap)?;
+            (end, None)
+        } else {
+            // Only video files carry or come with subtitles.
+            let subtitle_tracks = if source.is_video() {
+                match subtitles::load_tracks(&info.path, subtitle_file.as_deref().map(Path::new)) {
+                    Ok(tracks) => {
+                        if !tracks.is_empty() {
+                            println!("Loaded {} subtitle track(s)", tracks.len());
+                        }
+                        tracks
+                    },
+                    Err(e) => {
+                        eprintln!("Warning: Failed to load subtitles: {}", e);
+                        Vec::new()
+                    },
+                }
+            } else {
+                Vec::new()
+            };
 
-    Ok(())
+            let player = Player::builder(info.clone())
+                .source(source)
+                .renderer(Arc::clone(&renderer))
+                .size(width, height)
+                .color_mode(settings.color)
+                .audio(settings.audio)
+                .audio_track(audio_track_index(&info, audio_track, audio_lang.as_deref()))
+                .subtitle_track(subtitle_track_index(&subtitle_tracks, subtitle_track, subtitle_lang.as_deref()))
+                .subtitle_tracks(subtitle_tracks)
+                .subtitles(settings.subtitles, settings.subtitle_position)
+                .speed(settings.speed)
+                .pitch_correction(settings.pitch_correction)
+                .max_fps(settings.max_fps)
+                .build()?;
+
+            println!("Starting playback...");
+            println!("{}", keymap.player().footer());
+            let end = player.play(&mut playlist, &keymap)?;
+            (end, Some(player.summary()))
+        };
+        let more = match end {
+            PlaybackEnd::Quit => false,
+            PlaybackEnd::Finished => playlist.advance(),
+            PlaybackEnd::Next => playlist.skip_forward(),
+            PlaybackEnd::Previous => {
+                playlist.skip_back();
+                true
+            },
+        };
+        if !more {
+            println!("Playback complete.");
+            if let Some(summary) = summary.filter(|s| s.dropped_frames > 0 || s.late_frames > 0) {
+                println!("{} frames dropped, {} drawn late", summary.dropped_frames, summary.late_frames);
+            }
+            return Ok(());
+        }
+    }
 }
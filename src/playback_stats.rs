@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::time::{Duration, Instant};
+
+// Weight of the newest sample in the moving averages.
+const SMOOTHING: f64 = 0.1;
+// Achieved FPS is measured over this window.
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+
+/// Exponential moving average, empty until the first sample.
+#[derive(Default)]
+struct Average(Option<f64>);
+
+impl Average {
+    fn push(&mut self, value: f64) {
+        self.0 = Some(match self.0 {
+            Some(average) => average + (value - average) * SMOOTHING,
+            None => value,
+        });
+    }
+
+    fn get(&self) -> Option<f64> {
+        self.0
+    }
+}
+
+/// Per-frame timings and throughput gathered while playing, for the stats
+/// overlay.
+#[derive(Default)]
+pub struct PlaybackStats {
+    decode_ms: Average,
+    convert_ms: Average,
+    draw_ms: Average,
+    frame_bytes: Average,
+    shown: VecDeque<Instant>,
+}
+
+impl PlaybackStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records how long a frame took to decode and convert on the decode
+    /// thread.
+    pub fn record_frame(&mut self, decode: Duration, convert: Duration) {
+        self.decode_ms.push(decode.as_secs_f64() * 1000.0);
+        self.convert_ms.push(convert.as_secs_f64() * 1000.0);
+    }
+
+    /// Records a draw that put a new frame on screen, and the bytes it sent
+    /// to the terminal.
+    pub fn record_draw(&mut self, draw: Duration, bytes: u64) {
+        let now = Instant::now();
+        self.draw_ms.push(draw.as_secs_f64() * 1000.0);
+        self.frame_bytes.push(bytes as f64);
+        self.shown.push_back(now);
+        while self.shown.front().map(|time| now - *time > FPS_WINDOW).unwrap_or(false) {
+            self.shown.pop_front();
+        }
+    }
+
+    pub fn decode_ms(&self) -> Option<f64> {
+        self.decode_ms.get()
+    }
+
+    pub fn convert_ms(&self) -> Option<f64> {
+        self.convert_ms.get()
+    }
+
+    pub fn draw_ms(&self) -> Option<f64> {
+        self.draw_ms.get()
+    }
+
+    pub fn bytes_per_frame(&self) -> Option<f64> {
+        self.frame_bytes.get()
+    }
+
+    /// Frames actually drawn during the last second.
+    pub fn achieved_fps(&self) -> f64 {
+        let now = Instant::now();
+        self.shown.iter().filter(|time| now - **time <= FPS_WINDOW).count() as f64
+            / FPS_WINDOW.as_secs_f64()
+    }
+}
+
+/// Resident memory of this process in bytes. Only available on Linux.
+pub fn resident_memory() -> Option<u64> {
+    // statm reports sizes in pages; 4 KiB pages are all but universal.
+    let statm = fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * 4096)
+}
@@ -0,0 +1,1335 @@
+use std::io::{self, ErrorKind};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crossterm::event::{EnableMouseCapture, DisableMouseCapture};
+use std::sync::mpsc;
+use crossterm::{
+    cursor::Show,
+    event::{self, Event, KeyCode, KeyEventKind, MouseButton, MouseEvent, MouseEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout, Alignment, Position, Rect},
+    style::{Color, Style},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Clear, Gauge, Paragraph, Wrap},
+    Terminal
+};
+use rodio::{OutputStream, OutputStreamHandle, Sink, Source};
+use crate::audio_stream::{device_output_format, AudioPosition, AudioStream};
+use crate::cells::{Cell, CellGrid, ColorMode, Rgb};
+use crate::error::Error;
+use crate::frame_source::{FrameSourceBuilder, SourceMetadata};
+use crate::frame_stream::{FrameStream, PlaybackClock, StreamEvent, StreamFrame};
+use crate::frame_diff::{format_bandwidth, BandwidthMeter, CountingWriter};
+use crate::keymap::{Action, KeyPress, KeySequence, Keymap};
+use crate::playback_stats::{resident_memory, PlaybackStats};
+use crate::playlist::{Playlist, RepeatMode};
+use crate::probe::MediaInfo;
+use crate::raw_output::RawFrameWriter;
+use crate::renderer::{RampRenderer, Renderer};
+use crate::subtitles::{SubtitlePosition, SubtitleTrack};
+use crate::time_stretch::{format_rate, PlaybackRate, TimeStretch};
+use crate::timecode::{format_timestamp, parse_timestamp};
+use crate::scene_detection::DEFAULT_SCENE_THRESHOLD;
+
+// Frame rate assumed when neither the stream nor the metadata gives one.
+const FALLBACK_FPS: f64 = 25.0;
+
+// How long the player sleeps while waiting for the decoder to catch up, and
+// how often the status bar is redrawn when no new frame arrives.
+const DECODE_POLL: Duration = Duration::from_millis(5);
+const STATUS_REFRESH: Duration = Duration::from_millis(250);
+
+// Going back to the previous scene within this many seconds of a cut skips
+// to the scene before it, like a "previous track" button.
+const SCENE_BACK_GRACE: f64 = 1.0;
+
+// How far Page Up/Page Down skip.
+const SKIP_SECONDS: f64 = 10.0;
+
+// How far one step of the scroll wheel over the seek bar moves.
+const SCROLL_SEEK_SECONDS: f64 = 5.0;
+
+// Width of the stats overlay, including its border.
+const STATS_WIDTH: u16 = 32;
+
+/// Why the player stopped playing an item.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackEnd {
+    /// The user quit.
+    Quit,
+    /// The item played to the end.
+    Finished,
+    /// The user skipped to the next or previous playlist item.
+    Next,
+    Previous,
+}
+
+/// Counts from the last `play` or `play_raw`, for reporting once playback
+/// has ended. Returned by `Player::summary`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PlaybackSummary {
+    /// Frames shown on screen or written to stdout.
+    pub frames_shown: u64,
+    /// Frames skipped to catch up after falling behind.
+    pub dropped_frames: u64,
+    /// Frames shown after their deadline. Raw output drops these instead.
+    pub late_frames: u64,
+    /// Bytes written to the terminal or stdout.
+    pub bytes_written: u64,
+}
+
+/// Plays one video, either in the terminal UI with audio, subtitles and
+/// playback controls, or as raw frames on stdout. Built with
+/// `Player::builder`.
+pub struct Player {
+    info: MediaInfo,
+    source: FrameSourceBuilder,
+    renderer: Arc<dyn Renderer>,
+    // Grid size in cells.
+    width: u32,
+    height: u32,
+    color_mode: ColorMode,
+
+    subtitle_tracks: Vec<SubtitleTrack>,
+    subtitles_enabled: bool,
+    subtitle_position: SubtitlePosition,
+    // Index into `subtitle_tracks` of the track shown first.
+    subtitle_track: usize,
+
+    // Audio playback options
+    audio_enabled: bool,
+    audio_volume: f32,
+    // Index into the video's audio tracks of the track to play.
+    audio_track: usize,
+
+    // Playback speed for both video and audio, and whether audio keeps its
+    // pitch when it isn't 1x.
+    playback_rate: f64,
+    preserve_pitch: bool,
+    // Upper limit on frames drawn per second, for slow terminals.
+    max_fps: Option<f64>,
+
+    // Counts from the last run, kept for `summary`.
+    summary: Mutex<PlaybackSummary>,
+}
+
+/// Configuration for a `Player`. Everything but the grid size has a
+/// default.
+pub struct PlayerBuilder {
+    info: MediaInfo,
+    source: FrameSourceBuilder,
+    renderer: Arc<dyn Renderer>,
+    size: Option<(u32, u32)>,
+    color_mode: ColorMode,
+    subtitle_tracks: Vec<SubtitleTrack>,
+    subtitles_enabled: bool,
+    subtitle_position: SubtitlePosition,
+    subtitle_track: usize,
+    audio_enabled: bool,
+    audio_volume: f32,
+    audio_track: usize,
+    playback_rate: f64,
+    preserve_pitch: bool,
+    max_fps: Option<f64>,
+}
+
+impl PlayerBuilder {
+    /// Where frames come from; by default the probed file, as an image or a
+    /// video going by its extension.
+    pub fn source(mut self, source: FrameSourceBuilder) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// How frames are turned into cells; a `RampRenderer` by default.
+    pub fn renderer(mut self, renderer: Arc<dyn Renderer>) -> Self {
+        self.renderer = renderer;
+        self
+    }
+
+    /// Size of the rendered grid in cells.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some((width.max(1), height.max(1)));
+        self
+    }
+
+    pub fn color_mode(mut self, color_mode: ColorMode) -> Self {
+        self.color_mode = color_mode;
+        self
+    }
+
+    /// Whether the audio is played. Off by default.
+    pub fn audio(mut self, enabled: bool) -> Self {
+        self.audio_enabled = enabled;
+        self
+    }
+
+    /// The audio track to play, as an index into the probed audio tracks.
+    pub fn audio_track(mut self, index: usize) -> Self {
+        self.audio_track = index;
+        self
+    }
+
+    /// Initial volume, from 0.0 to 1.0.
+    pub fn volume(mut self, volume: f32) -> Self {
+        self.audio_volume = volume.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The subtitle tracks that can be shown, e.g. from
+    /// `subtitles::load_tracks`.
+    pub fn subtitle_tracks(mut self, tracks: Vec<SubtitleTrack>) -> Self {
+        self.subtitle_tracks = tracks;
+        self
+    }
+
+    /// The subtitle track shown first, as an index into the tracks.
+    pub fn subtitle_track(mut self, index: usize) -> Self {
+        self.subtitle_track = index;
+        self
+    }
+
+    /// Whether subtitles start out visible, and where they are drawn.
+    pub fn subtitles(mut self, enabled: bool, position: SubtitlePosition) -> Self {
+        self.subtitles_enabled = enabled;
+        self.subtitle_position = position;
+        self
+    }
+
+    /// Initial playback rate; 1.0 is real time.
+    pub fn speed(mut self, rate: f64) -> Self {
+        self.playback_rate = rate;
+        self
+    }
+
+    /// Whether audio is time-stretched to keep its pitch at other rates.
+    pub fn pitch_correction(mut self, enabled: bool) -> Self {
+        self.preserve_pitch = enabled;
+        self
+    }
+
+    /// Caps the number of frames drawn per second; frames in between are
+    /// skipped before conversion. `None` plays at the video's frame rate.
+    pub fn max_fps(mut self, max_fps: Option<f64>) -> Self {
+        self.max_fps = max_fps;
+        self
+    }
+
+    pub fn build(self) -> Result<Player, Error> {
+        let (width, height) = self.size.ok_or(Error::NotConfigured { setting: "grid size", method: "size" })?;
+
+        if !self.info.audio_tracks.is_empty() && self.audio_track >= self.info.audio_tracks.len() {
+            return Err(Error::InvalidInput(
+                format!("Audio track {} does not exist ({} available)", self.audio_track + 1, self.info.audio_tracks.len())
+            ));
+        }
+        if !self.subtitle_tracks.is_empty() && self.subtitle_track >= self.subtitle_tracks.len() {
+            return Err(Error::InvalidInput(
+                format!("Subtitle track {} does not exist ({} available)", self.subtitle_track + 1, self.subtitle_tracks.len())
+            ));
+        }
+
+        // Only video files have audio tracks.
+        let audio_enabled = self.audio_enabled && self.source.is_video();
+
+        Ok(Player {
+            info: self.info,
+            source: self.source,
+            renderer: self.renderer,
+            width,
+            height,
+            color_mode: self.color_mode,
+            subtitle_tracks: self.subtitle_tracks,
+            subtitles_enabled: self.subtitles_enabled,
+            subtitle_position: self.subtitle_position,
+            subtitle_track: self.subtitle_track,
+            audio_enabled,
+            audio_volume: self.audio_volume,
+            audio_track: self.audio_track,
+            playback_rate: self.playback_rate,
+            preserve_pitch: self.preserve_pitch,
+            max_fps: self.max_fps,
+            summary: Mutex::new(PlaybackSummary::default()),
+        })
+    }
+}
+
+impl Player {
+    /// Starts configuring a player for the video `info` describes.
+    pub fn builder(info: MediaInfo) -> PlayerBuilder {
+        PlayerBuilder {
+            source: FrameSourceBuilder::detect(&info.path),
+            info,
+            renderer: Arc::new(RampRenderer::new()),
+            size: None,
+            color_mode: ColorMode::Mono,
+            subtitle_tracks: Vec::new(),
+            subtitles_enabled: true,
+            subtitle_position: SubtitlePosition::Below,
+            subtitle_track: 0,
+            audio_enabled: false,
+            audio_volume: 0.5,
+            audio_track: 0,
+            playback_rate: 1.0,
+            preserve_pitch: true,
+            max_fps: None,
+        }
+    }
+
+    pub fn info(&self) -> &MediaInfo {
+        &self.info
+    }
+
+    /// Opens the audio track at `track` (an index into `audio_tracks`, or
+    /// the default stream when there is none) for streaming into a sink at
+    /// the output device's native format, played at `rate`. Also returns a
+    /// handle to its playback position.
+    fn open_audio(&self, track: usize, rate: &PlaybackRate) -> Result<(TimeStretch<AudioStream>, AudioPosition), Error> {
+        let stream_index = self.info.audio_tracks.get(track).map(|track| track.stream_index);
+        let (sample_rate, channels) = device_output_format();
+        let stream = AudioStream::open(&self.info.path, stream_index, sample_rate, channels)?;
+        let position = stream.position();
+        Ok((TimeStretch::new(stream, rate.clone(), self.preserve_pitch), position))
+    }
+
+    /// Starts decoding and converting frames for playback on a background
+    /// thread, detecting scene cuts along the way with `scene_threshold`.
+    fn open_frame_stream(&self, scene_threshold: Option<f64>) -> Result<FrameStream, Error> {
+        let renderer = Arc::clone(&self.renderer);
+        let (width, height) = (self.width, self.height);
+        FrameStream::spawn(self.source.clone(), self.max_fps, scene_threshold, move |frame| {
+            renderer.render_frame(frame, width, height)
+        })
+    }
+
+    /// Duration and frame count from the probe, or from the source itself
+    /// when the probe didn't find them.
+    fn duration(&self, metadata: &SourceMetadata) -> Option<f64> {
+        self.info.duration.or(metadata.duration)
+    }
+
+    fn frame_count(&self, metadata: &SourceMetadata) -> Option<u64> {
+        self.info.frame_count.or(metadata.frame_count)
+    }
+
+    /// Frame rate used for frame numbers and stepping: the source's own rate,
+    /// or one estimated from the metadata for containers that don't say.
+    fn nominal_fps(&self, metadata: &SourceMetadata) -> f64 {
+        if metadata.frame_rate > 0.0 {
+            return metadata.frame_rate;
+        }
+        match (self.frame_count(metadata), self.duration(metadata)) {
+            (Some(frames), Some(duration)) if frames > 0 && duration > 0.0 => frames as f64 / duration,
+            _ => FALLBACK_FPS,
+        }
+    }
+
+    /// Plays the video by writing frames directly to stdout, without the
+    /// alternate screen, raw mode or any of the player panels. Intended for
+    /// pipes, SSH sessions and recordings, so it plays through once and
+    /// returns instead of looping.
+    pub fn play_raw(&self) -> Result<(), Error> {
+        let mut stream = self.open_frame_stream(None)?;
+        let frame_interval = 1.0 / self.nominal_fps(&stream.metadata());
+        let rate = self.playback_rate;
+
+        let stdout = io::stdout();
+        let mut writer = RawFrameWriter::new(stdout.lock(), self.color_mode);
+        let mut clock = PlaybackClock::new();
+        let mut summary = PlaybackSummary::default();
+
+        while let Some(event) = stream.next() {
+            let frame = match event {
+                StreamEvent::Frame(frame) => frame,
+                StreamEvent::End => break,
+                StreamEvent::Failed(e) => return Err(e),
+            };
+            if summary.frames_shown == 0 {
+                clock.seek(frame.timestamp);
+            }
+
+            // Sleep until this frame's deadline rather than a fixed delay so
+            // slow writes don't accumulate into drift. A frame that is already
+            // a whole frame behind is skipped to catch up.
+            match clock.until(frame.timestamp, rate) {
+                Some(remaining) if remaining > Duration::ZERO => thread::sleep(remaining),
+                _ if summary.frames_shown > 0
+                    && clock.position(rate) - frame.timestamp > frame_interval * rate => {
+                    summary.dropped_frames += 1;
+                    continue;
+                },
+                _ => {},
+            }
+
+            match writer.write_frame(&frame.cells) {
+                Ok(()) => summary.frames_shown += 1,
+                // The reader went away (e.g. `| head`), which is a normal way to stop.
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        match writer.finish() {
+            Ok(_) => {},
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => {},
+            Err(e) => return Err(e.into()),
+        }
+        summary.bytes_written = writer.bytes_written();
+
+        *self.summary.lock().unwrap() = summary;
+        Ok(())
+    }
+
+    /// Converts a cell grid into ratatui text, grouping neighbouring cells of
+    /// the same colors into one span.
+    fn cells_to_text(&self, grid: &CellGrid) -> Text<'static> {
+        let lines: Vec<Line<'static>> = (0..grid.height())
+            .map(|y| {
+                let row = grid.row(y);
+                if self.color_mode == ColorMode::Mono {
+                    return Line::from(row.iter().map(|cell| cell.glyph).collect::<String>());
+                }
+
+                let color = |rgb: Rgb| match self.color_mode {
+                    ColorMode::Ansi256 => Color::Indexed(rgb.to_ansi256()),
+                    _ => Color::Rgb(rgb.r, rgb.g, rgb.b),
+                };
+                let style_of = |cell: &Cell| {
+                    let style = Style::default().fg(color(cell.fg));
+                    match cell.bg {
+                        Some(bg) => style.bg(color(bg)),
+                        None => style,
+                    }
+                };
+
+                let mut spans = Vec::new();
+                let mut run = String::new();
+                let mut run_style = None;
+                for cell in row {
+                    let style = style_of(cell);
+                    if run_style != Some(style) && !run.is_empty() {
+                        spans.push(Span::styled(std::mem::take(&mut run), run_style.unwrap()));
+                    }
+                    run_style = Some(style);
+                    run.push(cell.glyph);
+                }
+                if let Some(style) = run_style {
+                    spans.push(Span::styled(run, style));
+                }
+                Line::from(spans)
+            })
+            .collect();
+
+        Text::from(lines)
+    }
+
+    /// Plays the video in the terminal UI until it ends or the user quits
+    /// or skips. `playlist` is the playlist this video is the current item
+    /// of; its repeat and shuffle modes can be changed while playing.
+    /// `keymap` maps keys to actions; those the player doesn't respond to
+    /// are left out.
+    pub fn play(&self, playlist: &mut Playlist, keymap: &Keymap) -> Result<PlaybackEnd, Error> {
+        let mut summary = PlaybackSummary::default();
+        let end = self.run_player(playlist, keymap, &mut summary);
+        *self.summary.lock().unwrap() = summary;
+        end
+    }
+
+    /// Counts from the last `play` or `play_raw`, such as how many frames
+    /// were dropped. All zero before either has run.
+    pub fn summary(&self) -> PlaybackSummary {
+        *self.summary.lock().unwrap()
+    }
+
+    /// The player loop behind `play`, counting into `summary` as it goes.
+    fn run_player(&self, playlist: &mut Playlist, keymap: &Keymap, summary: &mut PlaybackSummary) -> Result<PlaybackEnd, Error> {
+        let keymap = &keymap.player();
+        // Scene changes are detected as frames are decoded, so scene
+        // navigation covers whatever has been played or buffered so far.
+        let mut stream = self.open_frame_stream(Some(DEFAULT_SCENE_THRESHOLD))?;
+        let metadata = stream.metadata();
+        let fps = self.nominal_fps(&metadata);
+        let frame_interval = 1.0 / fps;
+        let duration = self.duration(&metadata);
+        // Start times of the scenes found so far, in order.
+        let mut scene_times: Vec<f64> = Vec::new();
+
+        enable_raw_mode().map_err(Error::Terminal)?;
+        let mut terminal_guard = TerminalGuard::new();
+        // ratatui keeps the last screen and sends only the cells that changed,
+        // the same diff `FrameDiffer` makes for raw output, so the player
+        // just counts what goes out for the status bar.
+        let mut stdout = CountingWriter::new(io::stdout());
+        let output_bytes = stdout.counter();
+        execute!(
+            stdout,
+            EnterAlternateScreen,
+            EnableMouseCapture
+        ).map_err(Error::Terminal)?;
+        let backend = CrosstermBackend::new(stdout);
+        let mut terminal = Terminal::new(backend).map_err(Error::Terminal)?;
+
+        let (tx, rx) = mpsc::channel();
+        let mut paused = false;
+        let rate = PlaybackRate::new(self.playback_rate);
+        let total_frames = self.frame_count(&metadata)
+            .or_else(|| duration.map(|duration| (duration * fps).round() as u64))
+            .unwrap_or(0);
+
+        // The frame on screen, the next decoded one waiting for its deadline,
+        // and the clock both are timed against.
+        let mut current: Option<StreamFrame> = None;
+        let mut upcoming: Option<StreamFrame> = None;
+        let mut clock = PlaybackClock::new();
+        let mut stream_ended = false;
+        // Why the stream ended early, reported once playback reaches it.
+        let mut stream_error: Option<Error> = None;
+        // After a seek the first new frame is shown as soon as it arrives.
+        let mut show_next = true;
+        let mut show_stats = false;
+        // A-B repeat points; once both are set the segment between them
+        // loops.
+        let mut loop_start: Option<f64> = None;
+        let mut loop_end: Option<f64> = None;
+        // Set by the frame step key until the next frame has been shown.
+        let mut step_pending = false;
+        // Text typed into the go-to prompt while it is open.
+        let mut goto_input: Option<String> = None;
+        let last_frame_time = duration.map(|duration| (duration - frame_interval).max(0.0));
+        let mut stats = PlaybackStats::new();
+        let video_name = self.source.name();
+
+        // Audio is optional: it is only opened when enabled, and without an
+        // output device the video plays silently.
+        let output: Option<(OutputStream, OutputStreamHandle)> = if self.audio_enabled {
+            match OutputStream::try_default() {
+                Ok(output) => Some(output),
+                Err(e) => {
+                    eprintln!("Warning: {}", audio_unavailable("no audio output device", e));
+                    None
+                },
+            }
+        } else {
+            None
+        };
+        let sink = output.as_ref().and_then(|(_, handle)| match Sink::try_new(handle) {
+            Ok(sink) => Some(sink),
+            Err(e) => {
+                eprintln!("Warning: {}", audio_unavailable("failed to create audio sink", e));
+                None
+            },
+        });
+        let audio_setup_success = sink.is_some();
+
+        // Set up audio variables
+        let mut audio_muted = false;
+        let mut current_volume = self.audio_volume;
+        let mut audio_position: Option<AudioPosition> = None;
+
+        // Set initial volume and start audio playback if possible
+        let sink_arc = if let Some(sink) = sink {
+            sink.set_volume(self.audio_volume);
+
+            match self.open_audio(self.audio_track, &rate) {
+                Ok((source, position)) => {
+                    sink.append(source);
+                    audio_position = Some(position);
+                    if paused {
+                        sink.pause();
+                    } else {
+                        sink.play();
+                    }
+                },
+                Err(e) => {
+                    eprintln!("Warning: {}", e);
+                }
+            }
+
+            Some(Arc::new(Mutex::new(sink)))
+        } else {
+            None
+        };
+
+        let mut subtitles_on = self.subtitles_enabled;
+        let mut subtitle_track = self.subtitle_track;
+        let mut audio_track = self.audio_track;
+
+        // Shared audio control state
+        let paused_state = Arc::new(Mutex::new(paused));
+
+        let _input = InputReader::spawn(tx);
+        // Where the seek bar and clickable status fields were last drawn.
+        let mut mouse_targets = MouseTargets::default();
+        let mut key_sequence = KeySequence::new();
+        let controls = keymap.footer();
+
+        let mut bandwidth = BandwidthMeter::new();
+        let mut wait = Duration::ZERO;
+        let mut last_draw: Option<Instant> = None;
+
+        loop {
+            // Sleep until the next frame is due or a key arrives, whichever
+            // comes first.
+            let mut events: Vec<Event> = rx.recv_timeout(wait).into_iter().collect();
+            events.extend(rx.try_iter());
+            let mut needs_draw = !events.is_empty();
+            let mut seek_target: Option<f64> = None;
+            let position = current.as_ref().map(|frame| frame.timestamp).unwrap_or(0.0);
+
+            for event in events {
+                let action = match event {
+                    Event::Key(key) if key.kind != KeyEventKind::Release => {
+                        // The go-to prompt takes all typing while it is open.
+                        if let Some(input) = &mut goto_input {
+                            match key.code {
+                                KeyCode::Enter => {
+                                    if let Some(target) = parse_timestamp(input) {
+                                        let target = target.max(0.0);
+                                        seek_target = Some(last_frame_time.map(|last| target.min(last)).unwrap_or(target));
+                                    }
+                                    goto_input = None;
+                                },
+                                KeyCode::Esc => goto_input = None,
+                                KeyCode::Backspace => {
+                                    input.pop();
+                                },
+                                KeyCode::Char(c) if c.is_ascii_digit() || c == ':' || c == '.' => input.push(c),
+                                _ => {},
+                            }
+                            continue;
+                        }
+
+                        match key_sequence.feed(keymap, KeyPress::from_event(&key)) {
+                            Some(action) => action,
+                            None => continue,
+                        }
+                    },
+                    // Clicks and the scroll wheel run an action, or seek
+                    // directly.
+                    Event::Mouse(mouse) => match mouse_action(&mouse, &mouse_targets, last_frame_time) {
+                        Some(MouseAction::Run(action)) => action,
+                        Some(MouseAction::Seek(target)) => {
+                            seek_target = Some(target);
+                            continue;
+                        },
+                        Some(MouseAction::SeekBy(offset)) => {
+                            seek_target = Some((position + offset).max(0.0));
+                            continue;
+                        },
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+
+                match action {
+                    Action::Quit => {
+                        leave_player(&mut terminal, &sink_arc, &mut terminal_guard)?;
+                        return Ok(PlaybackEnd::Quit);
+                    },
+                    Action::NextItem if playlist.len() > 1 => {
+                        leave_player(&mut terminal, &sink_arc, &mut terminal_guard)?;
+                        return Ok(PlaybackEnd::Next);
+                    },
+                    Action::PrevItem if playlist.len() > 1 => {
+                        leave_player(&mut terminal, &sink_arc, &mut terminal_guard)?;
+                        return Ok(PlaybackEnd::Previous);
+                    },
+                    Action::CycleRepeat => {
+                        playlist.set_repeat(playlist.repeat().cycle());
+                    },
+                    Action::ToggleShuffle if playlist.len() > 1 => {
+                        playlist.set_shuffle(!playlist.is_shuffled());
+                    },
+                    Action::TogglePause => {
+                        paused = !paused;
+                        if paused {
+                            clock.pause(rate.get());
+                        } else {
+                            clock.resume();
+                        }
+
+                        // Update audio playback state
+                        if let Ok(mut paused_guard) = paused_state.lock() {
+                            *paused_guard = paused;
+                        }
+
+                        if let Some(sink_arc) = &sink_arc {
+                            if let Ok(sink) = sink_arc.lock() {
+                                if paused {
+                                    sink.pause();
+                                } else {
+                                    sink.play();
+                                }
+                            }
+                        }
+                    },
+                    Action::ToggleMute => {
+                        // Toggle mute
+                        audio_muted = !audio_muted;
+                        if let Some(sink_arc) = &sink_arc {
+                            if let Ok(sink) = sink_arc.lock() {
+                                if audio_muted {
+                                    sink.set_volume(0.0);
+                                } else {
+                                    sink.set_volume(current_volume);
+                                }
+                            }
+                        }
+                    },
+                    Action::VolumeUp => {
+                        // Increase volume
+                        current_volume = (current_volume + 0.1).min(1.0);
+                        if !audio_muted {
+                            if let Some(sink_arc) = &sink_arc {
+                                if let Ok(sink) = sink_arc.lock() {
+                                    sink.set_volume(current_volume);
+                                }
+                            }
+                        }
+                    },
+                    Action::VolumeDown => {
+                        // Decrease volume
+                        current_volume = (current_volume - 0.1).max(0.0);
+                        if !audio_muted {
+                            if let Some(sink_arc) = &sink_arc {
+                                if let Ok(sink) = sink_arc.lock() {
+                                    sink.set_volume(current_volume);
+                                }
+                            }
+                        }
+                    },
+                    Action::SpeedDown => {
+                        clock.rebase(rate.get());
+                        rate.slower();
+                    },
+                    Action::SpeedUp => {
+                        clock.rebase(rate.get());
+                        rate.faster();
+                    },
+                    Action::BackFrames => {
+                        seek_target = Some((position - 10.0 * frame_interval).max(0.0));
+                    },
+                    Action::ForwardFrames => {
+                        seek_target = Some(position + 10.0 * frame_interval);
+                    },
+                    Action::SeekBack => {
+                        seek_target = Some((position - SKIP_SECONDS).max(0.0));
+                    },
+                    Action::SeekForward => {
+                        seek_target = Some(position + SKIP_SECONDS);
+                    },
+                    Action::JumpStart => {
+                        seek_target = Some(0.0);
+                    },
+                    Action::JumpEnd => {
+                        seek_target = Some(last_frame_time.unwrap_or(position));
+                    },
+                    Action::StepForward => {
+                        step_pending = true;
+                    },
+                    Action::StepBack => {
+                        // A whole frame back; sources land on the frame on
+                        // screen within half a frame, which absorbs uneven
+                        // timestamps.
+                        seek_target = Some((position - frame_interval).max(0.0));
+                    },
+                    Action::GoTo => {
+                        goto_input = Some(String::new());
+                    },
+                    Action::ToggleSubtitles => {
+                        subtitles_on = !subtitles_on;
+                    },
+                    Action::AbRepeat => {
+                        // Set A, then B, then clear both.
+                        match (loop_start, loop_end) {
+                            (None, _) => loop_start = Some(position),
+                            (Some(start), None) if position > start => loop_end = Some(position),
+                            // B before A: move A instead.
+                            (Some(_), None) => loop_start = Some(position),
+                            (Some(_), Some(_)) => {
+                                loop_start = None;
+                                loop_end = None;
+                            },
+                        }
+                    },
+                    Action::ToggleStats => {
+                        show_stats = !show_stats;
+                    },
+                    Action::NextSubtitleTrack if !self.subtitle_tracks.is_empty() => {
+                        subtitle_track = (subtitle_track + 1) % self.subtitle_tracks.len();
+                        subtitles_on = true;
+                    },
+                    Action::NextAudioTrack if self.audio_enabled && self.info.audio_tracks.len() > 1 => {
+                        let next = (audio_track + 1) % self.info.audio_tracks.len();
+                        // On failure the previous track simply keeps playing.
+                        if let (Some(sink_arc), Ok((mut source, new_position))) = (&sink_arc, self.open_audio(next, &rate)) {
+                            let _ = source.try_seek(Duration::from_secs_f64(position));
+                            if let Ok(sink) = sink_arc.lock() {
+                                sink.clear();
+                                sink.append(source);
+                                if !paused {
+                                    sink.play();
+                                }
+                            }
+                            audio_track = next;
+                            audio_position = Some(new_position);
+                        }
+                    },
+                    Action::NextScene => {
+                        // Half a frame of slack so the cut we're on isn't "next".
+                        if let Some(&next) = scene_times.iter().find(|&&time| time > position + frame_interval / 2.0) {
+                            seek_target = Some(next);
+                        }
+                    },
+                    Action::PrevScene => {
+                        let previous = scene_times
+                            .iter()
+                            .rev()
+                            .find(|&&time| time + SCENE_BACK_GRACE < position)
+                            .copied()
+                            .unwrap_or(0.0);
+                        seek_target = Some(previous);
+                    },
+                    _ => {}
+                }
+            }
+
+            // Parts of the video decoded again after a seek find the same
+            // cuts a second time.
+            for cut in stream.scene_cuts() {
+                let index = scene_times.partition_point(|&time| time < cut.timestamp - frame_interval / 2.0);
+                if scene_times.get(index).is_none_or(|&time| time > cut.timestamp + frame_interval / 2.0) {
+                    scene_times.insert(index, cut.timestamp);
+                }
+            }
+
+            // Once the last frame has had its turn, either start over or hand
+            // back to the playlist. An A-B segment loops on its own, and the
+            // video's end counts as B if B is beyond it.
+            let finished = stream_ended && upcoming.is_none() && current
+                .as_ref()
+                .map(|frame| clock.position(rate.get()) >= frame.timestamp + frame_interval)
+                .unwrap_or(false);
+            // A source that ends before its first frame has nothing to show
+            // or loop over.
+            if stream_ended && upcoming.is_none() && current.is_none() {
+                leave_player(&mut terminal, &sink_arc, &mut terminal_guard)?;
+                return Err(stream_error.take().unwrap_or_else(|| {
+                    Error::InvalidInput(format!("{} has no frames to play", video_name))
+                }));
+            }
+            if finished {
+                if let Some(e) = stream_error.take() {
+                    leave_player(&mut terminal, &sink_arc, &mut terminal_guard)?;
+                    return Err(e);
+                }
+            }
+            if let Some((start, end)) = loop_start.zip(loop_end) {
+                // While a seek is pending the old frame is still current.
+                let past_end = !show_next && current.as_ref().map(|frame| frame.timestamp >= end).unwrap_or(false);
+                if (past_end || finished) && seek_target.is_none() {
+                    seek_target = Some(start);
+                }
+            } else if finished {
+                if !playlist.restart_current() {
+                    leave_player(&mut terminal, &sink_arc, &mut terminal_guard)?;
+                    return Ok(PlaybackEnd::Finished);
+                }
+                seek_target = Some(0.0);
+            }
+
+            if let Some(target) = seek_target {
+                stream.seek(target);
+                clock.seek(target);
+                // The sink drops the audio once it has played to the end, so
+                // seeking back from there needs the track opened again.
+                if let Some(sink_arc) = &sink_arc {
+                    let ended = sink_arc.lock().map(|sink| sink.empty()).unwrap_or(false);
+                    if ended && self.audio_enabled && audio_setup_success {
+                        if let Ok((source, new_position)) = self.open_audio(audio_track, &rate) {
+                            if let Ok(sink) = sink_arc.lock() {
+                                sink.append(source);
+                                if !paused {
+                                    sink.play();
+                                }
+                            }
+                            audio_position = Some(new_position);
+                        }
+                    }
+                }
+                seek_audio(&sink_arc, target);
+                upcoming = None;
+                stream_ended = false;
+                stream_error = None;
+                show_next = true;
+                step_pending = false;
+            }
+
+            // Take every frame that is due. If more than one is, only the
+            // newest is drawn and the ones before it count as dropped.
+            let mut new_frame = false;
+            loop {
+                if upcoming.is_none() && !stream_ended {
+                    match stream.try_next() {
+                        Some(StreamEvent::Frame(frame)) => upcoming = Some(frame),
+                        Some(StreamEvent::End) => stream_ended = true,
+                        Some(StreamEvent::Failed(e)) => {
+                            stream_ended = true;
+                            stream_error = Some(e);
+                        },
+                        None => {},
+                    }
+                }
+
+                let due = match &upcoming {
+                    Some(frame) => show_next || step_pending || clock.position(rate.get()) >= frame.timestamp,
+                    None => false,
+                };
+                if !due {
+                    break;
+                }
+
+                let frame = upcoming.take().expect("due frame exists");
+                stats.record_frame(frame.decode_time, frame.convert_time);
+                if show_next {
+                    // Start the clock from where the seek actually landed.
+                    clock.seek(frame.timestamp);
+                    show_next = false;
+                } else if step_pending {
+                    // Stepping moves the clock, and the audio with it, to
+                    // the new frame.
+                    clock.seek(frame.timestamp);
+                    seek_audio(&sink_arc, frame.timestamp);
+                    step_pending = false;
+                } else if new_frame {
+                    summary.dropped_frames += 1;
+                }
+                current = Some(frame);
+                new_frame = true;
+                summary.frames_shown += 1;
+            }
+
+            if new_frame {
+                needs_draw = true;
+                if let Some(frame) = &current {
+                    let lateness = (clock.position(rate.get()) - frame.timestamp) / rate.get();
+                    if lateness > frame_interval / 2.0 {
+                        summary.late_frames += 1;
+                    }
+                }
+            }
+
+            // Wake up for the next frame's deadline; poll briefly while the
+            // decoder catches up, and refresh the status now and then while
+            // nothing else changes.
+            wait = match &upcoming {
+                Some(frame) => clock.until(frame.timestamp, rate.get()).unwrap_or(STATUS_REFRESH),
+                None if paused || stream_ended => STATUS_REFRESH,
+                None => DECODE_POLL,
+            }
+            .min(STATUS_REFRESH);
+
+            if last_draw.map(|time| time.elapsed() >= STATUS_REFRESH).unwrap_or(true) {
+                needs_draw = true;
+            }
+            if !needs_draw {
+                continue;
+            }
+            last_draw = Some(Instant::now());
+
+            let output_rate = bandwidth.update(output_bytes.load(Ordering::Relaxed));
+
+            let playback_time = current.as_ref().map(|frame| frame.timestamp).unwrap_or(0.0);
+            let frame_number = (playback_time * fps).round() as u64 + 1;
+            let active_cue = self.subtitle_tracks
+                .get(subtitle_track)
+                .filter(|_| subtitles_on)
+                .and_then(|track| track.cue_at(playback_time));
+            let subtitle_area_height = if self.subtitle_position == SubtitlePosition::Below && !self.subtitle_tracks.is_empty() {
+                2
+            } else {
+                0
+            };
+
+            let stats_lines = if show_stats {
+                let ms = |value: Option<f64>| value
+                    .map(|ms| format!("{:.1} ms", ms))
+                    .unwrap_or_else(|| String::from("n/a"));
+                let (buffered, capacity) = stream.buffered();
+                // Positive when the audio is ahead of the picture.
+                let drift = audio_position
+                    .as_ref()
+                    .map(|audio| format!("{:+.0} ms", (audio.get() - clock.position(rate.get())) * 1000.0))
+                    .unwrap_or_else(|| String::from("n/a"));
+                let memory = resident_memory()
+                    .map(|bytes| format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0)))
+                    .unwrap_or_else(|| String::from("n/a"));
+                let frame_bytes = stats.bytes_per_frame()
+                    .map(|bytes| format!("{:.1} KB", bytes / 1024.0))
+                    .unwrap_or_else(|| String::from("n/a"));
+
+                vec![
+                    format!("Decode:   {}", ms(stats.decode_ms())),
+                    format!("Convert:  {}", ms(stats.convert_ms())),
+                    format!("Draw:     {}", ms(stats.draw_ms())),
+                    format!("FPS:      {:.1} / {:.1}", stats.achieved_fps(), fps * rate.get()),
+                    format!("Dropped:  {} (late {})", summary.dropped_frames, summary.late_frames),
+                    format!("Buffer:   {}/{}", buffered, capacity),
+                    format!("A/V:      {}", drift),
+                    format!("Memory:   {}", memory),
+                    format!("Output:   {}/frame", frame_bytes),
+                ]
+            } else {
+                Vec::new()
+            };
+
+            let bytes_before = output_bytes.load(Ordering::Relaxed);
+            let draw_started = Instant::now();
+
+            terminal.draw(|f| {
+                let size = f.area();
+
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([
+                        Constraint::Length(3),
+                        Constraint::Min(5),
+                        Constraint::Length(subtitle_area_height),
+                        Constraint::Length(1),
+                        Constraint::Length(3),
+                    ])
+                    .split(size);
+
+                let volume_status = if !audio_setup_success {
+                    "NO AUDIO"
+                } else if audio_muted {
+                    "MUTED"
+                } else {
+                    match (current_volume * 10.0).round() as i32 {
+                        0 => "VOL: 0%",
+                        1 => "VOL: 10%",
+                        2 => "VOL: 20%",
+                        3 => "VOL: 30%",
+                        4 => "VOL: 40%",
+                        5 => "VOL: 50%",
+                        6 => "VOL: 60%",
+                        7 => "VOL: 70%",
+                        8 => "VOL: 80%",
+                        9 => "VOL: 90%",
+                        _ => "VOL: 100%",
+                    }
+                };
+
+                let chapter_status = self.info.chapter_at(playback_time)
+                    .filter(|chapter| !chapter.title.is_empty())
+                    .map(|chapter| format!(" | Chapter: {}", chapter.title))
+                    .unwrap_or_default();
+
+                let subtitle_status = match self.subtitle_tracks.get(subtitle_track) {
+                    Some(track) if subtitles_on => format!(" | SUB: {}", track.label),
+                    Some(_) => String::from(" | SUB: OFF"),
+                    None => String::new(),
+                };
+
+                let audio_status = if self.info.audio_tracks.len() > 1 {
+                    let track = &self.info.audio_tracks[audio_track];
+                    format!(" | AUDIO: {}/{} {}", audio_track + 1, self.info.audio_tracks.len(), track.label())
+                } else {
+                    String::new()
+                };
+
+                let playlist_status = if playlist.len() > 1 {
+                    format!(
+                        " | PLAYLIST: {}/{}{} | REPEAT: {}",
+                        playlist.position(),
+                        playlist.len(),
+                        if playlist.is_shuffled() { " SHUFFLE" } else { "" },
+                        playlist.repeat().label()
+                    )
+                } else if playlist.repeat() != RepeatMode::All {
+                    format!(" | REPEAT: {}", playlist.repeat().label())
+                } else {
+                    String::new()
+                };
+
+                let loop_status = match (loop_start, loop_end, playlist.loop_progress()) {
+                    (Some(start), Some(end), _) => format!(" | A-B: {}-{}", format_timestamp(start), format_timestamp(end)),
+                    (Some(start), None, _) => format!(" | A: {}", format_timestamp(start)),
+                    (None, _, Some((pass, count))) => format!(" | LOOP: {}/{}", pass, count),
+                    (None, _, None) => String::new(),
+                };
+
+                let timing_status = if summary.dropped_frames > 0 || summary.late_frames > 0 {
+                    format!(" | DROPPED: {} LATE: {}", summary.dropped_frames, summary.late_frames)
+                } else {
+                    String::new()
+                };
+
+                // The pause and volume fields are separate spans so their
+                // positions can be worked out for mouse clicks.
+                let status_lead = Span::raw(format!(
+                    "Playing: {}{}{}{} | Frame: {}/{} | Speed: {}{} | ",
+                    video_name,
+                    playlist_status,
+                    loop_status,
+                    chapter_status,
+                    frame_number,
+                    total_frames,
+                    format_rate(rate.get()),
+                    timing_status
+                ));
+                let pause_span = Span::raw(if paused { "PAUSED" } else { "PLAYING" });
+                let volume_span = Span::raw(volume_status);
+                let status_tail = Span::raw(format!(
+                    "{}{} | NET: {}",
+                    audio_status,
+                    subtitle_status,
+                    format_bandwidth(output_rate)
+                ));
+
+                let status_block = Block::default().borders(Borders::ALL).title("ASCII Video Player");
+                let status_area = status_block.inner(chunks[0]);
+                let status = Line::from(vec![
+                    status_lead.clone(),
+                    pause_span.clone(),
+                    Span::raw(" | "),
+                    volume_span.clone(),
+                    status_tail,
+                ]);
+
+                // Same centering as the paragraph applies.
+                let line_start = status_area.x + (status_area.width / 2).saturating_sub(status.width() as u16 / 2);
+                let pause_x = line_start + status_lead.width() as u16;
+                let volume_x = pause_x + pause_span.width() as u16 + 3;
+                mouse_targets.pause = Rect::new(pause_x, status_area.y, pause_span.width() as u16, 1);
+                mouse_targets.volume = Rect::new(volume_x, status_area.y, volume_span.width() as u16, 1);
+                mouse_targets.seek_bar = chunks[3];
+
+                let status_widget = Paragraph::new(status)
+                    .block(status_block)
+                    .alignment(Alignment::Center)
+                    .style(Style::default());
+
+                let progress = match duration.filter(|duration| *duration > 0.0) {
+                    Some(duration) => (playback_time / duration).clamp(0.0, 1.0),
+                    None => 0.0,
+                };
+                let progress_label = match duration {
+                    Some(duration) => format!("{} / {}", format_timestamp(playback_time), format_timestamp(duration)),
+                    None => format_timestamp(playback_time),
+                };
+                let seek_bar = Gauge::default()
+                    .ratio(progress)
+                    .label(progress_label)
+                    .gauge_style(Style::default().fg(Color::White).bg(Color::DarkGray));
+
+                let empty_frame = CellGrid::new(0, 0);
+                let ascii_content = current.as_ref().map(|frame| &frame.cells).unwrap_or(&empty_frame);
+                let ascii_widget = Paragraph::new(self.cells_to_text(ascii_content))
+                    .style(Style::default());
+
+                let controls = match &goto_input {
+                    Some(input) => format!("Go to: {}_  (Enter - Jump | Esc - Cancel)", input),
+                    None => controls.clone(),
+                };
+                let controls_widget = Paragraph::new(controls)
+                    .block(Block::default().borders(Borders::ALL))
+                    .alignment(Alignment::Center)
+                    .style(Style::default());
+
+                f.render_widget(status_widget, chunks[0]);
+                f.render_widget(ascii_widget, chunks[1]);
+                f.render_widget(seek_bar, chunks[3]);
+                f.render_widget(controls_widget, chunks[4]);
+
+                if let Some(cue) = active_cue {
+                    let subtitle_widget = Paragraph::new(cue.text.clone())
+                        .alignment(Alignment::Center)
+                        .wrap(Wrap { trim: true })
+                        .style(Style::default());
+
+                    match self.subtitle_position {
+                        SubtitlePosition::Below => f.render_widget(subtitle_widget, chunks[2]),
+                        SubtitlePosition::Overlay => {
+                            // Cover the bottom rows of the frame itself, which
+                            // may be shorter than the area reserved for it.
+                            let video = chunks[1];
+                            let frame_bottom = video.y + video.height.min(ascii_content.height() as u16);
+                            let lines = (cue.text.lines().count() as u16).min(video.height);
+                            let area = Rect::new(video.x, frame_bottom.saturating_sub(lines), video.width, lines);
+                            f.render_widget(Clear, area);
+                            f.render_widget(subtitle_widget, area);
+                        },
+                    }
+                }
+
+                if show_stats {
+                    // Top-right corner of the video, clamped to fit.
+                    let video = chunks[1];
+                    let width = STATS_WIDTH.min(video.width);
+                    let height = (stats_lines.len() as u16 + 2).min(video.height);
+                    let area = Rect::new(video.x + video.width - width, video.y, width, height);
+                    let stats_widget = Paragraph::new(stats_lines.join("\n"))
+                        .block(Block::default().borders(Borders::ALL).title("Stats"))
+                        .style(Style::default());
+                    f.render_widget(Clear, area);
+                    f.render_widget(stats_widget, area);
+                }
+            }).map_err(Error::Terminal)?;
+
+            // Only draws that put a new frame up count towards the frame
+            // statistics; status refreshes would skew them.
+            if new_frame {
+                stats.record_draw(draw_started.elapsed(), output_bytes.load(Ordering::Relaxed) - bytes_before);
+            }
+            summary.bytes_written = output_bytes.load(Ordering::Relaxed);
+        }
+    }
+}
+
+/// Reads key presses and mouse events on a background thread until
+/// dropped, so the reader for one playlist item doesn't steal input from the
+/// next one's.
+struct InputReader {
+    stop: Arc<AtomicBool>,
+}
+
+impl InputReader {
+    fn spawn(events: mpsc::Sender<Event>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+
+        thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                if event::poll(Duration::from_millis(100)).unwrap_or(false) {
+                    let forwarded = match event::read() {
+                        Ok(event @ (Event::Key(_) | Event::Mouse(_))) => events.send(event).is_ok(),
+                        _ => true,
+                    };
+                    if !forwarded {
+                        break;
+                    }
+                }
+            }
+        });
+
+        InputReader { stop }
+    }
+}
+
+impl Drop for InputReader {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Screen areas from the last draw that respond to the mouse.
+#[derive(Default)]
+struct MouseTargets {
+    seek_bar: Rect,
+    pause: Rect,
+    volume: Rect,
+}
+
+enum MouseAction {
+    Run(Action),
+    /// Seeks to this many seconds, or by this many from the current frame.
+    Seek(f64),
+    SeekBy(f64),
+}
+
+/// What a mouse event does: clicking or dragging on the seek bar seeks,
+/// clicking the pause or volume field toggles pause or mute, and the scroll
+/// wheel seeks over the seek bar and changes the volume elsewhere.
+/// `last_frame_time` is the timestamp the end of the seek bar stands for.
+fn mouse_action(mouse: &MouseEvent, targets: &MouseTargets, last_frame_time: Option<f64>) -> Option<MouseAction> {
+    let point = Position::new(mouse.column, mouse.row);
+    let on_seek_bar = targets.seek_bar.contains(point);
+
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) | MouseEventKind::Drag(MouseButton::Left) if on_seek_bar => {
+            let fraction = (mouse.column - targets.seek_bar.x) as f64 / targets.seek_bar.width.max(1) as f64;
+            last_frame_time.map(|last| MouseAction::Seek(fraction * last))
+        },
+        MouseEventKind::Down(MouseButton::Left) if targets.pause.contains(point) => Some(MouseAction::Run(Action::TogglePause)),
+        MouseEventKind::Down(MouseButton::Left) if targets.volume.contains(point) => Some(MouseAction::Run(Action::ToggleMute)),
+        MouseEventKind::ScrollUp if on_seek_bar => Some(MouseAction::SeekBy(SCROLL_SEEK_SECONDS)),
+        MouseEventKind::ScrollDown if on_seek_bar => Some(MouseAction::SeekBy(-SCROLL_SEEK_SECONDS)),
+        MouseEventKind::ScrollUp => Some(MouseAction::Run(Action::VolumeUp)),
+        MouseEventKind::ScrollDown => Some(MouseAction::Run(Action::VolumeDown)),
+        _ => None,
+    }
+}
+
+/// Gives the terminal back to the shell if the player returns without
+/// calling `leave_player`, e.g. on an error, so the shell isn't left in raw
+/// mode on the alternate screen.
+struct TerminalGuard {
+    armed: bool,
+}
+
+impl TerminalGuard {
+    fn new() -> Self {
+        TerminalGuard { armed: true }
+    }
+
+    /// The terminal was restored the normal way.
+    fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        if self.armed {
+            let _ = disable_raw_mode();
+            let _ = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, Show);
+        }
+    }
+}
+
+/// Stops the audio and gives the terminal back to the shell.
+fn leave_player<W: io::Write>(
+    terminal: &mut Terminal<CrosstermBackend<W>>,
+    sink: &Option<Arc<Mutex<Sink>>>,
+    guard: &mut TerminalGuard,
+) -> Result<(), Error> {
+    if let Some(sink_arc) = sink {
+        if let Ok(sink) = sink_arc.lock() {
+            sink.stop();
+        }
+    }
+
+    disable_raw_mode().map_err(Error::Terminal)?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    ).map_err(Error::Terminal)?;
+    terminal.show_cursor().map_err(Error::Terminal)?;
+    guard.disarm();
+    Ok(())
+}
+
+fn audio_unavailable<E>(reason: &str, e: E) -> Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    Error::AudioUnavailable { reason: reason.to_string(), source: Some(Box::new(e)) }
+}
+
+/// Moves audio playback to `seconds`, if audio is playing and the source
+/// supports seeking.
+fn seek_audio(sink: &Option<Arc<Mutex<Sink>>>, seconds: f64) {
+    if let Some(sink_arc) = sink {
+        if let Ok(sink) = sink_arc.lock() {
+            let _ = sink.try_seek(Duration::from_secs_f64(seconds.max(0.0)));
+        }
+    }
+}
@@ -0,0 +1,463 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::error::Error;
+use crate::image_source::is_image_file;
+
+/// Video file extensions picked up when a directory is given as input,
+/// along with the image files `image_source` reads.
+const VIDEO_EXTENSIONS: [&str; 13] = [
+    "mp4", "m4v", "mkv", "webm", "mov", "avi", "wmv", "flv", "mpg", "mpeg", "ts", "ogv", "3gp",
+];
+
+/// What happens when an item finishes playing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepeatMode {
+    /// Stop after the last item.
+    Off,
+    /// Start over from the first item after the last.
+    All,
+    /// Keep playing the current item.
+    One,
+}
+
+impl RepeatMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "off" | "none" => Some(RepeatMode::Off),
+            "all" => Some(RepeatMode::All),
+            "one" => Some(RepeatMode::One),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RepeatMode::Off => "off",
+            RepeatMode::All => "all",
+            RepeatMode::One => "one",
+        }
+    }
+
+    /// The mode the repeat key switches to: off, all, one, off...
+    pub fn cycle(self) -> Self {
+        match self {
+            RepeatMode::Off => RepeatMode::All,
+            RepeatMode::All => RepeatMode::One,
+            RepeatMode::One => RepeatMode::Off,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RepeatMode::Off => "OFF",
+            RepeatMode::All => "ALL",
+            RepeatMode::One => "ONE",
+        }
+    }
+}
+
+/// Turns the inputs given on the command line into a list of files.
+/// Directories contribute the media files directly inside them and glob
+/// patterns the files they match, both in name order; anything else is
+/// taken as a file path as is.
+pub fn expand_inputs(inputs: &[String]) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+
+    for input in inputs {
+        let path = Path::new(input);
+
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = fs::read_dir(path).map_err(|e| Error::file(path, e))?
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file() && is_media_file(path))
+                .collect();
+            entries.sort();
+            files.extend(entries);
+        } else if !path.exists() && input.contains(['*', '?', '[']) {
+            let matches = glob::glob(input)
+                .map_err(|e| Error::InvalidInput(format!("Invalid pattern '{}': {}", input, e)))?;
+            // Unreadable entries are skipped like non-media files in a
+            // directory.
+            files.extend(matches.filter_map(Result::ok).filter(|path| path.is_file()));
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+
+    Ok(files)
+}
+
+fn is_media_file(path: &Path) -> bool {
+    let is_video = path.extension()
+        .map(|ext| VIDEO_EXTENSIONS.iter().any(|video| ext.eq_ignore_ascii_case(video)))
+        .unwrap_or(false);
+    is_video || is_image_file(path)
+}
+
+/// The files to play, the order they play in and where playback is.
+pub struct Playlist {
+    items: Vec<PathBuf>,
+    // Indices into `items` in play order, which differs from the input
+    // order while shuffled.
+    order: Vec<usize>,
+    // Position in `order` of the item playing.
+    position: usize,
+    shuffle: bool,
+    repeat: RepeatMode,
+    // With a loop count, how many times the playlist plays through, and
+    // which pass is playing (0-based).
+    loop_count: Option<u32>,
+    pass: u32,
+}
+
+impl Playlist {
+    /// A playlist starting at the first of `items`, which must not be empty.
+    pub fn new(items: Vec<PathBuf>, repeat: RepeatMode) -> Self {
+        assert!(!items.is_empty(), "playlist needs at least one item");
+        Playlist {
+            order: (0..items.len()).collect(),
+            items,
+            position: 0,
+            shuffle: false,
+            repeat,
+            loop_count: None,
+            pass: 0,
+        }
+    }
+
+    /// Plays the playlist `count` times through and then stops, instead of
+    /// repeating forever. Implies repeat-all.
+    pub fn set_loop_count(&mut self, count: u32) {
+        self.loop_count = Some(count.max(1));
+        self.repeat = RepeatMode::All;
+    }
+
+    /// The current pass and the total, 1-based, when a loop count is set.
+    pub fn loop_progress(&self) -> Option<(u32, u32)> {
+        self.loop_count.map(|count| (self.pass + 1, count))
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Always false, since a playlist can't be created empty.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn current(&self) -> &Path {
+        &self.items[self.order[self.position]]
+    }
+
+    /// 1-based position of the current item in play order.
+    pub fn position(&self) -> usize {
+        self.position + 1
+    }
+
+    pub fn repeat(&self) -> RepeatMode {
+        self.repeat
+    }
+
+    pub fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.repeat = repeat;
+    }
+
+    pub fn is_shuffled(&self) -> bool {
+        self.shuffle
+    }
+
+    /// Switches between input order and a random order. The current item
+    /// keeps playing either way; when shuffling, everything else comes after
+    /// it in random order.
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        let current = self.order[self.position];
+        self.shuffle = shuffle;
+
+        if shuffle {
+            let mut rest: Vec<usize> = (0..self.items.len()).filter(|&index| index != current).collect();
+            shuffle_indices(&mut rest);
+            self.order = std::iter::once(current).chain(rest).collect();
+            self.position = 0;
+        } else {
+            self.order = (0..self.items.len()).collect();
+            self.position = current;
+        }
+    }
+
+    /// Called when the current item finishes: whether it should start over
+    /// rather than move on, which the player handles without reopening the
+    /// file. Starting a single-item playlist over counts as a new pass.
+    pub fn restart_current(&mut self) -> bool {
+        match self.repeat {
+            RepeatMode::One => true,
+            RepeatMode::All if self.items.len() == 1 => self.next_pass(),
+            RepeatMode::All | RepeatMode::Off => false,
+        }
+    }
+
+    /// Moves on after the current item finished. Returns false when the
+    /// playlist is over.
+    pub fn advance(&mut self) -> bool {
+        if self.repeat == RepeatMode::One {
+            return true;
+        }
+        self.skip_forward()
+    }
+
+    /// Moves to the next item on request, regardless of repeat-one. Returns
+    /// false when the current item was the last and repeat is off or the
+    /// loop count has been reached.
+    pub fn skip_forward(&mut self) -> bool {
+        if self.position + 1 < self.order.len() {
+            self.position += 1;
+            true
+        } else if self.repeat != RepeatMode::Off && self.next_pass() {
+            self.wrap_around();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Starts another pass through the playlist, unless the loop count has
+    /// been reached.
+    fn next_pass(&mut self) -> bool {
+        match self.loop_count {
+            Some(count) if self.pass + 1 >= count => false,
+            _ => {
+                self.pass += 1;
+                true
+            },
+        }
+    }
+
+    /// Moves to the previous item. At the first item this wraps to the last
+    /// with repeat on, and stays put otherwise.
+    pub fn skip_back(&mut self) {
+        if self.position > 0 {
+            self.position -= 1;
+        } else if self.repeat != RepeatMode::Off {
+            self.position = self.order.len() - 1;
+        }
+    }
+
+    fn wrap_around(&mut self) {
+        // Each pass through a shuffled playlist gets a new order.
+        if self.shuffle {
+            shuffle_indices(&mut self.order);
+        }
+        self.position = 0;
+    }
+}
+
+/// Fisher-Yates shuffle driven by a xorshift generator seeded from the
+/// clock; playlist order doesn't need anything stronger.
+fn shuffle_indices(indices: &mut [usize]) {
+    let mut state = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64)
+        .unwrap_or(0)
+        | 1;
+
+    for i in (1..indices.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let j = (state % (i as u64 + 1)) as usize;
+        indices.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+    use super::*;
+
+    fn playlist(names: &[&str], repeat: RepeatMode) -> Playlist {
+        Playlist::new(names.iter().map(PathBuf::from).collect(), repeat)
+    }
+
+    /// A fresh directory under the system temp directory holding empty
+    /// files called `names`, removed when the test is done with it.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn with_files(test: &str, names: &[&str]) -> Self {
+            let dir = std::env::temp_dir().join(format!("ascii-rendering-{}-{}", test, process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            for name in names {
+                fs::write(dir.join(name), b"").unwrap();
+            }
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn file_names(files: &[PathBuf]) -> Vec<String> {
+        files.iter().map(|file| file.file_name().unwrap().to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn directory_expands_to_sorted_media_files() {
+        let dir = TempDir::with_files("dir", &["b.mkv", "a.MP4", "notes.txt", "c.gif", "d"]);
+        fs::create_dir(dir.0.join("sub.mp4")).unwrap();
+
+        let files = expand_inputs(&[dir.0.display().to_string()]).unwrap();
+        assert_eq!(file_names(&files), ["a.MP4", "b.mkv", "c.gif"]);
+    }
+
+    #[test]
+    fn glob_expands_to_matching_files() {
+        let dir = TempDir::with_files("glob", &["clip2.mp4", "clip1.mp4", "clip1.srt"]);
+        let pattern = dir.0.join("clip*.mp4").display().to_string();
+
+        let files = expand_inputs(&[pattern]).unwrap();
+        assert_eq!(file_names(&files), ["clip1.mp4", "clip2.mp4"]);
+    }
+
+    #[test]
+    fn other_inputs_are_kept_as_is() {
+        let inputs = [String::from("missing.mp4"), String::from("https://example.com/video.mp4")];
+        let files = expand_inputs(&inputs).unwrap();
+        assert_eq!(files, [PathBuf::from("missing.mp4"), PathBuf::from("https://example.com/video.mp4")]);
+    }
+
+    #[test]
+    fn repeat_off_stops_after_last_item() {
+        let mut list = playlist(&["a", "b"], RepeatMode::Off);
+        assert!(!list.restart_current());
+        assert!(list.advance());
+        assert_eq!(list.current(), Path::new("b"));
+        assert!(!list.advance());
+        assert_eq!(list.current(), Path::new("b"));
+
+        list.skip_back();
+        list.skip_back();
+        assert_eq!(list.current(), Path::new("a"));
+    }
+
+    #[test]
+    fn repeat_all_wraps_both_ways() {
+        let mut list = playlist(&["a", "b", "c"], RepeatMode::All);
+        list.skip_back();
+        assert_eq!(list.current(), Path::new("c"));
+        assert_eq!(list.position(), 3);
+        assert!(list.advance());
+        assert_eq!(list.current(), Path::new("a"));
+    }
+
+    #[test]
+    fn repeat_one_stays_on_item_until_skipped() {
+        let mut list = playlist(&["a", "b"], RepeatMode::One);
+        assert!(list.restart_current());
+        assert!(list.advance());
+        assert_eq!(list.current(), Path::new("a"));
+        assert!(list.skip_forward());
+        assert_eq!(list.current(), Path::new("b"));
+    }
+
+    #[test]
+    fn single_item_with_repeat_all_restarts() {
+        let mut list = playlist(&["a"], RepeatMode::All);
+        assert!(list.restart_current());
+        assert!(!playlist(&["a"], RepeatMode::Off).restart_current());
+    }
+
+    #[test]
+    fn repeat_mode_cycles_and_parses() {
+        assert_eq!(RepeatMode::Off.cycle(), RepeatMode::All);
+        assert_eq!(RepeatMode::All.cycle(), RepeatMode::One);
+        assert_eq!(RepeatMode::One.cycle(), RepeatMode::Off);
+        for mode in [RepeatMode::Off, RepeatMode::All, RepeatMode::One] {
+            assert_eq!(RepeatMode::parse(mode.name()), Some(mode));
+        }
+        assert_eq!(RepeatMode::parse("NONE"), Some(RepeatMode::Off));
+        assert_eq!(RepeatMode::parse("twice"), None);
+    }
+
+    #[test]
+    fn shuffle_keeps_current_item_first_and_every_item_once() {
+        let names: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let mut list = playlist(&names, RepeatMode::Off);
+        list.skip_forward();
+        list.skip_forward();
+
+        list.set_shuffle(true);
+        assert!(list.is_shuffled());
+        assert_eq!(list.current(), Path::new("2"));
+        assert_eq!(list.position(), 1);
+
+        let mut seen = vec![list.current().to_path_buf()];
+        while list.advance() {
+            seen.push(list.current().to_path_buf());
+        }
+        seen.sort();
+        let mut expected: Vec<PathBuf> = names.iter().map(PathBuf::from).collect();
+        expected.sort();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn unshuffle_returns_to_input_order() {
+        let mut list = playlist(&["a", "b", "c", "d"], RepeatMode::Off);
+        list.set_shuffle(true);
+        list.skip_forward();
+        let current = list.current().to_path_buf();
+
+        list.set_shuffle(false);
+        assert_eq!(list.current(), current);
+        let index = ["a", "b", "c", "d"].iter().position(|name| Path::new(name) == current).unwrap();
+        assert_eq!(list.position(), index + 1);
+    }
+
+    #[test]
+    fn directory_includes_every_image_format() {
+        let dir = TempDir::with_files("images", &["a.png", "b.JPG", "c.webp", "d.tiff", "e.bmp", "f.apng", "g.svg"]);
+
+        let files = expand_inputs(&[dir.0.display().to_string()]).unwrap();
+        assert_eq!(file_names(&files), ["a.png", "b.JPG", "c.webp", "d.tiff", "e.bmp", "f.apng"]);
+    }
+
+    #[test]
+    fn loop_count_limits_passes() {
+        let mut list = playlist(&["a", "b"], RepeatMode::Off);
+        list.set_loop_count(2);
+        assert_eq!(list.repeat(), RepeatMode::All);
+        assert_eq!(list.loop_progress(), Some((1, 2)));
+
+        assert!(list.advance());
+        assert!(list.advance());
+        assert_eq!(list.current(), Path::new("a"));
+        assert_eq!(list.loop_progress(), Some((2, 2)));
+        assert!(list.advance());
+        assert!(!list.advance());
+    }
+
+    #[test]
+    fn loop_count_restarts_single_item() {
+        let mut list = playlist(&["a"], RepeatMode::Off);
+        list.set_loop_count(3);
+        assert!(list.restart_current());
+        assert!(list.restart_current());
+        assert!(!list.restart_current());
+        assert_eq!(list.loop_progress(), Some((3, 3)));
+    }
+
+    #[test]
+    fn shuffle_indices_is_a_permutation() {
+        let mut indices: Vec<usize> = (0..100).collect();
+        shuffle_indices(&mut indices);
+        indices.sort();
+        assert_eq!(indices, (0..100).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,274 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use crate::error::Error;
+
+/// A chapter as stored in the container.
+#[derive(Clone, Debug)]
+pub struct Chapter {
+    pub start: f64,
+    pub end: f64,
+    pub title: String,
+}
+
+/// An audio stream as reported by ffprobe.
+#[derive(Clone, Debug)]
+pub struct AudioTrack {
+    /// Index of the stream within the container, as used by `-map 0:N`.
+    pub stream_index: usize,
+    pub codec: String,
+    pub channels: Option<u32>,
+    pub language: Option<String>,
+    pub title: Option<String>,
+}
+
+impl AudioTrack {
+    /// Short name for the status bar, e.g. "jpn (Commentary)".
+    pub fn label(&self) -> String {
+        match (&self.language, &self.title) {
+            (Some(language), Some(title)) => format!("{} ({})", language, title),
+            (Some(language), None) => language.clone(),
+            (None, Some(title)) => title.clone(),
+            (None, None) => format!("#{}", self.stream_index),
+        }
+    }
+}
+
+/// What ffprobe found out about a video.
+#[derive(Clone, Debug)]
+pub struct MediaInfo {
+    pub path: PathBuf,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub frame_count: Option<u64>,
+    pub duration: Option<f64>,
+    pub chapters: Vec<Chapter>,
+    pub audio_tracks: Vec<AudioTrack>,
+}
+
+impl MediaInfo {
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        self.width.zip(self.height)
+    }
+
+    pub fn chapter_at(&self, timestamp: f64) -> Option<&Chapter> {
+        self.chapters
+            .iter()
+            .find(|chapter| chapter.start <= timestamp && timestamp < chapter.end)
+    }
+
+    /// Index into `audio_tracks` of the track with 1-based position `number`.
+    pub fn audio_track_number(&self, number: usize) -> Result<usize, Error> {
+        if number == 0 || number > self.audio_tracks.len() {
+            return Err(Error::InvalidInput(
+                format!("Audio track {} does not exist ({} available)", number, self.audio_tracks.len())
+            ));
+        }
+
+        Ok(number - 1)
+    }
+
+    /// Index into `audio_tracks` of the first track tagged with `language`
+    /// (e.g. "jpn").
+    pub fn audio_track_language(&self, language: &str) -> Result<usize, Error> {
+        self.audio_tracks
+            .iter()
+            .position(|track| {
+                track.language
+                    .as_deref()
+                    .map(|tag| tag.eq_ignore_ascii_case(language))
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| Error::InvalidInput(
+                format!("No audio track with language '{}'", language)
+            ))
+    }
+}
+
+/// Reads a video's metadata with ffprobe. The stream dimensions, frame count
+/// and duration are always read; chapters and audio tracks take an extra
+/// ffprobe run each and can be skipped.
+#[derive(Clone, Debug)]
+pub struct Probe {
+    path: PathBuf,
+    chapters: bool,
+    audio_tracks: bool,
+}
+
+impl Probe {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Probe {
+            path: path.as_ref().to_path_buf(),
+            chapters: true,
+            audio_tracks: true,
+        }
+    }
+
+    pub fn chapters(mut self, enabled: bool) -> Self {
+        self.chapters = enabled;
+        self
+    }
+
+    pub fn audio_tracks(mut self, enabled: bool) -> Self {
+        self.audio_tracks = enabled;
+        self
+    }
+
+    pub fn run(&self) -> Result<MediaInfo, Error> {
+        if !self.path.exists() {
+            return Err(Error::NotFound(self.path.clone()));
+        }
+
+        let output = self.ffprobe(&[
+            "-select_streams", "v:0",
+            "-show_entries", "stream=width,height,nb_frames,duration",
+            "-of", "csv=p=0",
+        ])?;
+
+        if !output.status.success() {
+            return Err(self.failed(&output));
+        }
+
+        let mut info = MediaInfo {
+            path: self.path.clone(),
+            width: None,
+            height: None,
+            frame_count: None,
+            duration: None,
+            chapters: Vec::new(),
+            audio_tracks: Vec::new(),
+        };
+
+        let output_str = String::from_utf8_lossy(&output.stdout);
+        let parts: Vec<&str> = output_str.trim().split(',').collect();
+
+        if parts.len() >= 2 {
+            info.width = parts[0].parse::<u32>().ok();
+            info.height = parts[1].parse::<u32>().ok();
+
+            if parts.len() >= 3 {
+                info.frame_count = parts[2].parse::<u64>().ok();
+            }
+
+            if parts.len() >= 4 {
+                info.duration = parts[3].parse::<f64>().ok();
+            }
+        }
+
+        // Chapters and audio track details are optional, so failing to read
+        // them isn't fatal.
+        if self.chapters {
+            info.chapters = self.load_chapters().unwrap_or_default();
+        }
+        if self.audio_tracks {
+            info.audio_tracks = self.load_audio_tracks().unwrap_or_default();
+        }
+
+        Ok(info)
+    }
+
+    fn ffprobe(&self, args: &[&str]) -> Result<Output, Error> {
+        Command::new("ffprobe")
+            .args(["-v", "error"])
+            .args(args)
+            .arg(&self.path)
+            .output()
+            .map_err(|e| match e.kind() {
+                ErrorKind::NotFound | ErrorKind::PermissionDenied => Error::DecoderMissing { program: "ffprobe", source: e },
+                _ => Error::Io(e),
+            })
+    }
+
+    /// The error for an ffprobe run that exited with an error, with the
+    /// first line it printed as the reason.
+    fn failed(&self, output: &Output) -> Error {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().next().unwrap_or("ffprobe failed").trim();
+        // ffprobe starts its messages with the file name.
+        let prefix = format!("{}: ", self.path.display());
+        let reason = reason.strip_prefix(&prefix).unwrap_or(reason);
+        Error::ProbeFailed {
+            path: self.path.clone(),
+            reason: reason.to_string(),
+        }
+    }
+
+    fn load_chapters(&self) -> Result<Vec<Chapter>, Error> {
+        let output = self.ffprobe(&[
+            "-show_entries", "chapter=start_time,end_time:chapter_tags=title",
+            "-of", "csv=p=0",
+        ])?;
+
+        if !output.status.success() {
+            return Err(self.failed(&output));
+        }
+
+        let output_str = String::from_utf8_lossy(&output.stdout);
+        let chapters = output_str
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(3, ',');
+                let start = parts.next()?.parse::<f64>().ok()?;
+                let end = parts.next()?.parse::<f64>().ok()?;
+                // ffprobe quotes CSV fields that contain the separator.
+                let title = parts.next()
+                    .map(|title| title.trim_matches('"').replace("\"\"", "\""))
+                    .unwrap_or_default();
+                Some(Chapter { start, end, title })
+            })
+            .collect();
+
+        Ok(chapters)
+    }
+
+    fn load_audio_tracks(&self) -> Result<Vec<AudioTrack>, Error> {
+        let output = self.ffprobe(&[
+            "-select_streams", "a",
+            "-show_entries", "stream=index,codec_name,channels:stream_tags=language,title",
+            "-of", "compact=p=0",
+        ])?;
+
+        if !output.status.success() {
+            return Err(self.failed(&output));
+        }
+
+        // Compact output is one `key=value|key=value` line per stream, which
+        // unlike CSV tells a missing language apart from a missing title.
+        let output_str = String::from_utf8_lossy(&output.stdout);
+        let tracks = output_str
+            .lines()
+            .filter_map(|line| {
+                let mut track = AudioTrack {
+                    stream_index: 0,
+                    codec: String::new(),
+                    channels: None,
+                    language: None,
+                    title: None,
+                };
+                let mut has_index = false;
+
+                for field in line.split('|') {
+                    let (key, value) = match field.split_once('=') {
+                        Some(pair) => pair,
+                        None => continue,
+                    };
+                    match key {
+                        "index" => {
+                            track.stream_index = value.parse().ok()?;
+                            has_index = true;
+                        },
+                        "codec_name" => track.codec = value.to_string(),
+                        "channels" => track.channels = value.parse().ok(),
+                        "tag:language" => track.language = Some(value.to_string()),
+                        "tag:title" => track.title = Some(value.to_string()),
+                        _ => {}
+                    }
+                }
+
+                if has_index { Some(track) } else { None }
+            })
+            .collect();
+
+        Ok(tracks)
+    }
+}
@@ -0,0 +1,145 @@
+use std::io::{ErrorKind, Read};
+use image::imageops::{self, FilterType};
+use image::RgbImage;
+use crate::error::Error;
+use crate::frame_source::{scaled_size, DecodedFrame, FrameSource, SourceMetadata};
+
+/// Raw RGB24 frames of a declared size read back to back from a stream,
+/// such as stdin fed by `ffmpeg -f rawvideo -pix_fmt rgb24 -`. The stream
+/// carries no timing, so frames are spaced at a fixed rate. It can't be
+/// rewound either: seeking forward skips frames, seeking back fails.
+pub struct RawRgbSource<R: Read> {
+    reader: R,
+    width: u32,
+    height: u32,
+    frame_rate: f64,
+    max_width: u32,
+    // Index of the next frame in the stream.
+    position: u64,
+}
+
+impl<R: Read> RawRgbSource<R> {
+    pub fn new(reader: R, width: u32, height: u32, frame_rate: f64, max_width: u32) -> Self {
+        RawRgbSource {
+            reader,
+            width,
+            height,
+            frame_rate,
+            max_width,
+            position: 0,
+        }
+    }
+
+    /// Reads the next frame's pixels. Returns `None` at the end of the
+    /// stream, including when it ends partway through a frame.
+    fn read_frame(&mut self) -> Result<Option<RgbImage>, Error> {
+        let mut pixels = vec![0; self.width as usize * self.height as usize * 3];
+        match self.reader.read_exact(&mut pixels) {
+            Ok(()) => {},
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(Error::Io(e)),
+        }
+        self.position += 1;
+
+        Ok(RgbImage::from_raw(self.width, self.height, pixels))
+    }
+}
+
+impl<R: Read> FrameSource for RawRgbSource<R> {
+    fn metadata(&self) -> SourceMetadata {
+        let (width, height) = scaled_size(self.width, self.height, self.max_width);
+        SourceMetadata {
+            width,
+            height,
+            frame_rate: self.frame_rate,
+            frame_count: None,
+            duration: None,
+        }
+    }
+
+    fn next_frame(&mut self) -> Result<Option<DecodedFrame>, Error> {
+        let timestamp = self.position as f64 / self.frame_rate;
+        let image = match self.read_frame()? {
+            Some(image) => image,
+            None => return Ok(None),
+        };
+
+        let (width, height) = scaled_size(self.width, self.height, self.max_width);
+        let image = if width == self.width {
+            image
+        } else {
+            imageops::resize(&image, width, height, FilterType::Triangle)
+        };
+
+        Ok(Some(DecodedFrame { image, alpha: None, timestamp }))
+    }
+
+    fn seek(&mut self, timestamp: f64) -> Result<Option<DecodedFrame>, Error> {
+        let target = (timestamp.max(0.0) * self.frame_rate + 1e-6) as u64;
+        if target < self.position {
+            return Err(Error::InvalidInput(String::from("Raw input can't seek backwards")));
+        }
+
+        while self.position < target {
+            if self.read_frame()?.is_none() {
+                return Ok(None);
+            }
+        }
+        self.next_frame()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    /// `frames` 2x1 frames whose pixels all hold their frame's index, at
+    /// 10 frames per second, plus `extra` bytes of a frame cut short.
+    fn source(frames: u8, extra: usize) -> RawRgbSource<Cursor<Vec<u8>>> {
+        let mut bytes: Vec<u8> = (0..frames).flat_map(|frame| [frame; 6]).collect();
+        bytes.extend(std::iter::repeat_n(255, extra));
+        RawRgbSource::new(Cursor::new(bytes), 2, 1, 10.0, 80)
+    }
+
+    fn index(frame: &DecodedFrame) -> u8 {
+        frame.image.get_pixel(0, 0)[0]
+    }
+
+    #[test]
+    fn frames_are_spaced_at_the_frame_rate() {
+        let mut source = source(3, 0);
+        let frames: Vec<(u8, f64)> = std::iter::from_fn(|| source.next_frame().unwrap())
+            .map(|frame| (index(&frame), frame.timestamp))
+            .collect();
+        assert_eq!(frames, [(0, 0.0), (1, 0.1), (2, 0.2)]);
+        assert_eq!((source.metadata().width, source.metadata().height), (2, 1));
+    }
+
+    #[test]
+    fn truncated_last_frame_ends_the_stream() {
+        let mut source = source(2, 4);
+        assert!(source.next_frame().unwrap().is_some());
+        assert!(source.next_frame().unwrap().is_some());
+        assert!(source.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn seeking_forward_skips_frames() {
+        let mut source = source(5, 0);
+        let frame = source.seek(0.3).unwrap().unwrap();
+        assert_eq!((index(&frame), frame.timestamp), (3, 0.3));
+        assert_eq!(index(&source.next_frame().unwrap().unwrap()), 4);
+        // Seeking past the end runs out of frames.
+        assert!(source.seek(2.0).unwrap().is_none());
+    }
+
+    #[test]
+    fn seeking_back_fails() {
+        let mut source = source(5, 0);
+        source.seek(0.2).unwrap();
+        assert!(matches!(source.seek(0.1), Err(Error::InvalidInput(_))));
+        // The current position is still fine.
+        assert_eq!(index(&source.seek(0.3).unwrap().unwrap()), 3);
+    }
+}
@@ -0,0 +1,152 @@
+use std::io::{self, Write};
+use crate::cells::{CellGrid, ColorMode};
+use crate::frame_diff::{write_sgr, FrameDiffer};
+
+/// Writes rendered frames straight to an output stream without any terminal
+/// UI. Frames go through a `FrameDiffer`, so after the first full frame only
+/// changed cells are sent, using nothing but cursor positioning and SGR color
+/// sequences. Recordings replay in any VT100-compatible terminal.
+pub struct RawFrameWriter<W: Write> {
+    out: W,
+    differ: FrameDiffer,
+}
+
+impl<W: Write> RawFrameWriter<W> {
+    pub fn new(out: W, color_mode: ColorMode) -> Self {
+        RawFrameWriter {
+            out,
+            differ: FrameDiffer::new(color_mode),
+        }
+    }
+
+    pub fn write_frame(&mut self, frame: &CellGrid) -> io::Result<()> {
+        let bytes = self.differ.diff(frame);
+        self.out.write_all(&bytes)?;
+        self.out.flush()
+    }
+
+    /// Total number of bytes produced so far, including any a closed pipe
+    /// turned away.
+    pub fn bytes_written(&self) -> u64 {
+        self.differ.bytes_written()
+    }
+
+    /// Resets colors and moves the cursor below the frame. Returns the total
+    /// number of bytes written over the whole playback.
+    pub fn finish(&mut self) -> io::Result<u64> {
+        let bytes = self.differ.finish();
+        self.out.write_all(&bytes)?;
+        self.out.flush()?;
+        Ok(self.differ.bytes_written())
+    }
+}
+
+/// Writes `grid` as lines of text that stay in the scrollback, such as an
+/// image printed by the viewer. Colors are set only where they change within
+/// a row and reset at the end of each.
+pub fn write_lines<W: Write>(out: &mut W, grid: &CellGrid, color_mode: ColorMode) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    for y in 0..grid.height() {
+        let mut colors = None;
+        for cell in grid.row(y) {
+            if colors != Some((cell.fg, cell.bg)) {
+                write_sgr(&mut bytes, color_mode, Some(cell.fg), Some(cell.bg));
+                colors = Some((cell.fg, cell.bg));
+            }
+            let mut buf = [0u8; 4];
+            bytes.extend_from_slice(cell.glyph.encode_utf8(&mut buf).as_bytes());
+        }
+        if color_mode != ColorMode::Mono {
+            bytes.extend_from_slice(b"\x1b[0m");
+        }
+        bytes.push(b'\n');
+    }
+    out.write_all(&bytes)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cells::{Cell, Rgb};
+    use super::*;
+
+    fn grid(rows: &[&str]) -> CellGrid {
+        let mut grid = CellGrid::new(rows[0].chars().count(), rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, glyph) in row.chars().enumerate() {
+                grid.set(x, y, Cell { glyph, ..Cell::default() });
+            }
+        }
+        grid
+    }
+
+    /// "ab" in red on the terminal's background, then "c" in green on blue.
+    fn colored() -> CellGrid {
+        let mut grid = grid(&["abc", "abc"]);
+        for y in 0..2 {
+            for x in 0..2 {
+                grid.set(x, y, Cell { glyph: grid.row(y)[x].glyph, fg: Rgb::new(255, 0, 0), bg: None });
+            }
+            grid.set(2, y, Cell { glyph: 'c', fg: Rgb::new(0, 255, 0), bg: Some(Rgb::new(0, 0, 255)) });
+        }
+        grid
+    }
+
+    fn lines(grid: &CellGrid, color_mode: ColorMode) -> String {
+        let mut out = Vec::new();
+        write_lines(&mut out, grid, color_mode).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn mono_lines_are_plain_text() {
+        assert_eq!(lines(&grid(&["ab", "cd"]), ColorMode::Mono), "ab\ncd\n");
+        assert_eq!(lines(&colored(), ColorMode::Mono), "abc\nabc\n");
+    }
+
+    #[test]
+    fn color_lines_set_colors_once_per_run_and_reset_each_row() {
+        let row = "\x1b[38;2;255;0;0;49mab\x1b[38;2;0;255;0;48;2;0;0;255mc\x1b[0m\n";
+        assert_eq!(lines(&colored(), ColorMode::TrueColor), row.repeat(2));
+
+        let red = Rgb::new(255, 0, 0).to_ansi256();
+        let green = Rgb::new(0, 255, 0).to_ansi256();
+        let blue = Rgb::new(0, 0, 255).to_ansi256();
+        let row = format!("\x1b[38;5;{};49mab\x1b[38;5;{};48;5;{}mc\x1b[0m\n", red, green, blue);
+        assert_eq!(lines(&colored(), ColorMode::Ansi256), row.repeat(2));
+    }
+
+    #[test]
+    fn frames_after_the_first_send_only_changes() {
+        let mut out = Vec::new();
+        let total = {
+            let mut writer = RawFrameWriter::new(&mut out, ColorMode::Mono);
+            writer.write_frame(&grid(&["ab", "cd"])).unwrap();
+            writer.write_frame(&grid(&["ab", "cx"])).unwrap();
+            writer.finish().unwrap()
+        };
+
+        // A full redraw, then only the changed cell, then the cursor parked
+        // on the row below the frame.
+        assert_eq!(String::from_utf8_lossy(&out), "\x1b[0m\x1b[2J\x1b[1;1Hab\x1b[2;1Hcd\x1b[2;2Hx\x1b[3;1H");
+        assert_eq!(total, out.len() as u64);
+    }
+
+    #[test]
+    fn finish_resets_colors_before_parking_the_cursor() {
+        let mut out = Vec::new();
+        {
+            let mut writer = RawFrameWriter::new(&mut out, ColorMode::TrueColor);
+            writer.write_frame(&colored()).unwrap();
+            writer.finish().unwrap();
+        }
+        assert!(String::from_utf8_lossy(&out).ends_with("c\x1b[0m\x1b[3;1H"));
+    }
+
+    #[test]
+    fn finish_without_frames_leaves_the_cursor_alone() {
+        let mut out = Vec::new();
+        assert_eq!(RawFrameWriter::new(&mut out, ColorMode::Mono).finish().unwrap(), 0);
+        assert!(out.is_empty());
+    }
+}
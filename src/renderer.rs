@@ -0,0 +1,625 @@
+use std::path::Path;
+use std::sync::Arc;
+use image::imageops::{self, FilterType};
+use image::RgbImage;
+use crate::background::clear_transparent;
+use crate::cells::{Cell, CellGrid, Rgb};
+use crate::error::Error;
+use crate::frame_source::DecodedFrame;
+use crate::image_source::image_error;
+
+const ASCII_CHARS: &str = " .,:;i1tfLCG08@";
+
+// Ordered dithering thresholds for braille dots, in sixteenths.
+const BAYER_4X4: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+// Samples per cell when looking for edges. Cells are about twice as tall as
+// they are wide, so this keeps the samples square.
+const EDGE_SAMPLES_X: u32 = 4;
+const EDGE_SAMPLES_Y: u32 = 8;
+
+/// Default minimum gradient strength, on a 0-1 brightness scale, for a cell
+/// to be drawn as an edge.
+pub const DEFAULT_EDGE_THRESHOLD: f32 = 0.5;
+
+// How much of a cell's gradient has to point one way for it to count as an
+// edge rather than texture or noise.
+const MIN_EDGE_COHERENCE: f32 = 0.6;
+
+// Approximate ink coverage of each glyph in a typical monospace font, as
+// 2x3 samples: top left, top right, middle left, middle right, bottom left,
+// bottom right.
+const GLYPH_SHAPES: [(char, [f32; 6]); 32] = [
+    (' ', [0.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+    ('.', [0.0, 0.0, 0.0, 0.0, 0.2, 0.2]),
+    ('\'', [0.2, 0.2, 0.0, 0.0, 0.0, 0.0]),
+    ('"', [0.35, 0.35, 0.0, 0.0, 0.0, 0.0]),
+    ('-', [0.0, 0.0, 0.35, 0.35, 0.0, 0.0]),
+    ('_', [0.0, 0.0, 0.0, 0.0, 0.4, 0.4]),
+    ('~', [0.0, 0.0, 0.3, 0.3, 0.0, 0.0]),
+    (':', [0.0, 0.0, 0.2, 0.2, 0.2, 0.2]),
+    ('=', [0.0, 0.0, 0.5, 0.5, 0.1, 0.1]),
+    ('^', [0.35, 0.35, 0.1, 0.1, 0.0, 0.0]),
+    ('*', [0.3, 0.3, 0.3, 0.3, 0.0, 0.0]),
+    ('+', [0.1, 0.1, 0.45, 0.45, 0.1, 0.1]),
+    ('i', [0.1, 0.1, 0.15, 0.15, 0.15, 0.15]),
+    ('%', [0.3, 0.25, 0.3, 0.3, 0.25, 0.3]),
+    ('/', [0.0, 0.45, 0.25, 0.25, 0.45, 0.0]),
+    ('\\', [0.45, 0.0, 0.25, 0.25, 0.0, 0.45]),
+    ('<', [0.0, 0.35, 0.4, 0.0, 0.0, 0.35]),
+    ('>', [0.35, 0.0, 0.0, 0.4, 0.35, 0.0]),
+    ('[', [0.5, 0.2, 0.45, 0.0, 0.5, 0.2]),
+    (']', [0.2, 0.5, 0.0, 0.45, 0.2, 0.5]),
+    ('L', [0.45, 0.0, 0.45, 0.0, 0.6, 0.45]),
+    ('J', [0.0, 0.45, 0.0, 0.45, 0.45, 0.55]),
+    ('T', [0.6, 0.6, 0.25, 0.25, 0.25, 0.25]),
+    ('Y', [0.45, 0.45, 0.3, 0.3, 0.25, 0.25]),
+    ('F', [0.6, 0.45, 0.55, 0.3, 0.45, 0.0]),
+    ('P', [0.55, 0.55, 0.55, 0.5, 0.45, 0.0]),
+    ('b', [0.45, 0.0, 0.6, 0.45, 0.6, 0.55]),
+    ('d', [0.0, 0.45, 0.45, 0.6, 0.55, 0.6]),
+    ('o', [0.0, 0.0, 0.5, 0.5, 0.5, 0.5]),
+    ('8', [0.5, 0.5, 0.45, 0.45, 0.5, 0.5]),
+    ('#', [0.6, 0.6, 0.7, 0.7, 0.6, 0.6]),
+    ('@', [0.75, 0.75, 0.8, 0.8, 0.75, 0.7]),
+];
+
+// Coverage of the densest glyph above; full brightness maps to it.
+const MAX_GLYPH_COVERAGE: f32 = 0.8;
+
+/// Turns RGB frames into cell grids: a glyph, a foreground and optionally a
+/// background color per cell. The caller picks the grid size, so the same
+/// renderer serves the player, thumbnails and anything else. Renderers are
+/// shared with the decode thread, hence `Send + Sync`.
+pub trait Renderer: Send + Sync {
+    /// Renders `frame` into a grid of `width` by `height` cells.
+    fn render(&self, frame: &RgbImage, width: u32, height: u32) -> CellGrid;
+
+    /// Renders a decoded frame, leaving the cells it is transparent in to
+    /// the terminal's background when it carries an alpha channel.
+    fn render_frame(&self, frame: &DecodedFrame, width: u32, height: u32) -> CellGrid {
+        let mut grid = self.render(&frame.image, width, height);
+        if let Some(alpha) = &frame.alpha {
+            clear_transparent(&mut grid, alpha);
+        }
+        grid
+    }
+
+    /// Opens the image file at `path` and renders it.
+    fn render_file(&self, path: &Path, width: u32, height: u32) -> Result<CellGrid, Error> {
+        let img = image::open(path).map_err(|e| image_error(path, e))?;
+
+        Ok(self.render(&img.into_rgb8(), width, height))
+    }
+}
+
+/// The built-in renderers, by the name used for `--style` and the config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderStyle {
+    Ramp,
+    HalfBlock,
+    Braille,
+    Edges,
+    Shapes,
+}
+
+impl RenderStyle {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "ramp" | "ascii" => Some(RenderStyle::Ramp),
+            "blocks" | "halfblock" | "half-block" => Some(RenderStyle::HalfBlock),
+            "braille" => Some(RenderStyle::Braille),
+            "edges" => Some(RenderStyle::Edges),
+            "shapes" => Some(RenderStyle::Shapes),
+            _ => None,
+        }
+    }
+
+    /// The name `parse` accepts for this style.
+    pub fn name(self) -> &'static str {
+        match self {
+            RenderStyle::Ramp => "ramp",
+            RenderStyle::HalfBlock => "blocks",
+            RenderStyle::Braille => "braille",
+            RenderStyle::Edges => "edges",
+            RenderStyle::Shapes => "shapes",
+        }
+    }
+
+    /// Whether the style only makes sense with colors, since its glyphs
+    /// carry no brightness of their own.
+    pub fn needs_color(self) -> bool {
+        self == RenderStyle::HalfBlock
+    }
+
+    /// A renderer for this style with default settings. `invert` is ignored
+    /// by styles that draw with colors only.
+    pub fn renderer(self, invert: bool) -> Arc<dyn Renderer> {
+        match self {
+            RenderStyle::Ramp => Arc::new(RampRenderer::new().invert(invert)),
+            RenderStyle::HalfBlock => Arc::new(HalfBlockRenderer),
+            RenderStyle::Braille => Arc::new(BrailleRenderer::new().invert(invert)),
+            RenderStyle::Edges => Arc::new(EdgeRenderer::new().invert(invert)),
+            RenderStyle::Shapes => Arc::new(ShapeRenderer::new().invert(invert)),
+        }
+    }
+}
+
+/// One glyph per cell picked by brightness from a character ramp, sparse
+/// for dark cells and dense for bright ones. The classic look, and the only
+/// one that reads well without colors.
+#[derive(Clone, Debug)]
+pub struct RampRenderer {
+    ramp: Vec<char>,
+    invert: bool,
+}
+
+impl RampRenderer {
+    pub fn new() -> Self {
+        RampRenderer {
+            ramp: ASCII_CHARS.chars().collect(),
+            invert: false,
+        }
+    }
+
+    /// Maps bright pixels to sparse glyphs instead of dense ones, for light
+    /// terminal backgrounds.
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+
+    /// Replaces the ramp, ordered from the glyph for black to the glyph for
+    /// white. Empty ramps are ignored.
+    pub fn ramp(mut self, ramp: &str) -> Self {
+        if !ramp.is_empty() {
+            self.ramp = ramp.chars().collect();
+        }
+        self
+    }
+
+    fn glyph(&self, brightness: f32) -> char {
+        let brightness = if self.invert { 1.0 - brightness } else { brightness };
+        let index = (brightness * (self.ramp.len() - 1) as f32) as usize;
+        self.ramp.get(index).copied().unwrap_or(' ')
+    }
+}
+
+impl Default for RampRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Renderer for RampRenderer {
+    fn render(&self, frame: &RgbImage, width: u32, height: u32) -> CellGrid {
+        let (width, height) = (width.max(1), height.max(1));
+        let resized = imageops::resize(frame, width, height, FilterType::Lanczos3);
+        let mut grid = CellGrid::new(width as usize, height as usize);
+
+        for (x, y, pixel) in resized.enumerate_pixels() {
+            grid.set(x as usize, y as usize, Cell {
+                glyph: self.glyph(luma(pixel)),
+                fg: to_rgb(pixel),
+                bg: None,
+            });
+        }
+
+        grid
+    }
+}
+
+/// Upper half blocks with the top pixel as the foreground and the bottom
+/// pixel as the background, for two pixels per cell. Needs a color mode.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HalfBlockRenderer;
+
+impl Renderer for HalfBlockRenderer {
+    fn render(&self, frame: &RgbImage, width: u32, height: u32) -> CellGrid {
+        let (width, height) = (width.max(1), height.max(1));
+        let resized = imageops::resize(frame, width, height * 2, FilterType::Triangle);
+        let mut grid = CellGrid::new(width as usize, height as usize);
+
+        for y in 0..height {
+            for x in 0..width {
+                grid.set(x as usize, y as usize, Cell {
+                    glyph: '▀',
+                    fg: to_rgb(resized.get_pixel(x, y * 2)),
+                    bg: Some(to_rgb(resized.get_pixel(x, y * 2 + 1))),
+                });
+            }
+        }
+
+        grid
+    }
+}
+
+/// Braille patterns with 2x4 dots per cell, dithered so dot density follows
+/// brightness. Four times the detail of the ramp, at the cost of tone.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BrailleRenderer {
+    invert: bool,
+}
+
+impl BrailleRenderer {
+    pub fn new() -> Self {
+        BrailleRenderer { invert: false }
+    }
+
+    /// Raises dots for dark pixels instead of bright ones.
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+}
+
+impl Renderer for BrailleRenderer {
+    fn render(&self, frame: &RgbImage, width: u32, height: u32) -> CellGrid {
+        // Dot bits in the Unicode braille block, indexed by [row][column].
+        const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+        let (width, height) = (width.max(1), height.max(1));
+        let resized = imageops::resize(frame, width * 2, height * 4, FilterType::Triangle);
+        let mut grid = CellGrid::new(width as usize, height as usize);
+
+        for cy in 0..height {
+            for cx in 0..width {
+                let mut bits = 0;
+                // Colors of the raised dots, or of the whole cell if none are.
+                let mut lit = [0u32; 4];
+                let mut all = [0u32; 4];
+
+                for (dy, row) in DOTS.iter().enumerate() {
+                    for (dx, bit) in row.iter().enumerate() {
+                        let (x, y) = (cx * 2 + dx as u32, cy * 4 + dy as u32);
+                        let pixel = resized.get_pixel(x, y);
+                        let brightness = luma(pixel);
+                        let brightness = if self.invert { 1.0 - brightness } else { brightness };
+                        let threshold = (BAYER_4X4[y as usize % 4][x as usize % 4] as f32 + 0.5) / 16.0;
+
+                        accumulate(&mut all, pixel);
+                        if brightness > threshold {
+                            bits |= bit;
+                            accumulate(&mut lit, pixel);
+                        }
+                    }
+                }
+
+                grid.set(cx as usize, cy as usize, Cell {
+                    glyph: if bits == 0 { ' ' } else { char::from_u32(0x2800 + bits).unwrap_or(' ') },
+                    fg: average(if bits == 0 { &all } else { &lit }),
+                    bg: None,
+                });
+            }
+        }
+
+        grid
+    }
+}
+
+/// Line glyphs (`|`, `/`, `-`, `\`) along edges, following their direction,
+/// and the brightness ramp everywhere else. Outlines stay readable in grids
+/// too small for the ramp to show them.
+#[derive(Clone, Debug)]
+pub struct EdgeRenderer {
+    ramp: RampRenderer,
+    threshold: f32,
+}
+
+impl EdgeRenderer {
+    pub fn new() -> Self {
+        EdgeRenderer {
+            ramp: RampRenderer::new(),
+            threshold: DEFAULT_EDGE_THRESHOLD,
+        }
+    }
+
+    /// Inverts the ramp used away from edges.
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.ramp = self.ramp.invert(invert);
+        self
+    }
+
+    /// Minimum gradient strength for an edge; lower finds fainter edges.
+    pub fn threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold.max(0.0);
+        self
+    }
+
+    /// The line glyph for the edge in the samples of cell `(cx, cy)`, if
+    /// there is one clear enough to draw.
+    fn edge_glyph(&self, samples: &[f32], stride: u32, rows: u32, cx: u32, cy: u32) -> Option<char> {
+        let at = |x: i64, y: i64| {
+            let x = x.clamp(0, stride as i64 - 1) as u32;
+            let y = y.clamp(0, rows as i64 - 1) as u32;
+            samples[(y * stride + x) as usize]
+        };
+
+        // Structure tensor of the Sobel gradients over the cell. Unlike the
+        // summed gradient it doesn't cancel out on thin lines, whose two
+        // sides have opposite gradients.
+        let (mut jxx, mut jyy, mut jxy) = (0.0f32, 0.0f32, 0.0f32);
+        for sy in 0..EDGE_SAMPLES_Y {
+            for sx in 0..EDGE_SAMPLES_X {
+                let x = (cx * EDGE_SAMPLES_X + sx) as i64;
+                let y = (cy * EDGE_SAMPLES_Y + sy) as i64;
+                let gx = at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1)
+                    - at(x - 1, y - 1) - 2.0 * at(x - 1, y) - at(x - 1, y + 1);
+                let gy = at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1)
+                    - at(x - 1, y - 1) - 2.0 * at(x, y - 1) - at(x + 1, y - 1);
+                jxx += gx * gx;
+                jyy += gy * gy;
+                jxy += gx * gy;
+            }
+        }
+
+        let energy = jxx + jyy;
+        let count = (EDGE_SAMPLES_X * EDGE_SAMPLES_Y) as f32;
+        if energy <= 0.0 || (energy / count).sqrt() < self.threshold {
+            return None;
+        }
+        let coherence = ((jxx - jyy).powi(2) + 4.0 * jxy * jxy).sqrt() / energy;
+        if coherence < MIN_EDGE_COHERENCE {
+            return None;
+        }
+
+        // Dominant gradient direction, in image coordinates with y down. The
+        // edge runs across it; measure its angle with y up, as drawn.
+        let theta = 0.5 * (2.0 * jxy).atan2(jxx - jyy);
+        let angle = (-theta.cos()).atan2(-theta.sin()).to_degrees().rem_euclid(180.0);
+        Some(['-', '/', '|', '\\'][((angle + 22.5) / 45.0) as usize % 4])
+    }
+}
+
+impl Default for EdgeRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Renderer for EdgeRenderer {
+    fn render(&self, frame: &RgbImage, width: u32, height: u32) -> CellGrid {
+        let (width, height) = (width.max(1), height.max(1));
+        let colors = imageops::resize(frame, width, height, FilterType::Triangle);
+        let (stride, rows) = (width * EDGE_SAMPLES_X, height * EDGE_SAMPLES_Y);
+        let samples: Vec<f32> = imageops::resize(frame, stride, rows, FilterType::Triangle)
+            .pixels()
+            .map(luma)
+            .collect();
+
+        let mut grid = CellGrid::new(width as usize, height as usize);
+        for (x, y, pixel) in colors.enumerate_pixels() {
+            grid.set(x as usize, y as usize, Cell {
+                glyph: self.edge_glyph(&samples, stride, rows, x, y)
+                    .unwrap_or_else(|| self.ramp.glyph(luma(pixel))),
+                fg: to_rgb(pixel),
+                bg: None,
+            });
+        }
+
+        grid
+    }
+}
+
+/// Picks the glyph whose shape best matches each cell, comparing 2x3
+/// brightness samples against the glyphs' ink coverage. Follows contours
+/// more closely than a ramp, which only looks at a cell's average.
+#[derive(Clone, Debug)]
+pub struct ShapeRenderer {
+    invert: bool,
+    contrast: f32,
+}
+
+impl ShapeRenderer {
+    pub fn new() -> Self {
+        ShapeRenderer {
+            invert: false,
+            contrast: 2.0,
+        }
+    }
+
+    /// Matches dark areas to dense glyphs instead of bright ones.
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+
+    /// Exaggerates brightness differences within a cell before matching, so
+    /// shapes win over flat glyphs. 1 matches the samples as they are.
+    pub fn contrast(mut self, contrast: f32) -> Self {
+        self.contrast = contrast.max(1.0);
+        self
+    }
+
+    fn glyph(&self, samples: &mut [f32; 6]) -> char {
+        let peak = samples.iter().cloned().fold(0.0, f32::max);
+        if peak > 0.0 {
+            for sample in samples.iter_mut() {
+                *sample = (*sample / peak).powf(self.contrast) * peak * MAX_GLYPH_COVERAGE;
+            }
+        }
+
+        GLYPH_SHAPES
+            .iter()
+            .map(|(glyph, shape)| {
+                let distance: f32 = shape.iter().zip(samples.iter()).map(|(a, b)| (a - b) * (a - b)).sum();
+                (*glyph, distance)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(' ', |(glyph, _)| glyph)
+    }
+}
+
+impl Default for ShapeRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Renderer for ShapeRenderer {
+    fn render(&self, frame: &RgbImage, width: u32, height: u32) -> CellGrid {
+        let (width, height) = (width.max(1), height.max(1));
+        let colors = imageops::resize(frame, width, height, FilterType::Triangle);
+        let shapes = imageops::resize(frame, width * 2, height * 3, FilterType::Triangle);
+
+        let mut grid = CellGrid::new(width as usize, height as usize);
+        for (x, y, pixel) in colors.enumerate_pixels() {
+            let mut samples = [0.0; 6];
+            for (i, sample) in samples.iter_mut().enumerate() {
+                let brightness = luma(shapes.get_pixel(x * 2 + i as u32 % 2, y * 3 + i as u32 / 2));
+                *sample = if self.invert { 1.0 - brightness } else { brightness };
+            }
+
+            grid.set(x as usize, y as usize, Cell {
+                glyph: self.glyph(&mut samples),
+                fg: to_rgb(pixel),
+                bg: None,
+            });
+        }
+
+        grid
+    }
+}
+
+/// Perceived brightness from 0 to 1.
+fn luma(pixel: &image::Rgb<u8>) -> f32 {
+    (0.2126 * pixel[0] as f32 + 0.7152 * pixel[1] as f32 + 0.0722 * pixel[2] as f32) / 255.0
+}
+
+fn to_rgb(pixel: &image::Rgb<u8>) -> Rgb {
+    Rgb::new(pixel[0], pixel[1], pixel[2])
+}
+
+/// Adds a pixel to running `[r, g, b, count]` sums.
+fn accumulate(sums: &mut [u32; 4], pixel: &image::Rgb<u8>) {
+    sums[0] += pixel[0] as u32;
+    sums[1] += pixel[1] as u32;
+    sums[2] += pixel[2] as u32;
+    sums[3] += 1;
+}
+
+fn average(sums: &[u32; 4]) -> Rgb {
+    let count = sums[3].max(1);
+    Rgb::new((sums[0] / count) as u8, (sums[1] / count) as u8, (sums[2] / count) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: image::Rgb<u8> = image::Rgb([0, 0, 0]);
+    const WHITE: image::Rgb<u8> = image::Rgb([255, 255, 255]);
+
+    fn solid(color: image::Rgb<u8>) -> RgbImage {
+        RgbImage::from_pixel(16, 16, color)
+    }
+
+    /// Black on the left half (or top half) and white on the other.
+    fn step(vertical: bool) -> RgbImage {
+        RgbImage::from_fn(32, 32, |x, y| {
+            let position = if vertical { x } else { y };
+            if position < 16 { BLACK } else { WHITE }
+        })
+    }
+
+    #[test]
+    fn grids_have_the_requested_size() {
+        let frame = RgbImage::from_fn(37, 23, |x, y| image::Rgb([(x * 7) as u8, (y * 11) as u8, 128]));
+        let renderers: [Arc<dyn Renderer>; 5] = [
+            Arc::new(RampRenderer::new()),
+            Arc::new(HalfBlockRenderer),
+            Arc::new(BrailleRenderer::new()),
+            Arc::new(EdgeRenderer::new()),
+            Arc::new(ShapeRenderer::new()),
+        ];
+
+        for renderer in &renderers {
+            for (width, height) in [(1, 1), (5, 3), (80, 24)] {
+                let grid = renderer.render(&frame, width, height);
+                assert_eq!((grid.width(), grid.height()), (width as usize, height as usize));
+            }
+            let grid = renderer.render(&frame, 0, 0);
+            assert_eq!((grid.width(), grid.height()), (1, 1));
+        }
+    }
+
+    #[test]
+    fn ramp_maps_brightness_and_inverts() {
+        let ramp = RampRenderer::new();
+        let inverted = RampRenderer::new().invert(true);
+        let first = ASCII_CHARS.chars().next().unwrap();
+        let last = ASCII_CHARS.chars().last().unwrap();
+
+        assert_eq!(ramp.render(&solid(BLACK), 2, 2).row(0)[0].glyph, first);
+        assert_eq!(ramp.render(&solid(WHITE), 2, 2).row(0)[0].glyph, last);
+        assert_eq!(inverted.render(&solid(BLACK), 2, 2).row(0)[0].glyph, last);
+        assert_eq!(inverted.render(&solid(WHITE), 2, 2).row(0)[0].glyph, first);
+    }
+
+    #[test]
+    fn custom_ramp_replaces_glyphs() {
+        let ramp = RampRenderer::new().ramp("ab");
+        assert_eq!(ramp.render(&solid(BLACK), 1, 1).row(0)[0].glyph, 'a');
+        assert_eq!(ramp.render(&solid(WHITE), 1, 1).row(0)[0].glyph, 'b');
+        assert_eq!(RampRenderer::new().ramp("").glyph(1.0), RampRenderer::new().glyph(1.0));
+    }
+
+    #[test]
+    fn half_blocks_take_top_and_bottom_pixels() {
+        let red = image::Rgb([255, 0, 0]);
+        let blue = image::Rgb([0, 0, 255]);
+        // Two pixels per cell, so the frame isn't scaled at all.
+        let frame = RgbImage::from_fn(1, 2, |_, y| if y == 0 { red } else { blue });
+
+        let cell = HalfBlockRenderer.render(&frame, 1, 1).row(0)[0];
+        assert_eq!(cell.glyph, '▀');
+        assert_eq!(cell.fg, Rgb::new(255, 0, 0));
+        assert_eq!(cell.bg, Some(Rgb::new(0, 0, 255)));
+    }
+
+    #[test]
+    fn braille_raises_every_dot_for_white() {
+        let cell = BrailleRenderer::new().render(&solid(WHITE), 1, 1).row(0)[0];
+        assert_eq!(cell.glyph, '\u{28FF}');
+        assert_eq!(cell.fg, Rgb::new(255, 255, 255));
+
+        assert_eq!(BrailleRenderer::new().render(&solid(BLACK), 1, 1).row(0)[0].glyph, ' ');
+        assert_eq!(BrailleRenderer::new().invert(true).render(&solid(BLACK), 1, 1).row(0)[0].glyph, '\u{28FF}');
+    }
+
+    #[test]
+    fn edges_follow_their_direction() {
+        let edges = EdgeRenderer::new();
+        assert_eq!(edges.render(&step(true), 1, 1).row(0)[0].glyph, '|');
+        assert_eq!(edges.render(&step(false), 1, 1).row(0)[0].glyph, '-');
+    }
+
+    #[test]
+    fn flat_areas_fall_back_to_the_ramp() {
+        let edges = EdgeRenderer::new();
+        let ramp = RampRenderer::new();
+        assert_eq!(edges.render(&solid(WHITE), 1, 1).row(0)[0].glyph, ramp.glyph(1.0));
+        assert_eq!(edges.render(&solid(BLACK), 1, 1).row(0)[0].glyph, ramp.glyph(0.0));
+    }
+
+    #[test]
+    fn shapes_leave_black_empty() {
+        assert_eq!(ShapeRenderer::new().render(&solid(BLACK), 2, 2).row(0)[0].glyph, ' ');
+        assert_ne!(ShapeRenderer::new().render(&solid(WHITE), 2, 2).row(0)[0].glyph, ' ');
+        assert_eq!(ShapeRenderer::new().invert(true).render(&solid(WHITE), 2, 2).row(0)[0].glyph, ' ');
+    }
+
+    #[test]
+    fn styles_parse_by_name() {
+        for style in [RenderStyle::Ramp, RenderStyle::HalfBlock, RenderStyle::Braille, RenderStyle::Edges, RenderStyle::Shapes] {
+            assert_eq!(RenderStyle::parse(style.name()), Some(style));
+        }
+        assert_eq!(RenderStyle::parse("ASCII"), Some(RenderStyle::Ramp));
+        assert_eq!(RenderStyle::parse("sketch"), None);
+    }
+}
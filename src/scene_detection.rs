@@ -0,0 +1,226 @@
+use std::io::{self, Write};
+use image::imageops::{self, FilterType};
+use image::RgbImage;
+use crate::error::Error;
+use crate::frame_source::FrameSourceBuilder;
+use crate::timecode::format_timestamp;
+
+/// Mean absolute luminance difference (0.0 to 1.0) between consecutive
+/// frames above which a scene cut is reported.
+pub const DEFAULT_SCENE_THRESHOLD: f64 = 0.25;
+
+// Frames are compared on a small fixed-size luminance thumbnail so the
+// metric doesn't depend on the source or grid resolution.
+const LUMA_WIDTH: u32 = 64;
+const LUMA_HEIGHT: u32 = 36;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SceneCut {
+    /// Time of the first frame of the new scene, in seconds.
+    pub timestamp: f64,
+    /// Difference to the previous frame, from 0.0 (identical) to 1.0.
+    pub score: f64,
+}
+
+/// Detects scene cuts from a stream of frames using the sum of absolute
+/// differences between consecutive luminance thumbnails.
+pub struct SceneDetector {
+    threshold: f64,
+    previous: Option<Vec<u8>>,
+    cuts: Vec<SceneCut>,
+}
+
+impl SceneDetector {
+    pub fn new(threshold: f64) -> Self {
+        SceneDetector {
+            threshold,
+            previous: None,
+            cuts: Vec::new(),
+        }
+    }
+
+    /// Feeds the next frame in presentation order and returns its difference
+    /// to the previous one. The first frame always scores 0.
+    pub fn push(&mut self, timestamp: f64, image: &RgbImage) -> f64 {
+        self.push_luma(timestamp, luma_thumbnail(image))
+    }
+
+    /// Same as `push` for a thumbnail already made with `luma_thumbnail`,
+    /// which lets callers compute thumbnails in parallel.
+    pub fn push_luma(&mut self, timestamp: f64, luma: Vec<u8>) -> f64 {
+        let score = match &self.previous {
+            Some(previous) => {
+                let sad: u64 = previous
+                    .iter()
+                    .zip(&luma)
+                    .map(|(&a, &b)| (a as i32 - b as i32).unsigned_abs() as u64)
+                    .sum();
+                sad as f64 / (luma.len() as f64 * 255.0)
+            },
+            None => 0.0,
+        };
+
+        if score > self.threshold {
+            self.cuts.push(SceneCut { timestamp, score });
+        }
+
+        self.previous = Some(luma);
+        score
+    }
+
+    /// Forgets the previous frame, so the next one scores 0. Used when the
+    /// frames stop being consecutive, e.g. after a seek.
+    pub fn restart(&mut self) {
+        self.previous = None;
+    }
+
+    /// Cuts found so far, in the order their frames were pushed.
+    pub fn cuts(&self) -> &[SceneCut] {
+        &self.cuts
+    }
+
+    pub fn into_cuts(self) -> Vec<SceneCut> {
+        self.cuts
+    }
+}
+
+pub fn luma_thumbnail(image: &RgbImage) -> Vec<u8> {
+    let small = imageops::resize(image, LUMA_WIDTH, LUMA_HEIGHT, FilterType::Triangle);
+    small
+        .pixels()
+        .map(|p| (0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32) as u8)
+        .collect()
+}
+
+/// Decodes the whole source at low resolution and returns its scene cuts.
+pub fn detect_scenes(source: &FrameSourceBuilder, threshold: f64) -> Result<Vec<SceneCut>, Error> {
+    let mut source = source.clone().decode_width(LUMA_WIDTH * 2).open()?;
+    let mut detector = SceneDetector::new(threshold);
+
+    while let Some(frame) = source.next_frame()? {
+        detector.push(frame.timestamp, &frame.image);
+    }
+
+    Ok(detector.into_cuts())
+}
+
+/// Writes scene cuts as a JSON array of `{timestamp, timecode, score}` objects.
+pub fn write_scenes_json<W: Write>(out: &mut W, cuts: &[SceneCut]) -> io::Result<()> {
+    writeln!(out, "[")?;
+    for (i, cut) in cuts.iter().enumerate() {
+        writeln!(
+            out,
+            "  {{\"timestamp\": {:.3}, \"timecode\": \"{}\", \"score\": {:.4}}}{}",
+            cut.timestamp,
+            format_timestamp(cut.timestamp),
+            cut.score,
+            if i + 1 < cuts.len() { "," } else { "" }
+        )?;
+    }
+    writeln!(out, "]")
+}
+
+/// Writes scene cuts as CSV with a `timestamp,timecode,score` header.
+pub fn write_scenes_csv<W: Write>(out: &mut W, cuts: &[SceneCut]) -> io::Result<()> {
+    writeln!(out, "timestamp,timecode,score")?;
+    for cut in cuts {
+        writeln!(out, "{:.3},{},{:.4}", cut.timestamp, format_timestamp(cut.timestamp), cut.score)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(value: u8) -> RgbImage {
+        RgbImage::from_pixel(32, 18, image::Rgb([value, value, value]))
+    }
+
+    #[test]
+    fn first_frame_scores_zero() {
+        let mut detector = SceneDetector::new(DEFAULT_SCENE_THRESHOLD);
+        assert_eq!(detector.push(0.0, &solid(255)), 0.0);
+        assert!(detector.cuts().is_empty());
+    }
+
+    #[test]
+    fn identical_frames_are_not_cuts() {
+        let mut detector = SceneDetector::new(DEFAULT_SCENE_THRESHOLD);
+        for i in 0..5 {
+            assert_eq!(detector.push(i as f64 * 0.04, &solid(120)), 0.0);
+        }
+        assert!(detector.into_cuts().is_empty());
+    }
+
+    #[test]
+    fn reports_cut_at_the_new_scene() {
+        let mut detector = SceneDetector::new(DEFAULT_SCENE_THRESHOLD);
+        detector.push(0.0, &solid(0));
+        detector.push(0.04, &solid(0));
+        let score = detector.push(0.08, &solid(255));
+        assert!((score - 1.0).abs() < 1e-9);
+        detector.push(0.12, &solid(255));
+
+        let cuts = detector.into_cuts();
+        assert_eq!(cuts.len(), 1);
+        assert_eq!(cuts[0].timestamp, 0.08);
+        assert!((cuts[0].score - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn small_changes_stay_under_threshold() {
+        let mut detector = SceneDetector::new(DEFAULT_SCENE_THRESHOLD);
+        detector.push(0.0, &solid(100));
+        let score = detector.push(0.04, &solid(130));
+        assert!(score > 0.0 && score < DEFAULT_SCENE_THRESHOLD);
+        assert!(detector.cuts().is_empty());
+    }
+
+    #[test]
+    fn restart_forgets_previous_frame() {
+        let mut detector = SceneDetector::new(DEFAULT_SCENE_THRESHOLD);
+        detector.push(0.0, &solid(0));
+        detector.restart();
+        assert_eq!(detector.push(10.0, &solid(255)), 0.0);
+        assert!(detector.cuts().is_empty());
+    }
+
+    #[test]
+    fn thumbnails_have_fixed_size() {
+        let small = luma_thumbnail(&solid(0));
+        let large = luma_thumbnail(&RgbImage::new(640, 360));
+        assert_eq!(small.len(), (LUMA_WIDTH * LUMA_HEIGHT) as usize);
+        assert_eq!(large.len(), small.len());
+    }
+
+    #[test]
+    fn writes_csv_and_json() {
+        let cuts = [
+            SceneCut { timestamp: 1.5, score: 0.5 },
+            SceneCut { timestamp: 62.25, score: 0.75 },
+        ];
+
+        let mut csv = Vec::new();
+        write_scenes_csv(&mut csv, &cuts).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "timestamp,timecode,score\n1.500,0:01.50,0.5000\n62.250,1:02.25,0.7500\n"
+        );
+
+        let mut json = Vec::new();
+        write_scenes_json(&mut json, &cuts).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "[\n  {\"timestamp\": 1.500, \"timecode\": \"0:01.50\", \"score\": 0.5000},\n  \
+             {\"timestamp\": 62.250, \"timecode\": \"1:02.25\", \"score\": 0.7500}\n]\n"
+        );
+    }
+
+    #[test]
+    fn writes_empty_json_array() {
+        let mut json = Vec::new();
+        write_scenes_json(&mut json, &[]).unwrap();
+        assert_eq!(String::from_utf8(json).unwrap(), "[\n]\n");
+    }
+}
@@ -0,0 +1,746 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+use crate::cells::{CellGrid, ColorMode};
+use crate::error::Error;
+use crate::frame_source::{DecodedFrame, FrameSource, FrameSourceBuilder};
+use crate::frame_stream::PlaybackClock;
+use crate::raw_output::RawFrameWriter;
+use crate::renderer::Renderer;
+use crate::viewer::fit_grid;
+
+// Telnet commands and options (RFC 854, 857, 858, 1073).
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const INTERRUPT: u8 = 244;
+const SE: u8 = 240;
+const ECHO: u8 = 1;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+const NAWS: u8 = 31;
+
+// Window size of clients that don't report one, such as plain TCP clients.
+const DEFAULT_WINDOW: (u32, u32) = (80, 24);
+// Reported window sizes are clamped to this, so a client can't make the
+// server allocate a grid of billions of cells.
+const MAX_WINDOW: (u32, u32) = (500, 200);
+// Longest subnegotiation kept; NAWS needs 5 bytes, and anything past this
+// is dropped so a client can't grow the buffer without end.
+const MAX_SUBNEGOTIATION: usize = 64;
+// How often a client waiting for a frame checks for a resize or a quit.
+const CLIENT_POLL: Duration = Duration::from_millis(100);
+// A client that takes longer than this to accept a frame is dropped, so it
+// can't hold on to a thread forever.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+// Clients served at once unless `Server::max_clients` says otherwise. Each
+// takes two threads and a socket.
+const DEFAULT_MAX_CLIENTS: usize = 32;
+// Sent to a client turned away because the server is full.
+const FULL_MESSAGE: &[u8] = b"Sorry, the server is full. Try again later.\r\n";
+
+/// Streams a source as ANSI text to every client that connects over TCP,
+/// the way the classic Star Wars telnet server does. Telnet clients report
+/// their window size, and each gets the video fitted to it; anything else,
+/// such as `nc`, gets 80x24.
+///
+/// One thread decodes the source in real time, looping at the end, and
+/// every client is sent the latest frame, so slow clients skip frames
+/// instead of falling behind. Frames are rendered once per window size and
+/// shared by all clients of that size. Clients past `max_clients` are told
+/// the server is full and disconnected.
+pub struct Server {
+    source: FrameSourceBuilder,
+    renderer: Arc<dyn Renderer>,
+    color_mode: ColorMode,
+    playback_rate: f64,
+    max_fps: Option<f64>,
+    max_clients: usize,
+}
+
+impl Server {
+    pub fn new(source: FrameSourceBuilder, renderer: Arc<dyn Renderer>) -> Self {
+        Server {
+            source,
+            renderer,
+            color_mode: ColorMode::Mono,
+            playback_rate: 1.0,
+            max_fps: None,
+            max_clients: DEFAULT_MAX_CLIENTS,
+        }
+    }
+
+    /// Color mode of the escape sequences sent; the same for every client.
+    pub fn color_mode(mut self, color_mode: ColorMode) -> Self {
+        self.color_mode = color_mode;
+        self
+    }
+
+    /// Playback rate; 1.0 is real time.
+    pub fn speed(mut self, rate: f64) -> Self {
+        if rate > 0.0 {
+            self.playback_rate = rate;
+        }
+        self
+    }
+
+    /// Caps the number of frames sent per second. `None` streams at the
+    /// source's frame rate.
+    pub fn max_fps(mut self, max_fps: Option<f64>) -> Self {
+        self.max_fps = max_fps;
+        self
+    }
+
+    /// Caps the number of clients watching at once; at least one.
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients.max(1);
+        self
+    }
+
+    /// Listens on `address`, e.g. `127.0.0.1:2323`, and serves clients
+    /// until the process is stopped. Returns early only if the source can't
+    /// be opened or the address can't be listened on.
+    pub fn run(&self, address: &str) -> Result<(), Error> {
+        let listener = TcpListener::bind(address)
+            .map_err(|e| Error::Listen { address: address.to_string(), source: e })?;
+        self.serve(listener)
+    }
+
+    /// Serves clients connecting to `listener` until the process is
+    /// stopped, e.g. on a port picked by binding to port 0.
+    pub fn serve(&self, listener: TcpListener) -> Result<(), Error> {
+        let broadcast = Arc::new(Broadcast::default());
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let source = self.source.clone();
+        let (rate, max_fps) = (self.playback_rate, self.max_fps);
+        let decoder_broadcast = Arc::clone(&broadcast);
+
+        // The source holds FFmpeg state that can't move between threads, so
+        // it is opened on the thread that uses it.
+        thread::spawn(move || {
+            let source = match source.open() {
+                Ok(source) => {
+                    let _ = ready_tx.send(Ok(()));
+                    source
+                },
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                },
+            };
+            decode_loop(source, &decoder_broadcast, rate, max_fps);
+        });
+        ready_rx.recv()
+            .map_err(|_| Error::Io(io::Error::other("Decoder thread exited unexpectedly")))??;
+
+        let local_address = listener.local_addr()?;
+        eprintln!("Serving {} on {}, connect with: telnet {} {}",
+            self.source.name(), local_address, local_address.ip(), local_address.port());
+
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                // A connection that failed before it was accepted only
+                // affects that client.
+                Err(e) => {
+                    eprintln!("Warning: Failed to accept a connection: {}", e);
+                    continue;
+                },
+            };
+
+            let peer = stream.peer_addr().map(|address| address.to_string()).unwrap_or_default();
+            // Counted in here rather than on the client's thread, so clients
+            // connecting at the same moment can't all get the last place.
+            let watching = match broadcast.try_join(self.max_clients) {
+                Some(watching) => watching,
+                None => {
+                    eprintln!("{} turned away, {} already watching", peer, self.max_clients);
+                    // A fresh socket takes a line without blocking; if the
+                    // client is already gone there is no one to tell.
+                    let _ = stream.write_all(FULL_MESSAGE);
+                    let _ = stream.shutdown(Shutdown::Both);
+                    continue;
+                },
+            };
+
+            let broadcast = Arc::clone(&broadcast);
+            let renderer = Arc::clone(&self.renderer);
+            let color_mode = self.color_mode;
+            thread::spawn(move || {
+                eprintln!("{} connected ({} watching)", peer, watching);
+                // Errors only mean the client went away.
+                let _ = serve_client(stream, &broadcast, renderer.as_ref(), color_mode);
+                eprintln!("{} disconnected ({} watching)", peer, broadcast.leave());
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The latest decoded frame, and what has been rendered from it, shared
+/// between the decode thread and the clients.
+#[derive(Default)]
+struct Broadcast {
+    // The frame and its sequence number, counting from 1.
+    latest: Mutex<Option<(u64, Arc<DecodedFrame>)>>,
+    frame_ready: Condvar,
+    // One slot per window size that some client has.
+    renders: Mutex<HashMap<(u32, u32), Arc<RenderSlot>>>,
+    clients: Mutex<usize>,
+    clients_changed: Condvar,
+}
+
+/// The latest frame rendered for one window size. Clients of that size take
+/// turns: the first to want a new frame renders it, the others reuse it.
+#[derive(Default)]
+struct RenderSlot {
+    rendered: Mutex<Option<(u64, Arc<CellGrid>)>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A client thread that panicked leaves nothing half-updated behind.
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Broadcast {
+    /// Counts a new client in, unless `max` are already connected. Returns
+    /// how many are connected now.
+    fn try_join(&self, max: usize) -> Option<usize> {
+        let mut clients = lock(&self.clients);
+        if *clients >= max {
+            return None;
+        }
+        *clients += 1;
+        self.clients_changed.notify_all();
+        Some(*clients)
+    }
+
+    fn leave(&self) -> usize {
+        let mut clients = lock(&self.clients);
+        *clients -= 1;
+        *clients
+    }
+
+    /// Blocks while nobody is watching. Returns whether it had to wait.
+    fn wait_for_clients(&self) -> bool {
+        let mut clients = lock(&self.clients);
+        let waited = *clients == 0;
+        while *clients == 0 {
+            clients = self.clients_changed.wait(clients).unwrap_or_else(PoisonError::into_inner);
+        }
+        waited
+    }
+
+    fn publish(&self, frame: DecodedFrame) {
+        {
+            let mut latest = lock(&self.latest);
+            let sequence = latest.as_ref().map_or(1, |(sequence, _)| sequence + 1);
+            *latest = Some((sequence, Arc::new(frame)));
+        }
+        self.frame_ready.notify_all();
+
+        // Slots are held by the clients using them; the rest are for window
+        // sizes nobody has any more.
+        lock(&self.renders).retain(|_, slot| Arc::strong_count(slot) > 1);
+    }
+
+    /// The latest frame once it is newer than `shown`, or whatever is latest
+    /// after `timeout`.
+    fn wait_for_frame(&self, shown: u64, timeout: Duration) -> Option<(u64, Arc<DecodedFrame>)> {
+        let latest = lock(&self.latest);
+        let (latest, _) = self.frame_ready
+            .wait_timeout_while(latest, timeout, |latest| latest.as_ref().is_none_or(|(sequence, _)| *sequence <= shown))
+            .unwrap_or_else(PoisonError::into_inner);
+        latest.as_ref().map(|(sequence, frame)| (*sequence, Arc::clone(frame)))
+    }
+
+    fn slot(&self, window: (u32, u32)) -> Arc<RenderSlot> {
+        Arc::clone(lock(&self.renders).entry(window).or_default())
+    }
+}
+
+impl RenderSlot {
+    /// Frame `sequence` as rendered by `render`, which only runs if no
+    /// other client of this size got there first.
+    fn get<F>(&self, sequence: u64, render: F) -> Arc<CellGrid>
+    where
+        F: FnOnce() -> CellGrid,
+    {
+        let mut rendered = lock(&self.rendered);
+        if let Some((rendered_sequence, grid)) = &*rendered {
+            if *rendered_sequence == sequence {
+                return Arc::clone(grid);
+            }
+        }
+        let grid = Arc::new(render());
+        *rendered = Some((sequence, Arc::clone(&grid)));
+        grid
+    }
+}
+
+/// Decodes the source at `rate` times real time and publishes every frame,
+/// starting over at the end. Decoding pauses while nobody is connected.
+fn decode_loop(mut source: Box<dyn FrameSource>, broadcast: &Broadcast, rate: f64, max_fps: Option<f64>) {
+    let min_interval = max_fps.filter(|fps| *fps > 0.0).map(|fps| 1.0 / fps);
+    let mut clock = PlaybackClock::new();
+    let mut last_sent: Option<f64> = None;
+    // Frames decoded since the source last started over.
+    let mut pass_frames = 0u64;
+    let mut restart = false;
+
+    loop {
+        let frame = if restart { source.seek(0.0) } else { source.next_frame() };
+        restart = false;
+
+        let frame = match frame {
+            Ok(Some(frame)) => frame,
+            // A single frame, such as a still image, simply stays up.
+            Ok(None) if pass_frames <= 1 => return,
+            Ok(None) => {
+                restart = true;
+                pass_frames = 0;
+                last_sent = None;
+                continue;
+            },
+            Err(e) => {
+                eprintln!("Warning: {}, the stream stops at the last frame", e);
+                return;
+            },
+        };
+
+        if pass_frames == 0 || broadcast.wait_for_clients() {
+            clock.seek(frame.timestamp);
+        }
+        pass_frames += 1;
+
+        if let (Some(interval), Some(last)) = (min_interval, last_sent) {
+            // A little slack keeps e.g. 30fps content at 30fps with --max-fps 30.
+            if frame.timestamp - last < interval * 0.99 {
+                continue;
+            }
+        }
+        last_sent = Some(frame.timestamp);
+
+        if let Some(remaining) = clock.until(frame.timestamp, rate) {
+            thread::sleep(remaining);
+        }
+        broadcast.publish(frame);
+    }
+}
+
+/// Sends frames to one client until it quits or disconnects.
+fn serve_client(
+    stream: TcpStream,
+    broadcast: &Broadcast,
+    renderer: &dyn Renderer,
+    color_mode: ColorMode,
+) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let mut control = stream.try_clone()?;
+
+    // Character at a time without local echo, and the window size now and
+    // whenever it changes. The output is UTF-8, which never contains the
+    // IAC byte, so frames need no escaping.
+    control.write_all(&[IAC, WILL, ECHO, IAC, WILL, SUPPRESS_GO_AHEAD, IAC, DO, NAWS])?;
+    control.write_all(b"\x1b[?25l")?;
+
+    let window = Arc::new(Mutex::new(DEFAULT_WINDOW));
+    let quit = Arc::new(AtomicBool::new(false));
+    {
+        let (window, quit) = (Arc::clone(&window), Arc::clone(&quit));
+        let mut input = stream.try_clone()?;
+        thread::spawn(move || {
+            read_input(&mut input, &window, &quit);
+            quit.store(true, Ordering::Relaxed);
+        });
+    }
+
+    let writer = RawFrameWriter::new(stream.try_clone()?, color_mode);
+    let result = write_frames(writer, broadcast, renderer, &window, &quit)
+        .and_then(|()| control.write_all(b"\x1b[?25h\r\n"));
+    // Also ends the input thread if the client is still connected.
+    let _ = stream.shutdown(Shutdown::Both);
+    result
+}
+
+fn write_frames(
+    mut writer: RawFrameWriter<TcpStream>,
+    broadcast: &Broadcast,
+    renderer: &dyn Renderer,
+    window: &Mutex<(u32, u32)>,
+    quit: &AtomicBool,
+) -> io::Result<()> {
+    let mut shown: Option<(u64, (u32, u32))> = None;
+    let mut render_slot: Option<((u32, u32), Arc<RenderSlot>)> = None;
+
+    while !quit.load(Ordering::Relaxed) {
+        let shown_sequence = shown.map_or(0, |(sequence, _)| sequence);
+        let (sequence, frame) = match broadcast.wait_for_frame(shown_sequence, CLIENT_POLL) {
+            Some(latest) => latest,
+            None => continue,
+        };
+        let size = *lock(window);
+        if shown == Some((sequence, size)) {
+            continue;
+        }
+
+        let slot = match render_slot.take() {
+            Some((slot_size, slot)) if slot_size == size => slot,
+            _ => broadcast.slot(size),
+        };
+        let screen = slot.get(sequence, || render_screen(&frame, renderer, size));
+        render_slot = Some((size, slot));
+
+        writer.write_frame(&screen)?;
+        shown = Some((sequence, size));
+    }
+
+    writer.finish().map(|_| ())
+}
+
+/// Handles what the client sends: window sizes, and `q`, Ctrl+C or Ctrl+D
+/// to quit. Returns when the client quits or disconnects.
+fn read_input(input: &mut TcpStream, window: &Mutex<(u32, u32)>, quit: &AtomicBool) {
+    let mut parser = TelnetParser::default();
+    let mut buffer = [0u8; 256];
+
+    while !quit.load(Ordering::Relaxed) {
+        let read = match input.read(&mut buffer) {
+            Ok(0) | Err(_) => return,
+            Ok(read) => read,
+        };
+        for &byte in &buffer[..read] {
+            match parser.feed(byte) {
+                Some(ClientInput::Resize(width, height)) => *lock(window) = (width, height),
+                Some(ClientInput::Quit) => return,
+                None => {},
+            }
+        }
+    }
+}
+
+/// The whole client window: the frame fitted to it and centered. The last
+/// row stays free for the cursor once the client quits.
+fn render_screen(frame: &DecodedFrame, renderer: &dyn Renderer, (columns, rows): (u32, u32)) -> CellGrid {
+    let columns = columns.clamp(1, MAX_WINDOW.0);
+    let rows = rows.clamp(2, MAX_WINDOW.1);
+    let mut screen = CellGrid::new(columns as usize, rows as usize - 1);
+    let (width, height) = fit_grid(
+        frame.image.width(),
+        frame.image.height(),
+        screen.width() as u32,
+        screen.height() as u32,
+    );
+
+    let grid = renderer.render_frame(frame, width, height);
+    let left = (screen.width() - grid.width()) / 2;
+    let top = (screen.height() - grid.height()) / 2;
+    for y in 0..grid.height() {
+        for (x, cell) in grid.row(y).iter().enumerate() {
+            screen.set(left + x, top + y, *cell);
+        }
+    }
+    screen
+}
+
+/// What a client asked for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ClientInput {
+    /// The window is now `columns` by `rows` cells.
+    Resize(u32, u32),
+    Quit,
+}
+
+/// Splits the bytes a telnet client sends into key presses and commands.
+/// Only window size reports are acted on; other options are ignored.
+#[derive(Default)]
+struct TelnetParser {
+    state: TelnetState,
+    subnegotiation: Vec<u8>,
+}
+
+#[derive(Default)]
+enum TelnetState {
+    #[default]
+    Data,
+    Command,
+    // The option byte that follows WILL, WONT, DO or DONT.
+    Option,
+    Subnegotiation,
+    SubnegotiationCommand,
+}
+
+impl TelnetParser {
+    fn feed(&mut self, byte: u8) -> Option<ClientInput> {
+        match self.state {
+            TelnetState::Data => match byte {
+                IAC => self.state = TelnetState::Command,
+                // q, Ctrl+C, Ctrl+D.
+                b'q' | b'Q' | 0x03 | 0x04 => return Some(ClientInput::Quit),
+                _ => {},
+            },
+            TelnetState::Command => {
+                self.state = match byte {
+                    WILL | WONT | DO | DONT => TelnetState::Option,
+                    SB => {
+                        self.subnegotiation.clear();
+                        TelnetState::Subnegotiation
+                    },
+                    INTERRUPT => return Some(ClientInput::Quit),
+                    _ => TelnetState::Data,
+                };
+            },
+            TelnetState::Option => self.state = TelnetState::Data,
+            TelnetState::Subnegotiation => match byte {
+                IAC => self.state = TelnetState::SubnegotiationCommand,
+                _ => self.push_subnegotiation(byte),
+            },
+            TelnetState::SubnegotiationCommand => match byte {
+                // An escaped 255 within the data, e.g. a width of 255.
+                IAC => {
+                    self.push_subnegotiation(IAC);
+                    self.state = TelnetState::Subnegotiation;
+                },
+                SE => {
+                    self.state = TelnetState::Data;
+                    if let [NAWS, w1, w0, h1, h0] = self.subnegotiation[..] {
+                        let width = u16::from_be_bytes([w1, w0]) as u32;
+                        let height = u16::from_be_bytes([h1, h0]) as u32;
+                        // 0 means the client doesn't know.
+                        if width > 0 && height > 0 {
+                            return Some(ClientInput::Resize(width.min(MAX_WINDOW.0), height.min(MAX_WINDOW.1)));
+                        }
+                    }
+                },
+                _ => self.state = TelnetState::Data,
+            },
+        }
+        None
+    }
+
+    fn push_subnegotiation(&mut self, byte: u8) {
+        if self.subnegotiation.len() < MAX_SUBNEGOTIATION {
+            self.subnegotiation.push(byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use image::RgbImage;
+    use crate::frame_source::SourceMetadata;
+    use crate::renderer::RampRenderer;
+
+    fn feed_all(parser: &mut TelnetParser, bytes: &[u8]) -> Vec<ClientInput> {
+        bytes.iter().filter_map(|&byte| parser.feed(byte)).collect()
+    }
+
+    /// A window size report as a client sends it, with 255 bytes doubled.
+    fn naws(width: u16, height: u16) -> Vec<u8> {
+        let mut bytes = vec![IAC, SB, NAWS];
+        for byte in width.to_be_bytes().into_iter().chain(height.to_be_bytes()) {
+            bytes.push(byte);
+            if byte == IAC {
+                bytes.push(IAC);
+            }
+        }
+        bytes.extend([IAC, SE]);
+        bytes
+    }
+
+    #[test]
+    fn reads_window_size() {
+        let mut parser = TelnetParser::default();
+        let mut bytes = vec![IAC, WILL, NAWS];
+        bytes.extend(naws(120, 40));
+        assert_eq!(feed_all(&mut parser, &bytes), [ClientInput::Resize(120, 40)]);
+    }
+
+    #[test]
+    fn unescapes_iac_in_window_size() {
+        let mut parser = TelnetParser::default();
+        // A width of 255 is sent as IAC IAC.
+        let bytes = [IAC, SB, NAWS, 0, IAC, IAC, 0, 50, IAC, SE];
+        assert_eq!(feed_all(&mut parser, &bytes), [ClientInput::Resize(255, 50)]);
+    }
+
+    #[test]
+    fn clamps_huge_window_size() {
+        let mut parser = TelnetParser::default();
+        assert_eq!(feed_all(&mut parser, &naws(65535, 65535)), [ClientInput::Resize(MAX_WINDOW.0, MAX_WINDOW.1)]);
+    }
+
+    #[test]
+    fn ignores_unknown_or_zero_window_size() {
+        let mut parser = TelnetParser::default();
+        assert!(feed_all(&mut parser, &naws(0, 0)).is_empty());
+        // Too short to be a window size.
+        assert!(feed_all(&mut parser, &[IAC, SB, NAWS, 0, 80, IAC, SE]).is_empty());
+        assert_eq!(feed_all(&mut parser, &naws(80, 24)), [ClientInput::Resize(80, 24)]);
+    }
+
+    #[test]
+    fn caps_unterminated_subnegotiation() {
+        let mut parser = TelnetParser::default();
+        let mut bytes = vec![IAC, SB, NAWS];
+        bytes.extend(std::iter::repeat_n(7, 100_000));
+        assert!(feed_all(&mut parser, &bytes).is_empty());
+        assert_eq!(parser.subnegotiation.len(), MAX_SUBNEGOTIATION);
+
+        // Ending it recovers the parser.
+        assert!(feed_all(&mut parser, &[IAC, SE]).is_empty());
+        assert_eq!(feed_all(&mut parser, &naws(100, 30)), [ClientInput::Resize(100, 30)]);
+    }
+
+    #[test]
+    fn truncated_command_does_not_swallow_data() {
+        let mut parser = TelnetParser::default();
+        // IAC followed by a byte that isn't a command is dropped with it.
+        assert!(feed_all(&mut parser, &[IAC, b'x']).is_empty());
+        assert_eq!(feed_all(&mut parser, b"q"), [ClientInput::Quit]);
+    }
+
+    #[test]
+    fn option_bytes_are_not_keys() {
+        let mut parser = TelnetParser::default();
+        // Option 3 is also Ctrl+C.
+        assert!(feed_all(&mut parser, &[IAC, DO, SUPPRESS_GO_AHEAD, IAC, WILL, 4]).is_empty());
+        assert_eq!(feed_all(&mut parser, &[IAC, INTERRUPT]), [ClientInput::Quit]);
+    }
+
+    /// A gray clip of 10 frames that loops forever.
+    struct TestClip {
+        position: u64,
+    }
+
+    impl FrameSource for TestClip {
+        fn metadata(&self) -> SourceMetadata {
+            SourceMetadata { width: 64, height: 32, frame_rate: 20.0, frame_count: Some(10), duration: Some(0.5) }
+        }
+
+        fn next_frame(&mut self) -> Result<Option<DecodedFrame>, Error> {
+            if self.position >= 10 {
+                return Ok(None);
+            }
+            let level = (self.position * 25) as u8;
+            let frame = DecodedFrame {
+                image: RgbImage::from_pixel(64, 32, image::Rgb([level, level, level])),
+                alpha: None,
+                timestamp: self.position as f64 / 20.0,
+            };
+            self.position += 1;
+            Ok(Some(frame))
+        }
+
+        fn seek(&mut self, timestamp: f64) -> Result<Option<DecodedFrame>, Error> {
+            self.position = (timestamp * 20.0) as u64;
+            self.next_frame()
+        }
+    }
+
+    /// Reads from `client` until `done` holds for everything read so far.
+    fn read_until(client: &mut TcpStream, received: &mut Vec<u8>, done: impl Fn(&[u8]) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut buffer = [0u8; 4096];
+        while !done(received) {
+            assert!(Instant::now() < deadline, "timed out waiting for the server");
+            match client.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => received.extend_from_slice(&buffer[..read]),
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {},
+                Err(e) => panic!("read failed: {}", e),
+            }
+        }
+    }
+
+    /// Whether the last full redraw in `output` is `columns` wide: in mono,
+    /// the first row is exactly that many glyphs between two cursor moves.
+    fn last_redraw_width(output: &[u8]) -> Option<usize> {
+        let output = String::from_utf8_lossy(output);
+        let redraw = output.rsplit("\x1b[2J").next()?;
+        let first_row = redraw.strip_prefix("\x1b[1;1H")?;
+        let end = first_row.find("\x1b[2;1H")?;
+        Some(first_row[..end].chars().count())
+    }
+
+    /// Starts serving the test clip on a free loopback port.
+    fn start_server(max_clients: usize) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let source = FrameSourceBuilder::custom("clip", |_| Ok(Box::new(TestClip { position: 0 })));
+            let _ = Server::new(source, Arc::new(RampRenderer::new()))
+                .max_clients(max_clients)
+                .serve(listener);
+        });
+        address
+    }
+
+    fn connect(address: std::net::SocketAddr) -> TcpStream {
+        let client = TcpStream::connect(address).unwrap();
+        client.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+        client
+    }
+
+    #[test]
+    fn loopback_client_is_resized() {
+        let mut client = connect(start_server(DEFAULT_MAX_CLIENTS));
+        let mut received = Vec::new();
+
+        // Negotiation first, then frames at the default size.
+        read_until(&mut client, &mut received, |output| last_redraw_width(output) == Some(80));
+        assert!(received.starts_with(&[IAC, WILL, ECHO, IAC, WILL, SUPPRESS_GO_AHEAD, IAC, DO, NAWS]));
+
+        client.write_all(&naws(40, 12)).unwrap();
+        read_until(&mut client, &mut received, |output| last_redraw_width(output) == Some(40));
+
+        // Quitting parks the cursor below the 11 rows of the 40x12 window.
+        client.write_all(b"q").unwrap();
+        read_until(&mut client, &mut received, |output| output.ends_with(b"\x1b[?25h\r\n"));
+        assert!(received.ends_with(b"\x1b[12;1H\x1b[?25h\r\n"));
+    }
+
+    #[test]
+    fn clients_past_the_limit_are_turned_away() {
+        let address = start_server(1);
+        let mut first = connect(address);
+        let mut received = Vec::new();
+        read_until(&mut first, &mut received, |output| last_redraw_width(output) == Some(80));
+
+        let mut second = connect(address);
+        let mut refused = Vec::new();
+        // Read to the end; the server hangs up after the message.
+        read_until(&mut second, &mut refused, |_| false);
+        assert_eq!(refused, FULL_MESSAGE);
+
+        // Once the first client has left, its place is free again. It is
+        // counted out just after its connection closes, so the next client
+        // may need another try.
+        first.write_all(b"q").unwrap();
+        read_until(&mut first, &mut received, |_| false);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let mut third = connect(address);
+            let mut received = Vec::new();
+            read_until(&mut third, &mut received, |output| {
+                output == FULL_MESSAGE || last_redraw_width(output) == Some(80)
+            });
+            if received != FULL_MESSAGE {
+                break;
+            }
+            assert!(Instant::now() < deadline, "the first client's place was never freed");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
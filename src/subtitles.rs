@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use ffmpeg_next as ffmpeg;
+use ffmpeg::codec::subtitle::Rect;
+use ffmpeg::media::Type;
+use crate::error::Error;
+use crate::timecode::parse_timestamp;
+
+// Used when a subtitle packet carries neither an end time nor a duration.
+const DEFAULT_CUE_DURATION: f64 = 3.0;
+
+/// Where the active subtitle cue is drawn in the player.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubtitlePosition {
+    /// In a dedicated area between the video and the controls.
+    Below,
+    /// Over the bottom rows of the video frame.
+    Overlay,
+}
+
+impl SubtitlePosition {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "below" => Some(SubtitlePosition::Below),
+            "overlay" => Some(SubtitlePosition::Overlay),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SubtitlePosition::Below => "below",
+            SubtitlePosition::Overlay => "overlay",
+        }
+    }
+}
+
+/// One subtitle line (or group of lines) and the time range it is shown for.
+#[derive(Clone, Debug)]
+pub struct Cue {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct SubtitleTrack {
+    /// Short human-readable name shown in the status bar, e.g. "eng" or the
+    /// sidecar file name.
+    pub label: String,
+    cues: Vec<Cue>,
+}
+
+impl SubtitleTrack {
+    pub fn new(label: String, mut cues: Vec<Cue>) -> Self {
+        cues.sort_by(|a, b| a.start.total_cmp(&b.start));
+        SubtitleTrack { label, cues }
+    }
+
+    /// The cue shown at `timestamp`. When cues overlap, the one that started
+    /// last wins.
+    pub fn cue_at(&self, timestamp: f64) -> Option<&Cue> {
+        let started = self.cues.partition_point(|cue| cue.start <= timestamp);
+        self.cues[..started]
+            .iter()
+            .rev()
+            .find(|cue| timestamp < cue.end)
+    }
+}
+
+/// Parses SRT or WebVTT content. Both formats are blocks separated by blank
+/// lines with a `start --> end` timing line, so one parser handles both;
+/// VTT headers, notes and styles have no timing line and are skipped.
+pub fn parse_cues(content: &str) -> Vec<Cue> {
+    let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+
+    content
+        .split("\n\n")
+        .filter_map(|block| {
+            let lines: Vec<&str> = block.lines().collect();
+            let timing = lines.iter().position(|line| line.contains("-->"))?;
+
+            let mut times = lines[timing].split("-->");
+            let start = parse_timestamp(times.next()?.trim())?;
+            // VTT allows cue settings such as `align:start` after the end time.
+            let end = parse_timestamp(times.next()?.split_whitespace().next()?)?;
+
+            let text = lines[timing + 1..]
+                .iter()
+                .map(|line| clean_text(line))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            if text.trim().is_empty() {
+                return None;
+            }
+            Some(Cue { start, end, text })
+        })
+        .collect()
+}
+
+/// Removes markup (`<i>`, `<c.yellow>`, `{\an8}`) and decodes the few HTML
+/// entities WebVTT uses.
+fn clean_text(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut depth: Option<char> = None;
+
+    for c in line.chars() {
+        match (depth, c) {
+            (None, '<') => depth = Some('>'),
+            (None, '{') => depth = Some('}'),
+            (Some(close), c) if c == close => depth = None,
+            (Some(_), _) => {},
+            (None, c) => out.push(c),
+        }
+    }
+
+    out.replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+}
+
+/// Extracts the dialogue text from an ASS event line as produced by FFmpeg's
+/// text subtitle decoders.
+fn ass_to_text(event: &str) -> String {
+    // Events are `ReadOrder,Layer,Style,Name,MarginL,MarginR,MarginV,Effect,Text`,
+    // or `Dialogue: Layer,Start,End,Style,...` from older FFmpeg versions.
+    let fields = if event.starts_with("Dialogue:") { 10 } else { 9 };
+    let text = event.splitn(fields, ',').nth(fields - 1).unwrap_or(event);
+
+    clean_text(&text.replace("\\N", "\n").replace("\\n", "\n").replace("\\h", " "))
+}
+
+pub fn load_sidecar<P: AsRef<Path>>(path: P) -> Result<SubtitleTrack, Error> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|e| Error::file(path, e))?;
+    let cues = parse_cues(&String::from_utf8_lossy(&bytes));
+
+    let label = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("external"));
+
+    Ok(SubtitleTrack::new(label, cues))
+}
+
+/// Every subtitle track for a video: `extra_file` first if given, then
+/// `.srt`/`.vtt` files next to the video, then text tracks embedded in the
+/// container. Only a broken `extra_file` is an error; the others are
+/// reported and skipped.
+pub fn load_tracks<P: AsRef<Path>>(video_path: P, extra_file: Option<&Path>) -> Result<Vec<SubtitleTrack>, Error> {
+    let video_path = video_path.as_ref();
+    let mut tracks = Vec::new();
+
+    if let Some(path) = extra_file {
+        tracks.push(load_sidecar(path)?);
+    }
+
+    for path in find_sidecars(video_path) {
+        if extra_file == Some(path.as_path()) {
+            continue;
+        }
+        match load_sidecar(&path) {
+            Ok(track) => tracks.push(track),
+            Err(e) => eprintln!("Warning: Failed to load {}: {}", path.display(), e),
+        }
+    }
+
+    match load_embedded(video_path) {
+        Ok(embedded) => tracks.extend(embedded),
+        Err(e) => eprintln!("Warning: Failed to read embedded subtitles: {}", e),
+    }
+
+    Ok(tracks)
+}
+
+/// Index of the first track whose label mentions `language`, which matches
+/// both embedded tracks ("jpn (Signs)") and sidecar files named like
+/// `movie.jpn.srt`.
+pub fn find_language(tracks: &[SubtitleTrack], language: &str) -> Option<usize> {
+    tracks.iter().position(|track| {
+        track.label
+            .split([' ', '.', '(', ')'])
+            .any(|word| word.eq_ignore_ascii_case(language))
+    })
+}
+
+/// `.srt` and `.vtt` files next to the video whose names start with the
+/// video's file stem, e.g. `movie.srt` or `movie.en.vtt` for `movie.mp4`.
+pub fn find_sidecars<P: AsRef<Path>>(video_path: P) -> Vec<PathBuf> {
+    let video_path = video_path.as_ref();
+    let stem = match video_path.file_stem() {
+        Some(stem) => stem.to_string_lossy().into_owned(),
+        None => return Vec::new(),
+    };
+    let dir = match video_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let mut sidecars: Vec<PathBuf> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let is_subtitle = path.extension()
+                    .map(|ext| ext.eq_ignore_ascii_case("srt") || ext.eq_ignore_ascii_case("vtt"))
+                    .unwrap_or(false);
+                let matches_video = path.file_name()
+                    .map(|name| name.to_string_lossy().starts_with(&stem))
+                    .unwrap_or(false);
+                is_subtitle && matches_video
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    sidecars.sort();
+    sidecars
+}
+
+/// Decodes every text subtitle stream in the container. Bitmap subtitles
+/// (DVD/PGS) can't be shown as text and are skipped.
+pub fn load_embedded<P: AsRef<Path>>(video_path: P) -> Result<Vec<SubtitleTrack>, Error> {
+    let video_path = video_path.as_ref();
+    ffmpeg::init().map_err(|e| Error::decode("Failed to initialize FFmpeg", video_path, e))?;
+
+    let mut input = ffmpeg::format::input(&video_path)
+        .map_err(|e| Error::decode("Failed to open video", video_path, e))?;
+
+    let mut decoders = HashMap::new();
+    let mut tracks: Vec<(String, Vec<Cue>)> = Vec::new();
+
+    for stream in input.streams() {
+        let parameters = stream.parameters();
+        if parameters.medium() != Type::Subtitle {
+            continue;
+        }
+
+        let decoder = match ffmpeg::codec::context::Context::from_parameters(parameters)
+            .and_then(|context| context.decoder().subtitle())
+        {
+            Ok(decoder) => decoder,
+            Err(_) => continue,
+        };
+
+        let metadata = stream.metadata();
+        let label = match (metadata.get("language"), metadata.get("title")) {
+            (Some(language), Some(title)) => format!("{} ({})", language, title),
+            (Some(language), None) => language.to_string(),
+            (None, Some(title)) => title.to_string(),
+            (None, None) => format!("track {}", tracks.len() + 1),
+        };
+
+        decoders.insert(stream.index(), (decoder, f64::from(stream.time_base()), tracks.len()));
+        tracks.push((label, Vec::new()));
+    }
+
+    if decoders.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Only subtitle packets are wanted, so the demuxer is told to discard
+    // every other stream and skips their data instead of returning it.
+    let skipped: Vec<usize> = input.streams()
+        .map(|stream| stream.index())
+        .filter(|index| !decoders.contains_key(index))
+        .collect();
+    for index in skipped {
+        if let Some(mut stream) = input.stream_mut(index) {
+            // ffmpeg-next has no setter for a stream's discard level; this
+            // writes a plain field of a stream `input` owns.
+            unsafe { (*stream.as_mut_ptr()).discard = ffmpeg::ffi::AVDiscard::AVDISCARD_ALL };
+        }
+    }
+
+    for (stream, packet) in input.packets() {
+        let (decoder, time_base, track) = match decoders.get_mut(&stream.index()) {
+            Some(entry) => entry,
+            None => continue,
+        };
+
+        let mut subtitle = ffmpeg::Subtitle::new();
+        if !matches!(decoder.decode(&packet, &mut subtitle), Ok(true)) {
+            continue;
+        }
+
+        // Decoded subtitles carry their pts in microseconds, with display
+        // times in milliseconds relative to it.
+        let base = match subtitle.pts() {
+            Some(pts) => pts as f64 / 1_000_000.0,
+            None => packet.pts().unwrap_or(0) as f64 * *time_base,
+        };
+        let start = base + subtitle.start() as f64 / 1000.0;
+        let end = if subtitle.end() > subtitle.start() && subtitle.end() != u32::MAX {
+            base + subtitle.end() as f64 / 1000.0
+        } else if packet.duration() > 0 {
+            start + packet.duration() as f64 * *time_base
+        } else {
+            start + DEFAULT_CUE_DURATION
+        };
+
+        let text = subtitle
+            .rects()
+            .filter_map(|rect| match rect {
+                Rect::Text(text) => Some(clean_text(text.get())),
+                Rect::Ass(ass) => Some(ass_to_text(ass.get())),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if !text.trim().is_empty() {
+            tracks[*track].1.push(Cue { start, end, text });
+        }
+    }
+
+    Ok(tracks
+        .into_iter()
+        .filter(|(_, cues)| !cues.is_empty())
+        .map(|(label, cues)| SubtitleTrack::new(label, cues))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(start: f64, end: f64, text: &str) -> Cue {
+        Cue { start, end, text: text.to_string() }
+    }
+
+    #[test]
+    fn parses_srt() {
+        let srt = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\nHello\r\n\r\n\
+                   2\r\n00:00:03,000 --> 00:00:04,000\r\n<i>Two</i>\r\nlines\r\n";
+        let cues = parse_cues(srt);
+
+        assert_eq!(cues.len(), 2);
+        assert_eq!((cues[0].start, cues[0].end, cues[0].text.as_str()), (1.0, 2.5, "Hello"));
+        assert_eq!((cues[1].start, cues[1].end, cues[1].text.as_str()), (3.0, 4.0, "Two\nlines"));
+    }
+
+    #[test]
+    fn parses_vtt_and_skips_headers() {
+        let vtt = "WEBVTT\n\nNOTE a comment\n\nSTYLE\n::cue { color: yellow }\n\n\
+                   intro\n00:01.000 --> 00:02.000 align:start line:0\n<c.yellow>Tom &amp; Jerry</c>\n";
+        let cues = parse_cues(vtt);
+
+        assert_eq!(cues.len(), 1);
+        assert_eq!((cues[0].start, cues[0].end), (1.0, 2.0));
+        assert_eq!(cues[0].text, "Tom & Jerry");
+    }
+
+    #[test]
+    fn skips_empty_and_broken_cues() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\n{\\an8}\n\n\
+                   2\n00:00:xx,000 --> 00:00:03,000\nBroken\n\n\
+                   3\n00:00:04,000 --> 00:00:05,000\nKept\n";
+        let cues = parse_cues(srt);
+
+        assert_eq!(cues.len(), 1);
+        assert_eq!(cues[0].text, "Kept");
+    }
+
+    #[test]
+    fn cue_at_prefers_latest_overlapping_cue() {
+        let track = SubtitleTrack::new(String::from("eng"), vec![
+            cue(5.0, 6.0, "late"),
+            cue(1.0, 4.0, "first"),
+            cue(2.0, 3.0, "second"),
+        ]);
+
+        assert!(track.cue_at(0.5).is_none());
+        assert_eq!(track.cue_at(1.5).unwrap().text, "first");
+        assert_eq!(track.cue_at(2.5).unwrap().text, "second");
+        assert_eq!(track.cue_at(3.0).unwrap().text, "first");
+        assert!(track.cue_at(4.5).is_none());
+        assert_eq!(track.cue_at(5.0).unwrap().text, "late");
+        assert!(track.cue_at(6.0).is_none());
+    }
+
+    #[test]
+    fn extracts_ass_dialogue() {
+        assert_eq!(ass_to_text("0,0,Default,,0,0,0,,Hello\\Nworld, again"), "Hello\nworld, again");
+        assert_eq!(
+            ass_to_text("Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{\\i1}Hi{\\i0}\\hthere"),
+            "Hi there"
+        );
+    }
+
+    #[test]
+    fn finds_language_in_labels() {
+        let tracks = vec![
+            SubtitleTrack::new(String::from("movie.en.srt"), Vec::new()),
+            SubtitleTrack::new(String::from("jpn (Signs)"), Vec::new()),
+        ];
+
+        assert_eq!(find_language(&tracks, "EN"), Some(0));
+        assert_eq!(find_language(&tracks, "jpn"), Some(1));
+        assert_eq!(find_language(&tracks, "signs"), Some(1));
+        assert_eq!(find_language(&tracks, "movie.en"), None);
+        assert_eq!(find_language(&tracks, "fre"), None);
+    }
+
+    #[test]
+    fn parses_positions() {
+        assert_eq!(SubtitlePosition::parse("Overlay"), Some(SubtitlePosition::Overlay));
+        assert_eq!(SubtitlePosition::parse(SubtitlePosition::Below.name()), Some(SubtitlePosition::Below));
+        assert_eq!(SubtitlePosition::parse("above"), None);
+    }
+}
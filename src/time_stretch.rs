@@ -0,0 +1,401 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use rodio::source::SeekError;
+use rodio::Source;
+
+pub const MIN_RATE: f64 = 0.25;
+pub const MAX_RATE: f64 = 4.0;
+
+/// Rates the speed keys step through.
+const RATE_STEPS: [f64; 10] = [0.25, 0.5, 0.75, 1.0, 1.25, 1.5, 1.75, 2.0, 3.0, 4.0];
+
+// WSOLA works on overlapping 30ms windows and may shift each one by up to
+// 10ms to line its waveform up with the previous one.
+const WINDOW_MS: u32 = 30;
+const TOLERANCE_MS: u32 = 10;
+// Only every few frames of the overlap are compared when searching for the
+// best shift; the match is just as good and the search is much cheaper.
+const SEARCH_STRIDE: usize = 4;
+
+/// Playback rate shared between the player and the audio thread, so the
+/// audio follows rate changes without being rebuilt.
+#[derive(Clone, Debug)]
+pub struct PlaybackRate(Arc<AtomicU64>);
+
+impl PlaybackRate {
+    pub fn new(rate: f64) -> Self {
+        PlaybackRate(Arc::new(AtomicU64::new(rate.clamp(MIN_RATE, MAX_RATE).to_bits())))
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, rate: f64) {
+        self.0.store(rate.clamp(MIN_RATE, MAX_RATE).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Moves to the next faster step, e.g. 1.1x to 1.25x.
+    pub fn faster(&self) {
+        let rate = self.get();
+        let next = RATE_STEPS.iter().copied().find(|&step| step > rate + 1e-9).unwrap_or(MAX_RATE);
+        self.set(next);
+    }
+
+    /// Moves to the next slower step, e.g. 1.1x to 1x.
+    pub fn slower(&self) {
+        let rate = self.get();
+        let next = RATE_STEPS.iter().rev().copied().find(|&step| step < rate - 1e-9).unwrap_or(MIN_RATE);
+        self.set(next);
+    }
+}
+
+/// Formats a rate for the status bar, e.g. "1.25x" or "2x".
+pub fn format_rate(rate: f64) -> String {
+    format!("{}x", (rate * 100.0).round() / 100.0)
+}
+
+/// Plays a source at the shared `PlaybackRate`. With pitch preservation the
+/// audio is time-stretched with WSOLA (waveform-similarity overlap-add), so
+/// speech stays at its normal pitch; without it the samples are simply
+/// resampled, like a tape running faster or slower.
+pub struct TimeStretch<S> {
+    source: S,
+    rate: PlaybackRate,
+    preserve_pitch: bool,
+    channels: usize,
+    window: Vec<f32>,
+    hop: usize,
+    tolerance: usize,
+    // Interleaved input not yet consumed, and the (fractional) frame in it
+    // where the next window nominally starts.
+    input: Vec<f32>,
+    position: f64,
+    // Start of the input that would naturally follow the last window; the
+    // next window is aligned to it.
+    continuation: Option<usize>,
+    // Second half of the last window, waiting to be overlapped with the next.
+    tail: Vec<f32>,
+    output: VecDeque<f32>,
+    source_done: bool,
+}
+
+impl<S: Source<Item = f32>> TimeStretch<S> {
+    pub fn new(source: S, rate: PlaybackRate, preserve_pitch: bool) -> Self {
+        let channels = source.channels().max(1) as usize;
+        let sample_rate = source.sample_rate();
+        let window_len = ((sample_rate * WINDOW_MS / 1000) as usize).max(4) & !1;
+        let hop = window_len / 2;
+
+        // A periodic Hann window sums to exactly 1 at 50% overlap, so at 1x
+        // the input comes out unchanged.
+        let window = (0..window_len)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / window_len as f32).cos())
+            .collect();
+
+        TimeStretch {
+            source,
+            rate,
+            preserve_pitch,
+            channels,
+            window,
+            hop,
+            tolerance: (sample_rate * TOLERANCE_MS / 1000) as usize,
+            input: Vec::new(),
+            position: 0.0,
+            continuation: None,
+            tail: vec![0.0; hop * channels],
+            output: VecDeque::new(),
+            source_done: false,
+        }
+    }
+
+    /// Pulls from the source until `frames` frames of input are buffered or
+    /// it runs out.
+    fn fill(&mut self, frames: usize) {
+        while self.input.len() < frames * self.channels && !self.source_done {
+            match self.source.next() {
+                Some(sample) => self.input.push(sample),
+                None => self.source_done = true,
+            }
+        }
+    }
+
+    /// Drops input before frame `frames`, which is no longer needed.
+    fn discard(&mut self, frames: usize) {
+        let frames = frames.min(self.input.len() / self.channels);
+        self.input.drain(..frames * self.channels);
+        self.position -= frames as f64;
+        self.continuation = self.continuation.map(|frame| frame.saturating_sub(frames));
+    }
+
+    /// Produces the next block of output. Returns false once the source is
+    /// exhausted and everything has been played.
+    fn process(&mut self) -> bool {
+        let rate = self.rate.get();
+        if self.preserve_pitch {
+            self.stretch(rate)
+        } else {
+            self.resample(rate)
+        }
+    }
+
+    fn stretch(&mut self, rate: f64) -> bool {
+        let window_len = self.window.len();
+        let channels = self.channels;
+        let hop = self.hop;
+
+        // At 1x each window continues exactly where the last one left off,
+        // which reproduces the input sample for sample.
+        let nominal = match self.continuation {
+            Some(continuation) if rate == 1.0 => continuation,
+            _ => self.position.round() as usize,
+        };
+        let search = if rate == 1.0 { 0 } else { self.tolerance };
+
+        self.fill(nominal + search + window_len);
+        let available = self.input.len() / channels;
+        if available < nominal + window_len {
+            // Not enough input left for a full window: let the last one
+            // fade out and stop.
+            self.output.extend(self.tail.drain(..));
+            return !self.output.is_empty();
+        }
+
+        let start = match self.continuation {
+            Some(continuation) if search > 0 => {
+                let lowest = nominal.saturating_sub(search);
+                let highest = (nominal + search).min(available - window_len);
+                (lowest..=highest)
+                    .map(|candidate| (candidate, self.similarity(continuation, candidate)))
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(candidate, _)| candidate)
+                    .unwrap_or(nominal)
+            },
+            _ => nominal,
+        };
+
+        for i in 0..hop {
+            for c in 0..channels {
+                let sample = self.input[(start + i) * channels + c] * self.window[i];
+                self.output.push_back(sample + self.tail[i * channels + c]);
+            }
+        }
+        for i in 0..hop {
+            for c in 0..channels {
+                self.tail[i * channels + c] = self.input[(start + hop + i) * channels + c] * self.window[hop + i];
+            }
+        }
+
+        self.continuation = Some(start + hop);
+        self.position = if rate == 1.0 {
+            (start + hop) as f64
+        } else {
+            self.position + hop as f64 * rate
+        };
+
+        let needed_from = (self.position as usize).saturating_sub(self.tolerance).min(start + hop);
+        self.discard(needed_from);
+        true
+    }
+
+    /// Cross-correlation of the `hop` frames starting at `a` and `b`, on the
+    /// channel sum.
+    fn similarity(&self, a: usize, b: usize) -> f32 {
+        let channels = self.channels;
+        (0..self.hop)
+            .step_by(SEARCH_STRIDE)
+            .map(|i| {
+                let x: f32 = self.input[(a + i) * channels..(a + i + 1) * channels].iter().sum();
+                let y: f32 = self.input[(b + i) * channels..(b + i + 1) * channels].iter().sum();
+                x * y
+            })
+            .sum()
+    }
+
+    fn resample(&mut self, rate: f64) -> bool {
+        let channels = self.channels;
+        self.fill((self.position + self.hop as f64 * rate) as usize + 2);
+        let available = self.input.len() / channels;
+
+        for _ in 0..self.hop {
+            let index = self.position as usize;
+            if index + 1 >= available {
+                break;
+            }
+            let fraction = (self.position - index as f64) as f32;
+            for c in 0..channels {
+                let a = self.input[index * channels + c];
+                let b = self.input[(index + 1) * channels + c];
+                self.output.push_back(a + (b - a) * fraction);
+            }
+            self.position += rate;
+        }
+
+        self.discard(self.position as usize);
+        !self.output.is_empty()
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for TimeStretch<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        loop {
+            if let Some(sample) = self.output.pop_front() {
+                return Some(sample);
+            }
+            if !self.process() {
+                return None;
+            }
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Source for TimeStretch<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        // Depends on the rate, which may change at any time.
+        None
+    }
+
+    /// Seeks the underlying source; `pos` is a position in the source, not
+    /// in the stretched output.
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(pos)?;
+        self.input.clear();
+        self.output.clear();
+        self.position = 0.0;
+        self.continuation = None;
+        self.tail.iter_mut().for_each(|sample| *sample = 0.0);
+        self.source_done = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::buffer::SamplesBuffer;
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    /// One second of a 440 Hz tone, with the channels slightly different so
+    /// a mix-up would show.
+    fn tone(channels: u16) -> SamplesBuffer<f32> {
+        let samples: Vec<f32> = (0..SAMPLE_RATE as usize)
+            .flat_map(|n| {
+                let t = n as f32 / SAMPLE_RATE as f32;
+                (0..channels).map(move |c| (2.0 * PI * 440.0 * t).sin() * (1.0 - 0.25 * c as f32))
+            })
+            .collect();
+        SamplesBuffer::new(channels, SAMPLE_RATE, samples)
+    }
+
+    fn stretched(rate: f64, preserve_pitch: bool, channels: u16) -> Vec<f32> {
+        TimeStretch::new(tone(channels), PlaybackRate::new(rate), preserve_pitch).collect()
+    }
+
+    /// Output frames at `rate` should be the input length divided by the
+    /// rate, give or take the input left over at the end, which doesn't
+    /// fill a whole window, and the faded-out tail of the last window.
+    fn assert_length(output: &[f32], rate: f64, channels: u16) {
+        assert_eq!(output.len() % channels as usize, 0);
+        let frames = output.len() / channels as usize;
+        let expected = SAMPLE_RATE as f64 / rate;
+        let leftover = (SAMPLE_RATE * (WINDOW_MS + TOLERANCE_MS) / 1000) as f64 / rate;
+        let tail = (SAMPLE_RATE * WINDOW_MS / 1000) as f64;
+        assert!(
+            (frames as f64 - expected).abs() <= leftover + tail,
+            "{} frames at {}x, expected about {}", frames, rate, expected
+        );
+    }
+
+    #[test]
+    fn stretch_keeps_length_proportional_to_rate() {
+        for rate in [0.5, 0.75, 1.0, 1.5, 2.0, 4.0] {
+            assert_length(&stretched(rate, true, 2), rate, 2);
+        }
+    }
+
+    #[test]
+    fn resample_keeps_length_proportional_to_rate() {
+        for rate in [0.25, 0.5, 1.0, 1.25, 3.0] {
+            assert_length(&stretched(rate, false, 1), rate, 1);
+        }
+    }
+
+    #[test]
+    fn stretch_at_normal_speed_reproduces_input() {
+        let input: Vec<f32> = tone(2).collect();
+        let output = stretched(1.0, true, 2);
+        let stretch = TimeStretch::new(tone(2), PlaybackRate::new(1.0), true);
+        // The first half window fades in from silence and the last one
+        // fades out.
+        let fade = stretch.hop * 2;
+
+        assert!(output.len() <= input.len());
+        let middle = input.iter().zip(&output).enumerate().take(output.len() - fade).skip(fade);
+        for (i, (a, b)) in middle {
+            assert!((a - b).abs() < 1e-4, "sample {} differs: {} vs {}", i, a, b);
+        }
+    }
+
+    #[test]
+    fn stretch_keeps_channels_apart() {
+        let output = stretched(1.5, true, 2);
+        let peak = |channel: usize| output.iter().skip(channel).step_by(2).fold(0.0f32, |max, s| max.max(s.abs()));
+        let (left, right) = (peak(0), peak(1));
+        assert!(left > 0.9, "left peak {}", left);
+        assert!((right / left - 0.75).abs() < 0.05, "right peak {} vs left {}", right, left);
+    }
+
+    #[test]
+    fn seeking_restarts_cleanly() {
+        let mut stretch = TimeStretch::new(tone(1), PlaybackRate::new(2.0), true);
+        stretch.by_ref().take(1000).for_each(drop);
+        stretch.try_seek(Duration::from_millis(500)).unwrap();
+        let rest: Vec<f32> = stretch.collect();
+        assert_length(&rest, 2.0 * 2.0, 1);
+    }
+
+    #[test]
+    fn rate_steps_and_clamps() {
+        let rate = PlaybackRate::new(1.1);
+        rate.faster();
+        assert_eq!(rate.get(), 1.25);
+        rate.slower();
+        rate.slower();
+        assert_eq!(rate.get(), 0.75);
+
+        rate.set(10.0);
+        assert_eq!(rate.get(), MAX_RATE);
+        rate.faster();
+        assert_eq!(rate.get(), MAX_RATE);
+        rate.set(0.0);
+        assert_eq!(rate.get(), MIN_RATE);
+        rate.slower();
+        assert_eq!(rate.get(), MIN_RATE);
+    }
+
+    #[test]
+    fn formats_rates() {
+        assert_eq!(format_rate(1.0), "1x");
+        assert_eq!(format_rate(1.25), "1.25x");
+        assert_eq!(format_rate(0.333), "0.33x");
+    }
+}
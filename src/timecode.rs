@@ -0,0 +1,77 @@
+/// Formats seconds as `M:SS.ss`, or `H:MM:SS.ss` for an hour or more.
+pub fn format_timestamp(seconds: f64) -> String {
+    // Rounded to the hundredths shown before splitting, so 59.996 carries
+    // over to 1:00.00 instead of showing 0:60.00.
+    let centis = (seconds.max(0.0) * 100.0).round() as u64;
+    let hours = centis / 360_000;
+    let minutes = centis / 6_000 % 60;
+    let secs = centis / 100 % 60;
+    let fraction = centis % 100;
+
+    if hours > 0 {
+        format!("{}:{:02}:{:02}.{:02}", hours, minutes, secs, fraction)
+    } else {
+        format!("{}:{:02}.{:02}", minutes, secs, fraction)
+    }
+}
+
+/// Parses `[[H:]M:]S[.sss]` into seconds, e.g. `1:23:45`, `83.5` or SRT's
+/// `00:01:23,500`.
+pub fn parse_timestamp(value: &str) -> Option<f64> {
+    let value = value.replace(',', ".");
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        seconds = seconds * 60.0 + part.trim().parse::<f64>().ok()?;
+    }
+    Some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_minutes_and_hours() {
+        assert_eq!(format_timestamp(0.0), "0:00.00");
+        assert_eq!(format_timestamp(83.5), "1:23.50");
+        assert_eq!(format_timestamp(5025.25), "1:23:45.25");
+    }
+
+    #[test]
+    fn rounds_before_carrying() {
+        assert_eq!(format_timestamp(59.996), "1:00.00");
+        assert_eq!(format_timestamp(3599.999), "1:00:00.00");
+        assert_eq!(format_timestamp(12.344), "0:12.34");
+    }
+
+    #[test]
+    fn clamps_negative_times() {
+        assert_eq!(format_timestamp(-3.0), "0:00.00");
+    }
+
+    #[test]
+    fn parses_every_form() {
+        assert_eq!(parse_timestamp("83.5"), Some(83.5));
+        assert_eq!(parse_timestamp("1:23.5"), Some(83.5));
+        assert_eq!(parse_timestamp("1:23:45"), Some(5025.0));
+        assert_eq!(parse_timestamp("00:01:23,500"), Some(83.5));
+        assert_eq!(parse_timestamp(" 2 : 03 "), Some(123.0));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert_eq!(parse_timestamp(""), None);
+        assert_eq!(parse_timestamp("1:xx"), None);
+        assert_eq!(parse_timestamp("1::2"), None);
+    }
+
+    #[test]
+    fn round_trips_through_parse() {
+        for seconds in [0.0, 0.01, 9.99, 59.994, 59.996, 61.5, 599.995, 3599.99, 3600.0, 45296.78] {
+            let formatted = format_timestamp(seconds);
+            let parsed = parse_timestamp(&formatted).expect("formatted timestamps parse");
+            assert!((parsed - seconds).abs() <= 0.005 + 1e-9, "{} -> {} -> {}", seconds, formatted, parsed);
+            assert_eq!(format_timestamp(parsed), formatted);
+        }
+    }
+}
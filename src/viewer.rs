@@ -0,0 +1,366 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use crossterm::{
+    cursor::{Hide, Show},
+    event::{self, Event, KeyEventKind},
+    execute,
+    terminal::{self, disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use image::imageops;
+use image::{DynamicImage, RgbaImage};
+use crate::background::Background;
+use crate::cells::{Cell, CellGrid, ColorMode};
+use crate::error::Error;
+use crate::image_source::{image_error, to_frame};
+use crate::keymap::{Action, KeyPress, KeySequence, Keymap};
+use crate::player::PlaybackEnd;
+use crate::playlist::Playlist;
+use crate::raw_output::{write_lines, RawFrameWriter};
+use crate::renderer::Renderer;
+
+/// Terminal cells are about twice as tall as they are wide. Grids are sized
+/// with this ratio so images keep their proportions.
+pub const CELL_ASPECT: f64 = 2.0;
+
+const ZOOM_STEP: f64 = 1.25;
+const MAX_ZOOM: f64 = 32.0;
+// Each pan key moves the view by this fraction of what is visible.
+const PAN_STEP: f64 = 0.2;
+
+/// The largest grid of at most `max_width` by `max_height` cells that shows
+/// a `width` by `height` pixel image without distorting it.
+pub fn fit_grid(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    let aspect = width.max(1) as f64 / height.max(1) as f64 * CELL_ASPECT;
+    let max_width = max_width.max(1);
+    let max_height = max_height.max(1);
+
+    let grid_height = (max_width as f64 / aspect).round() as u32;
+    if grid_height <= max_height {
+        (max_width, grid_height.max(1))
+    } else {
+        (((max_height as f64 * aspect).round() as u32).clamp(1, max_width), max_height)
+    }
+}
+
+/// The part of an image on screen: a zoom factor, 1 showing all of it, and
+/// the point at the center of the view as a fraction of the image size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    zoom: f64,
+    center_x: f64,
+    center_y: f64,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport { zoom: 1.0, center_x: 0.5, center_y: 0.5 }
+    }
+}
+
+impl Viewport {
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    pub fn zoom_in(&mut self) {
+        self.zoom = (self.zoom * ZOOM_STEP).min(MAX_ZOOM);
+        self.clamp();
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.zoom = (self.zoom / ZOOM_STEP).max(1.0);
+        self.clamp();
+    }
+
+    /// Moves the view by `dx` and `dy` steps; positive is right and down.
+    pub fn pan(&mut self, dx: f64, dy: f64) {
+        self.center_x += dx * PAN_STEP / self.zoom;
+        self.center_y += dy * PAN_STEP / self.zoom;
+        self.clamp();
+    }
+
+    /// Keeps the view inside the image.
+    fn clamp(&mut self) {
+        let half = 0.5 / self.zoom;
+        self.center_x = self.center_x.clamp(half, 1.0 - half);
+        self.center_y = self.center_y.clamp(half, 1.0 - half);
+    }
+
+    /// The visible `(x, y, width, height)` in pixels of a `width` by
+    /// `height` image.
+    pub fn crop(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let crop_width = ((width as f64 / self.zoom).round() as u32).clamp(1, width.max(1));
+        let crop_height = ((height as f64 / self.zoom).round() as u32).clamp(1, height.max(1));
+        let x = (self.center_x * width as f64 - crop_width as f64 / 2.0).round().max(0.0) as u32;
+        let y = (self.center_y * height as f64 - crop_height as f64 / 2.0).round().max(0.0) as u32;
+        (
+            x.min(width.saturating_sub(crop_width)),
+            y.min(height.saturating_sub(crop_height)),
+            crop_width,
+            crop_height,
+        )
+    }
+}
+
+/// Shows a still image at full resolution: printed once at a size that fits
+/// the terminal, or in an interactive viewer with zoom and pan. Needs
+/// neither FFmpeg nor ffprobe.
+pub struct ImageViewer {
+    image: RgbaImage,
+    name: String,
+    renderer: Arc<dyn Renderer>,
+    color_mode: ColorMode,
+    background: Background,
+}
+
+impl ImageViewer {
+    /// Loads the image at `path`; of an animation, the first frame.
+    pub fn open<P: AsRef<Path>>(path: P, renderer: Arc<dyn Renderer>, color_mode: ColorMode) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let image = image::open(path).map_err(|e| image_error(path, e))?;
+        let name = path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        Ok(ImageViewer::new(image, &name, renderer, color_mode))
+    }
+
+    pub fn new<I>(image: I, name: &str, renderer: Arc<dyn Renderer>, color_mode: ColorMode) -> Self
+    where
+        I: Into<DynamicImage>,
+    {
+        ImageViewer {
+            image: image.into().into_rgba8(),
+            name: name.to_string(),
+            renderer,
+            color_mode,
+            background: Background::default(),
+        }
+    }
+
+    /// What transparent parts of the image are drawn over.
+    pub fn background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+
+    /// The grid size that fits the whole image into `max_width` by
+    /// `max_height` cells.
+    pub fn fit(&self, max_width: u32, max_height: u32) -> (u32, u32) {
+        fit_grid(self.image.width(), self.image.height(), max_width, max_height)
+    }
+
+    /// Renders the part of the image `viewport` shows into `width` by
+    /// `height` cells.
+    pub fn render(&self, viewport: &Viewport, width: u32, height: u32) -> CellGrid {
+        let (x, y, crop_width, crop_height) = viewport.crop(self.image.width(), self.image.height());
+        let crop = imageops::crop_imm(&self.image, x, y, crop_width, crop_height);
+
+        // Large photos are shrunk with a cheap filter first, to a size that
+        // still leaves every renderer several pixels per cell.
+        let (max_width, max_height) = (width * 8, height * 16);
+        let image = if crop_width > max_width || crop_height > max_height {
+            let scale = (max_width as f64 / crop_width as f64).min(max_height as f64 / crop_height as f64);
+            let width = ((crop_width as f64 * scale).round() as u32).max(1);
+            let height = ((crop_height as f64 * scale).round() as u32).max(1);
+            imageops::thumbnail(&*crop, width, height)
+        } else {
+            crop.to_image()
+        };
+
+        let frame = to_frame(DynamicImage::ImageRgba8(image), u32::MAX, self.background, 0.0);
+        self.renderer.render_frame(&frame, width, height)
+    }
+
+    /// Writes the whole image as `width` by `height` cells of text to `out`,
+    /// for scripts and pipes.
+    pub fn print<W: Write>(&self, out: &mut W, width: u32, height: u32) -> io::Result<()> {
+        let grid = self.render(&Viewport::default(), width, height);
+        write_lines(out, &grid, self.color_mode)
+    }
+
+    /// Shows the image full screen until the user quits or moves to another
+    /// playlist item. The image is fitted to the terminal, and refitted when
+    /// the terminal is resized. `keymap` maps keys to actions; those the
+    /// viewer doesn't respond to are left out.
+    pub fn view(&self, playlist: &Playlist, keymap: &Keymap) -> Result<PlaybackEnd, Error> {
+        enable_raw_mode().map_err(Error::Terminal)?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen, Hide).map_err(Error::Terminal)?;
+
+        let writer = RawFrameWriter::new(stdout.lock(), self.color_mode);
+        let result = self.run(playlist, &keymap.viewer(), writer);
+
+        disable_raw_mode().map_err(Error::Terminal)?;
+        execute!(stdout, Show, LeaveAlternateScreen).map_err(Error::Terminal)?;
+        result
+    }
+
+    fn run<W: Write>(&self, playlist: &Playlist, keymap: &Keymap, mut writer: RawFrameWriter<W>) -> Result<PlaybackEnd, Error> {
+        let mut viewport = Viewport::default();
+        let mut key_sequence = KeySequence::new();
+        let controls = keymap.footer();
+
+        loop {
+            let (columns, rows) = terminal::size().map_err(Error::Terminal)?;
+            let screen = self.screen(&viewport, columns as u32, rows as u32, &controls);
+            writer.write_frame(&screen).map_err(Error::Terminal)?;
+
+            let key = match event::read().map_err(Error::Terminal)? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => key,
+                // Anything else, such as a resize, just redraws.
+                _ => continue,
+            };
+            let action = match key_sequence.feed(keymap, KeyPress::from_event(&key)) {
+                Some(action) => action,
+                None => continue,
+            };
+            match action {
+                Action::Quit => break,
+                Action::ZoomIn => viewport.zoom_in(),
+                Action::ZoomOut => viewport.zoom_out(),
+                Action::ResetZoom => viewport = Viewport::default(),
+                Action::PanLeft => viewport.pan(-1.0, 0.0),
+                Action::PanRight => viewport.pan(1.0, 0.0),
+                Action::PanUp => viewport.pan(0.0, -1.0),
+                Action::PanDown => viewport.pan(0.0, 1.0),
+                Action::PrevItem if playlist.len() > 1 => {
+                    writer.finish().map_err(Error::Terminal)?;
+                    return Ok(PlaybackEnd::Previous);
+                },
+                Action::NextItem if playlist.len() > 1 => {
+                    writer.finish().map_err(Error::Terminal)?;
+                    return Ok(PlaybackEnd::Next);
+                },
+                _ => {},
+            }
+        }
+
+        writer.finish().map_err(Error::Terminal)?;
+        Ok(PlaybackEnd::Quit)
+    }
+
+    /// The whole terminal screen: the visible part of the image centered
+    /// above a status line ending in `controls`.
+    fn screen(&self, viewport: &Viewport, columns: u32, rows: u32, controls: &str) -> CellGrid {
+        let mut screen = CellGrid::new(columns.max(1) as usize, rows.max(2) as usize);
+        let image_rows = screen.height() as u32 - 1;
+
+        let (width, height) = self.fit(screen.width() as u32, image_rows);
+        let grid = self.render(viewport, width, height);
+        let left = (screen.width() - grid.width()) / 2;
+        let top = (image_rows as usize - grid.height()) / 2;
+        for y in 0..grid.height() {
+            for (x, cell) in grid.row(y).iter().enumerate() {
+                screen.set(left + x, top + y, *cell);
+            }
+        }
+
+        let (image_width, image_height) = self.dimensions();
+        let status = format!(
+            "{}  {}x{}  {:.0}%  | {}",
+            self.name, image_width, image_height, viewport.zoom() * 100.0, controls
+        );
+        let status_row = screen.height() - 1;
+        for (x, glyph) in status.chars().take(screen.width()).enumerate() {
+            screen.set(x, status_row, Cell { glyph, ..Cell::default() });
+        }
+
+        screen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_wide_and_tall_images() {
+        // 2:1 pixels are 4:1 in cells.
+        assert_eq!(fit_grid(200, 100, 80, 40), (80, 20));
+        // A tall image is limited by the height instead.
+        assert_eq!(fit_grid(100, 400, 80, 40), (20, 40));
+        assert_eq!(fit_grid(1, 1000, 80, 40), (1, 40));
+        assert_eq!(fit_grid(0, 0, 0, 0), (1, 1));
+    }
+
+    #[test]
+    fn default_view_shows_everything() {
+        assert_eq!(Viewport::default().crop(640, 480), (0, 0, 640, 480));
+    }
+
+    #[test]
+    fn zoom_is_bounded() {
+        let mut viewport = Viewport::default();
+        viewport.zoom_out();
+        assert_eq!(viewport.zoom(), 1.0);
+
+        for _ in 0..100 {
+            viewport.zoom_in();
+        }
+        assert_eq!(viewport.zoom(), MAX_ZOOM);
+        let (_, _, width, height) = viewport.crop(640, 480);
+        assert_eq!((width, height), (20, 15));
+    }
+
+    #[test]
+    fn zoom_keeps_the_center() {
+        let mut viewport = Viewport::default();
+        viewport.zoom_in();
+        viewport.zoom_in();
+        let (x, y, width, height) = viewport.crop(1000, 1000);
+        assert_eq!((width, height), (640, 640));
+        assert_eq!((x, y), (180, 180));
+    }
+
+    #[test]
+    fn pan_stops_at_the_edges() {
+        let mut viewport = Viewport::default();
+        viewport.pan(1.0, 0.0);
+        assert_eq!(viewport, Viewport::default());
+
+        viewport.zoom_in();
+        viewport.zoom_in();
+        for _ in 0..50 {
+            viewport.pan(-1.0, 1.0);
+        }
+        let (x, y, width, height) = viewport.crop(1000, 1000);
+        assert_eq!((x, y), (0, 1000 - height));
+        assert_eq!(width, height);
+    }
+
+    #[test]
+    fn zooming_out_pulls_the_view_back_inside() {
+        let mut viewport = Viewport::default();
+        for _ in 0..10 {
+            viewport.zoom_in();
+        }
+        for _ in 0..50 {
+            viewport.pan(1.0, 1.0);
+        }
+        viewport.zoom_out();
+        let (x, y, width, height) = viewport.crop(1000, 500);
+        assert_eq!((x + width, y + height), (1000, 500));
+
+        for _ in 0..10 {
+            viewport.zoom_out();
+        }
+        assert_eq!(viewport, Viewport::default());
+    }
+
+    #[test]
+    fn crop_stays_inside_tiny_images() {
+        let mut viewport = Viewport::default();
+        for _ in 0..20 {
+            viewport.zoom_in();
+        }
+        viewport.pan(5.0, 5.0);
+        let (x, y, width, height) = viewport.crop(3, 2);
+        assert!(width >= 1 && height >= 1);
+        assert!(x + width <= 3 && y + height <= 2);
+    }
+}